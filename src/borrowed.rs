@@ -0,0 +1,109 @@
+use std::borrow::Cow;
+use std::fmt;
+use num::bigint::BigInt;
+use num::rational::BigRational;
+
+use reader::{self, read_unsliced, ParseError, Span};
+use symbol;
+use {Node, SymbolicExpr};
+
+// A SymbolicExpr whose symbols and strings borrow from the source, so only
+// strings containing escapes need their own allocation
+pub enum BorrowedExpr<'src> {
+    Int(i64),
+    BigInt(BigInt),
+    Rational(BigRational),
+    Float(f64),
+    Bool(bool),
+    Str(Cow<'src, str>),
+    Char(char),
+    Symbol(Cow<'src, str>),
+    ListExpr(Vec<BorrowedNode<'src>>)
+}
+
+pub struct BorrowedNode<'src> {
+    pub expr: BorrowedExpr<'src>,
+    pub span: Span,
+}
+
+impl<'src> BorrowedNode<'src> {
+    // Copy into an owned node which can outlive the source
+    pub fn to_node(&self) -> Node {
+        let expr = match self.expr {
+            BorrowedExpr::Int(i) => SymbolicExpr::Int(i),
+            BorrowedExpr::BigInt(ref b) => SymbolicExpr::BigInt(b.clone()),
+            BorrowedExpr::Rational(ref r) => SymbolicExpr::Rational(r.clone()),
+            BorrowedExpr::Float(x) => SymbolicExpr::Float(x),
+            BorrowedExpr::Bool(b) => SymbolicExpr::Bool(b),
+            BorrowedExpr::Str(ref s) => SymbolicExpr::Str(s.to_string()),
+            BorrowedExpr::Char(c) => SymbolicExpr::Char(c),
+            BorrowedExpr::Symbol(ref s) => SymbolicExpr::Symbol(symbol::intern(&**s)),
+            BorrowedExpr::ListExpr(ref items) => {
+                SymbolicExpr::ListExpr(items.iter().map(|item| item.to_node()).collect())
+            }
+        };
+        Node { expr: expr, span: self.span }
+    }
+}
+
+impl<'src> fmt::Display for BorrowedExpr<'src> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            BorrowedExpr::Int(num) => write!(f, "(Int {})", num),
+            BorrowedExpr::BigInt(ref num) => write!(f, "(BigInt {})", num),
+            BorrowedExpr::Rational(ref num) => write!(f, "(Rational {}/{})", num.numer(), num.denom()),
+            BorrowedExpr::Float(num) if num.is_finite() && num.fract() == 0.0 => {
+                write!(f, "(Float {:.1})", num)
+            }
+            BorrowedExpr::Float(num) => write!(f, "(Float {})", num),
+            BorrowedExpr::Bool(b) => write!(f, "(Bool {})", b),
+            BorrowedExpr::Str(ref s) => write!(f, "(String {})", reader::escape_string(&**s)),
+            BorrowedExpr::Char(c) => write!(f, "(Char {})", reader::escape_char(c)),
+            BorrowedExpr::Symbol(ref sym) => write!(f, "(Symbol {})", sym),
+            BorrowedExpr::ListExpr(ref items) => {
+                try!(f.write_str("(List"));
+                for item in items.iter() {
+                    try!(write!(f, " {}", item.expr));
+                }
+                f.write_str(")")
+            }
+        }
+    }
+}
+
+impl<'src> fmt::Display for BorrowedNode<'src> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.expr.fmt(f)
+    }
+}
+
+// Fill in the text the reader left empty from the source.  Symbols which
+// already have text were introduced by the reader, e.g. quote or cons.
+fn borrow<'src>(code: &'src str, node: Node) -> BorrowedNode<'src> {
+    let span = node.span;
+    let source = &code[span.start.offset..span.end.offset];
+    let expr = match node.expr {
+        SymbolicExpr::Int(i) => BorrowedExpr::Int(i),
+        SymbolicExpr::BigInt(b) => BorrowedExpr::BigInt(b),
+        SymbolicExpr::Rational(r) => BorrowedExpr::Rational(r),
+        SymbolicExpr::Float(x) => BorrowedExpr::Float(x),
+        SymbolicExpr::Bool(b) => BorrowedExpr::Bool(b),
+        SymbolicExpr::Char(c) => BorrowedExpr::Char(c),
+        SymbolicExpr::Str(ref s) if s.is_empty() => {
+            BorrowedExpr::Str(Cow::Borrowed(&source[1..source.len() - 1]))
+        }
+        SymbolicExpr::Str(s) => BorrowedExpr::Str(Cow::Owned(s)),
+        SymbolicExpr::Symbol(s) if s == symbol::EMPTY => BorrowedExpr::Symbol(Cow::Borrowed(source)),
+        SymbolicExpr::Symbol(s) => BorrowedExpr::Symbol(Cow::Owned(s.name())),
+        SymbolicExpr::ListExpr(items) => {
+            BorrowedExpr::ListExpr(items.into_iter().map(|item| borrow(code, item)).collect())
+        }
+    };
+    BorrowedNode { expr: expr, span: span }
+}
+
+// Read without copying symbols or strings out of the source
+pub fn read_borrowed<'src>(code: &'src str) -> Result<Vec<BorrowedNode<'src>>, ParseError> {
+    let forms = try!(read_unsliced(code));
+    Ok(forms.into_iter().map(|form| borrow(code, form)).collect())
+}
@@ -0,0 +1,140 @@
+use std::cell::Cell;
+use std::os;
+use libc;
+
+// Colouring of the REPL's output with ANSI escapes.  Results are coloured by
+// syntax, with brackets coloured by how deeply they're nested so that the
+// ends of a long nested result can be matched by eye, and errors and the
+// prompt each have their own colour.  Colour is off unless turned on, which
+// main does when standard output is a terminal, NO_COLOR isn't set and
+// --no-color wasn't given.
+
+const RESET: &'static str = "\x1b[0m";
+const PROMPT: &'static str = "\x1b[1;34m";
+const ERROR: &'static str = "\x1b[1;31m";
+const TYPE: &'static str = "\x1b[2m";
+const STRING: &'static str = "\x1b[32m";
+const NUMBER: &'static str = "\x1b[36m";
+const BOOLEAN: &'static str = "\x1b[33m";
+const OPAQUE: &'static str = "\x1b[35m";
+
+// Colours of brackets, cycled through by depth
+const BRACKETS: [&'static str; 4] = ["\x1b[1;33m", "\x1b[1;35m", "\x1b[1;36m", "\x1b[1;32m"];
+
+thread_local!(static ENABLED: Cell<bool> = Cell::new(false));
+
+pub fn enable(on: bool) {
+    ENABLED.with(|enabled| enabled.set(on));
+}
+
+pub fn enabled() -> bool {
+    ENABLED.with(|enabled| enabled.get())
+}
+
+// Whether colour should be used by default, which is when standard output is
+// a terminal and the NO_COLOR convention isn't asking for no colour
+pub fn wanted() -> bool {
+    let no_color = os::getenv("NO_COLOR").map(|value| !value.is_empty()).unwrap_or(false);
+    !no_color && unsafe { libc::isatty(1) != 0 }
+}
+
+fn paint(colour: &str, text: &str) -> String {
+    if enabled() && !text.is_empty() {
+        format!("{}{}{}", colour, text, RESET)
+    } else {
+        text.to_string()
+    }
+}
+
+pub fn prompt(text: &str) -> String {
+    paint(PROMPT, text)
+}
+
+pub fn error(text: &str) -> String {
+    paint(ERROR, text)
+}
+
+pub fn type_(text: &str) -> String {
+    paint(TYPE, text)
+}
+
+// Colour a printed value: strings, numbers, booleans, things printed in
+// angle brackets such as <closure>, and brackets by depth
+pub fn value(text: &str) -> String {
+    if !enabled() {
+        return text.to_string()
+    }
+    let chars: Vec<char> = text.chars().collect();
+    let mut out = String::new();
+    let mut depth = 0;
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        match c {
+            '(' | '[' | '{' => {
+                out.push_str(&*paint(BRACKETS[depth % BRACKETS.len()], &*c.to_string()));
+                depth += 1;
+                i += 1;
+            }
+            ')' | ']' | '}' => {
+                depth = if depth > 0 { depth - 1 } else { 0 };
+                out.push_str(&*paint(BRACKETS[depth % BRACKETS.len()], &*c.to_string()));
+                i += 1;
+            }
+            '"' => {
+                let end = string_end(&*chars, i);
+                out.push_str(&*paint(STRING, &*collect(&chars[i..end])));
+                i = end;
+            }
+            '<' if i + 1 < chars.len() && chars[i + 1].is_alphabetic() &&
+                   chars[i..].iter().position(|&c| c == '>').is_some() => {
+                let end = i + chars[i..].iter().position(|&c| c == '>').unwrap() + 1;
+                out.push_str(&*paint(OPAQUE, &*collect(&chars[i..end])));
+                i = end;
+            }
+            c if c.is_whitespace() || c == '|' => {
+                out.push(c);
+                i += 1;
+            }
+            _ => {
+                let end = i + chars[i..].iter()
+                    .take_while(|&&c| !c.is_whitespace() && !"()[]{}\"|".contains(c))
+                    .count();
+                let token = collect(&chars[i..end]);
+                let colour = match &*token {
+                    "true" | "false" => Some(BOOLEAN),
+                    _ if is_number(&*token) => Some(NUMBER),
+                    _ => None
+                };
+                match colour {
+                    Some(colour) => out.push_str(&*paint(colour, &*token)),
+                    None => out.push_str(&*token)
+                }
+                i = end;
+            }
+        }
+    }
+    out
+}
+
+// Index after the string starting at start, allowing for escaped quotes
+fn string_end(chars: &[char], start: usize) -> usize {
+    let mut i = start + 1;
+    while i < chars.len() {
+        match chars[i] {
+            '\\' => i += 2,
+            '"' => return i + 1,
+            _ => i += 1
+        }
+    }
+    chars.len()
+}
+
+fn is_number(token: &str) -> bool {
+    let digits = if token.starts_with("-") || token.starts_with("+") { &token[1..] } else { token };
+    digits.chars().next().map(|c| c.is_digit(10)).unwrap_or(false)
+}
+
+fn collect(chars: &[char]) -> String {
+    chars.iter().cloned().collect()
+}
@@ -0,0 +1,145 @@
+use std::fmt;
+use std::mem;
+
+use reader::{read_tokens, ParseError, Position, Span, Token, TokenKind};
+use Node;
+
+// Concrete syntax tree which keeps the whitespace and comments of the source
+// as trivia, so printing it reproduces the source exactly
+pub struct CstNode {
+    // Whitespace and comments preceding the node
+    pub leading: String,
+    pub kind: CstKind,
+    pub span: Span,
+}
+
+pub enum CstKind {
+    // The atom exactly as written, e.g. 0xFF rather than 255
+    Atom(String),
+    // Opening delimiter, elements, trivia before the close and the close
+    List(String, Vec<CstNode>, String, String),
+    // Reader sugar such as ' or ,@ and the form it applies to
+    Prefix(String, Box<CstNode>),
+}
+
+pub struct Cst {
+    pub forms: Vec<CstNode>,
+    // Trivia after the last form
+    pub trailing: String,
+    // The forms as the plain reader sees them
+    pub ast: Vec<Node>,
+}
+
+impl CstNode {
+    fn write_source(&self, out: &mut String) {
+        out.push_str(&*self.leading);
+        match self.kind {
+            CstKind::Atom(ref text) => out.push_str(&**text),
+            CstKind::List(ref open, ref elements, ref trailing, ref close) => {
+                out.push_str(&**open);
+                for element in elements.iter() {
+                    element.write_source(out);
+                }
+                out.push_str(&**trailing);
+                out.push_str(&**close);
+            }
+            CstKind::Prefix(ref prefix, ref form) => {
+                out.push_str(&**prefix);
+                form.write_source(out);
+            }
+        }
+    }
+}
+
+impl Cst {
+    pub fn to_source(&self) -> String {
+        let mut out = String::new();
+        for form in self.forms.iter() {
+            form.write_source(&mut out);
+        }
+        out.push_str(&*self.trailing);
+        out
+    }
+}
+
+impl fmt::Display for Cst {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&*self.to_source())
+    }
+}
+
+fn text(code: &str, start: Position, end: Position) -> String {
+    code[start.offset..end.offset].to_string()
+}
+
+// An open list or prefix with the elements of its parent set aside
+struct Open {
+    leading: String,
+    delimiter: String,
+    start: Position,
+    parent: Vec<CstNode>,
+    is_prefix: bool,
+}
+
+fn build(code: &str, tokens: &[Token]) -> (Vec<CstNode>, String) {
+    let mut elements = Vec::new();
+    let mut stack: Vec<Open> = Vec::new();
+    let mut last = Position::new();
+
+    for token in tokens.iter() {
+        let leading = text(code, last, token.span.start);
+        let delimiter = text(code, token.span.start, token.span.end);
+        last = token.span.end;
+
+        let mut node = match token.kind {
+            TokenKind::Open | TokenKind::Prefix => {
+                stack.push(Open {
+                    leading: leading,
+                    delimiter: delimiter,
+                    start: token.span.start,
+                    parent: mem::replace(&mut elements, Vec::new()),
+                    is_prefix: token.kind == TokenKind::Prefix,
+                });
+                continue
+            }
+            TokenKind::Atom => CstNode { leading: leading, kind: CstKind::Atom(delimiter), span: token.span },
+            TokenKind::Close => {
+                let open = stack.pop().unwrap();
+                let children = mem::replace(&mut elements, open.parent);
+                CstNode {
+                    leading: open.leading,
+                    kind: CstKind::List(open.delimiter, children, leading, delimiter),
+                    span: Span::new(open.start, token.span.end),
+                }
+            }
+        };
+
+        // Complete forms are wrapped by any prefixes waiting on them
+        loop {
+            match stack.last() {
+                Some(open) if open.is_prefix => {}
+                _ => break
+            }
+            let open = stack.pop().unwrap();
+            let end = node.span.end;
+            elements = open.parent;
+            node = CstNode {
+                leading: open.leading,
+                kind: CstKind::Prefix(open.delimiter, Box::new(node)),
+                span: Span::new(open.start, end),
+            };
+        }
+        elements.push(node);
+    }
+
+    let trailing = code[last.offset..].to_string();
+    (elements, trailing)
+}
+
+// Read keeping all trivia, so tools can rewrite parts of a source and print
+// the rest back unchanged
+pub fn read_lossless(code: &str) -> Result<Cst, ParseError> {
+    let (ast, tokens) = try!(read_tokens(code));
+    let (forms, trailing) = build(code, &*tokens);
+    Ok(Cst { forms: forms, trailing: trailing, ast: ast })
+}
@@ -0,0 +1,79 @@
+use editor::Editor;
+use eval::{Pause, Resumption, Stepper};
+use transcript;
+
+// Steps through evaluations from the terminal.  At each pause the
+// expression about to be evaluated is shown with the function it's in, as
+// are the arguments of a function with a breakpoint on entering it, and
+// commands are read until one says how to go on:
+//
+//   s or Enter   step into the expression
+//   n            step over it, pausing once it's evaluated
+//   c            continue without pausing
+//   v            show the variables bound
+//   w            show the functions called to get here
+//   q            abandon the evaluation
+pub struct Debugger {
+    editor: Editor,
+}
+
+impl Debugger {
+    pub fn new() -> Debugger {
+        Debugger { editor: Editor::new(None) }
+    }
+
+    // The function entered and its arguments
+    fn show_breakpoint(&self, pause: &Pause) {
+        let args: Vec<String> = pause.env.bindings().iter().rev()
+            .map(|&(name, ref value)| format!("{} = {}", name, value))
+            .collect();
+        match pause.calls.first() {
+            Some(name) => outln!("break in {} with {}", name, args.connect(", ")),
+            None => {}
+        }
+    }
+}
+
+impl Stepper for Debugger {
+    fn pause(&mut self, pause: &Pause) -> Resumption {
+        if pause.breakpoint {
+            self.show_breakpoint(pause);
+        }
+        match pause.calls.first() {
+            Some(name) => outln!("[{}] {}: {}", pause.calls.len(), name, pause.expr),
+            None => outln!("[0] {}", pause.expr)
+        }
+        loop {
+            let line = match self.editor.read_line("step> ") {
+                Ok(Some(line)) => line,
+                // With no more input there's no way to go on
+                _ => return Resumption::Abandon
+            };
+            transcript::record(&*format!("step> {}\n", line));
+            match line.trim() {
+                "" | "s" => return Resumption::Into,
+                "n" => return Resumption::Over,
+                "c" => return Resumption::Continue,
+                "q" => return Resumption::Abandon,
+                "v" => {
+                    let bindings = pause.env.bindings();
+                    if bindings.is_empty() {
+                        outln!("  no variables");
+                    }
+                    for &(name, ref value) in bindings.iter() {
+                        outln!("  {} = {}", name, value);
+                    }
+                }
+                "w" => {
+                    if pause.calls.is_empty() {
+                        outln!("  at the top level");
+                    }
+                    for name in pause.calls.iter() {
+                        outln!("  in {}", name);
+                    }
+                }
+                _ => outln!("s step, n next, c continue, v variables, w where, q quit")
+            }
+        }
+    }
+}
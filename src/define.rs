@@ -0,0 +1,226 @@
+use eval::{EvalError, Interpreter};
+use number::Number;
+use symbol::{self, SymbolId};
+use value::Value;
+
+// A clause of a define, Patterns -> Body or Patterns -> Body where Guard
+pub struct Clause {
+    pub patterns: Vec<Value>,
+    pub body: Value,
+    pub guard: Option<Value>,
+}
+
+// The tests a clause's arguments must pass and the variables its patterns
+// bind, each to the path into the arguments where its value is found
+struct Matcher {
+    tests: Vec<Value>,
+    bindings: Vec<(SymbolId, Value)>,
+}
+
+fn call(name: &str, args: Vec<Value>) -> Value {
+    let mut items = vec![Value::Symbol(symbol::intern(name))];
+    items.extend(args.into_iter());
+    Value::list(items)
+}
+
+impl Matcher {
+    fn new() -> Matcher {
+        Matcher { tests: Vec::new(), bindings: Vec::new() }
+    }
+
+    fn bound(&self, name: SymbolId) -> Option<Value> {
+        self.bindings.iter().find(|&&(bound, _)| bound == name).map(|&(_, ref path)| path.clone())
+    }
+
+    // Compile a pattern matched against the value at path.  A variable seen
+    // before in the clause must equal its first match.
+    fn pattern(&mut self, expr: &Value, pattern: &Value, path: Value) -> Result<(), EvalError> {
+        match *pattern {
+            Value::Symbol(name) if name == symbol::intern("_") => {}
+            Value::Symbol(name) if name.is_variable() => {
+                match self.bound(name) {
+                    Some(first) => self.tests.push(call("=", vec![path, first])),
+                    None => self.bindings.push((name, path))
+                }
+            }
+            Value::Num(_) | Value::Str(_) | Value::Bool(_) | Value::Symbol(_) | Value::Nil => {
+                self.tests.push(call("=", vec![path, pattern.clone()]))
+            }
+            // [H | T], which reads as (cons H T)
+            Value::List(ref items) if items.len() == 3 && items[0] == Value::Symbol(symbol::intern("cons")) => {
+                self.tests.push(call("cons?", vec![path.clone()]));
+                try!(self.pattern(expr, &items[1], call("hd", vec![path.clone()])));
+                try!(self.pattern(expr, &items[2], call("tl", vec![path])));
+            }
+            // (@p A B C) is the tuple (@p A (@p B C))
+            Value::List(ref items) if items.len() >= 3 && items[0] == Value::Symbol(symbol::intern("@p")) => {
+                self.tests.push(call("tuple?", vec![path.clone()]));
+                try!(self.pattern(expr, &items[1], call("fst", vec![path.clone()])));
+                let second = if items.len() == 3 {
+                    items[2].clone()
+                } else {
+                    let mut rest = vec![items[0].clone()];
+                    rest.extend(items[2..].iter().map(|item| item.clone()));
+                    Value::list(rest)
+                };
+                try!(self.pattern(expr, &second, call("snd", vec![path])));
+            }
+            // (@v X Rest) splits the first element off a vector, leaving the
+            // others as a new vector
+            Value::List(ref items) if items.len() >= 3 && items[0] == Value::Symbol(symbol::intern("@v")) => {
+                self.tests.push(call("shen.+vector?", vec![path.clone()]));
+                try!(self.pattern(expr, &items[1], call("hdv", vec![path.clone()])));
+                let rest = if items.len() == 3 {
+                    items[2].clone()
+                } else {
+                    let mut rest = vec![items[0].clone()];
+                    rest.extend(items[2..].iter().map(|item| item.clone()));
+                    Value::list(rest)
+                };
+                try!(self.pattern(expr, &rest, call("tlv", vec![path])));
+            }
+            // (@s C Rest) splits the first character C off a string.  A
+            // longer literal such as (@s "ab" Rest) matches each of its
+            // characters in turn.
+            Value::List(ref items) if items.len() >= 3 && items[0] == Value::Symbol(symbol::intern("@s")) => {
+                let mut first = match items[1] {
+                    Value::Str(ref s) => s.chars().map(|c| Value::string(c.to_string())).collect(),
+                    ref other => vec![other.clone()]
+                };
+                let mut rest = if items.len() == 3 {
+                    items[2].clone()
+                } else {
+                    let mut rest = vec![items[0].clone()];
+                    rest.extend(items[2..].iter().map(|item| item.clone()));
+                    Value::list(rest)
+                };
+                while first.len() > 1 {
+                    rest = Value::list(vec![items[0].clone(), first.pop().unwrap(), rest]);
+                }
+                match first.pop() {
+                    Some(first) => {
+                        self.tests.push(call("shen.+string?", vec![path.clone()]));
+                        try!(self.pattern(expr, &first, call("pos", vec![path.clone(), Value::Num(Number::Int(0))])));
+                        try!(self.pattern(expr, &rest, call("tlstr", vec![path])));
+                    }
+                    // The empty string splits off nothing
+                    None => try!(self.pattern(expr, &rest, path))
+                }
+            }
+            _ => return Err(EvalError::Malformed(symbol::DEFINE, expr.clone()))
+        }
+        Ok(())
+    }
+
+    // The guard is only tried once the patterns match, as it may use the
+    // variables they bind
+    fn condition(&self, guard: &Option<Value>) -> Value {
+        let mut tests = self.tests.clone();
+        match *guard {
+            Some(ref guard) => tests.push(self.scope(guard)),
+            None => {}
+        }
+        match tests.len() {
+            0 => Value::Bool(true),
+            1 => tests.pop().unwrap(),
+            _ => {
+                let mut items = vec![Value::Symbol(symbol::AND)];
+                items.extend(tests.into_iter());
+                Value::list(items)
+            }
+        }
+    }
+
+    // The expression evaluated with the clause's variables bound
+    fn scope(&self, body: &Value) -> Value {
+        if self.bindings.is_empty() {
+            return body.clone()
+        }
+        let mut items = vec![Value::Symbol(symbol::LET)];
+        for &(name, ref path) in self.bindings.iter() {
+            items.push(Value::Symbol(name));
+            items.push(path.clone());
+        }
+        items.push(body.clone());
+        Value::list(items)
+    }
+}
+
+// Split the type signature, such as {number --> number}, off the items
+// following the name, giving the items between the braces
+pub fn signature<'v>(expr: &Value, items: &'v [Value]) -> Result<(Option<&'v [Value]>, &'v [Value]), EvalError> {
+    let open = Value::Symbol(symbol::intern("{"));
+    let close = Value::Symbol(symbol::intern("}"));
+    if items.first() != Some(&open) {
+        return Ok((None, items))
+    }
+    match items.iter().position(|item| *item == close) {
+        Some(end) => Ok((Some(&items[1..end]), &items[end + 1..])),
+        None => Err(EvalError::Malformed(symbol::DEFINE, expr.clone()))
+    }
+}
+
+pub fn clauses(expr: &Value, items: &[Value]) -> Result<Vec<Clause>, EvalError> {
+    let arrow = Value::Symbol(symbol::intern("->"));
+    let where_ = Value::Symbol(symbol::intern("where"));
+    let malformed = || EvalError::Malformed(symbol::DEFINE, expr.clone());
+    let mut clauses = Vec::new();
+    let mut rest = items;
+    while !rest.is_empty() {
+        let arity = match rest.iter().position(|item| *item == arrow) {
+            Some(arity) if arity + 1 < rest.len() => arity,
+            _ => return Err(malformed())
+        };
+        let guard = match rest.get(arity + 2) {
+            Some(item) if *item == where_ => {
+                match rest.get(arity + 3) {
+                    Some(guard) => Some(guard.clone()),
+                    None => return Err(malformed())
+                }
+            }
+            _ => None
+        };
+        let length = if guard.is_some() { arity + 4 } else { arity + 2 };
+        clauses.push(Clause { patterns: rest[..arity].to_vec(), body: rest[arity + 1].clone(), guard: guard });
+        rest = &rest[length..];
+    }
+    if clauses.is_empty() || clauses.iter().any(|clause| clause.patterns.len() != clauses[0].patterns.len()) {
+        return Err(malformed())
+    }
+    Ok(clauses)
+}
+
+// (define Name Patterns -> Body ...) compiles to a defun whose body is a cond
+// trying each clause in turn, raising an error when none matches.  A clause
+// whose where guard is false falls through to the next.
+//
+// (define len [] -> 0 [_ | T] -> (+ 1 (len T)))
+//
+// (defun len (V1) (cond ((= V1 ()) 0)
+//                       ((cons? V1) (let T (tl V1) (+ 1 (len T))))
+//                       (true (simple-error "partial function len"))))
+pub fn compile(interpreter: &mut Interpreter, expr: &Value, items: &[Value]) -> Result<Value, EvalError> {
+    let name = match items.get(1) {
+        Some(&Value::Symbol(name)) => name,
+        _ => return Err(EvalError::Malformed(symbol::DEFINE, expr.clone()))
+    };
+    let (_, rest) = try!(signature(expr, &items[2..]));
+    let clauses = try!(clauses(expr, rest));
+    let params: Vec<Value> = clauses[0].patterns.iter()
+        .map(|_| Value::Symbol(interpreter.gensym("V")))
+        .collect();
+
+    let mut cond = vec![Value::Symbol(symbol::COND)];
+    for clause in clauses.iter() {
+        let mut matcher = Matcher::new();
+        for (pattern, param) in clause.patterns.iter().zip(params.iter()) {
+            try!(matcher.pattern(expr, pattern, param.clone()));
+        }
+        cond.push(Value::list(vec![matcher.condition(&clause.guard), matcher.scope(&clause.body)]));
+    }
+    let failure = call("simple-error", vec![Value::string(format!("partial function {}", name))]);
+    cond.push(Value::list(vec![Value::Bool(true), failure]));
+
+    let params = if params.is_empty() { Value::Nil } else { Value::list(params) };
+    Ok(Value::list(vec![Value::Symbol(symbol::DEFUN), Value::Symbol(name), params, Value::list(cond)]))
+}
@@ -0,0 +1,431 @@
+// Examples of reading and evaluating, run by burton --demo, which show what
+// each part of the implementation does
+
+use std::old_io::MemReader;
+use std::old_io::fs;
+use std::old_path::Path;
+use std::os;
+use std::num::Float;
+use number::{self, Number};
+use symbol;
+use eval::Interpreter;
+use value::Value;
+use reader::{self, parse_one, read, read_iter, read_partial, read_with_options, read_with_recovery, read_with_table};
+use reader::{ParseError, ParseOutcome, Reader, ReaderOptions, ReadTable};
+use {borrowed, cst, pretty, repl};
+use {Node, SymbolicExpr};
+
+fn print_read(ast: Result<Vec<Node>, ParseError>) {
+    match ast {
+        Ok(sexprs) => {
+            for s in sexprs.iter() {
+                println!("{}", s);
+            }
+        }
+        Err(e) => println!("{}", e)
+    }
+}
+
+fn print_eval(interpreter: &mut Interpreter, code: &str) {
+    match read(code) {
+        Ok(forms) => {
+            for form in forms.iter() {
+                repl::print_result(interpreter.eval_toplevel(&Value::from_node(form)));
+            }
+        }
+        Err(e) => println!("{}", e)
+    }
+}
+
+// Evaluate expressions, checking each gives the value, as printed, or the
+// error expected of it.  The demo ends at the first which doesn't, so that a
+// rule which has changed can't go unnoticed.
+fn check_eval(interpreter: &mut Interpreter, cases: &[(&str, &str)]) {
+    for &(code, expected) in cases.iter() {
+        let mut got = String::new();
+        match read(code) {
+            Ok(forms) => {
+                for form in forms.iter() {
+                    got = match interpreter.eval_toplevel(&Value::from_node(form)) {
+                        Ok((value, _)) => value.to_string(),
+                        Err(e) => e.to_string()
+                    };
+                }
+            }
+            Err(e) => got = e.to_string()
+        }
+        if got != expected {
+            panic!("{} gave {}, expected {}", code, got, expected)
+        }
+        println!("{} ==> {}", code, got);
+    }
+}
+
+pub fn run() {
+    let code = "12.3";
+    print_read(read(code));
+
+    let sym = "+";
+    print_read(read(sym));
+
+    let list = "()";
+    print_read(read(list));
+
+    let add = "(+ 1 2)";
+    print_read(read(add));
+
+    let magsqr = "(* (+ 1 2) (+ 3 4))";
+    print_read(read(magsqr));
+
+    let commented = "(+ 1 2) \\\\ add one and two\n(* 3 4)";
+    print_read(read(commented));
+
+    let block = "\\* (ignored (entirely))\n   across lines *\\ (+ 1 2)";
+    print_read(read(block));
+
+    let negative = "(- -5 -2.5 -x)";
+    print_read(read(negative));
+
+    let scientific = "(1e10 2.5E-3 -4e+2)";
+    print_read(read(scientific));
+
+    let radix = "(0xFF 0o17 0b1010 -0x10)";
+    print_read(read(radix));
+
+    let mixed = "(3 3.0 1e2)";
+    print_read(read(mixed));
+
+    let big = "(9223372036854775807 9223372036854775808 0x1FFFFFFFFFFFFFFFF -9223372036854775808 -0x8000000000000000)";
+    print_read(read(big));
+
+    let booleans = "(and true false)";
+    print_read(read(booleans));
+
+    match read("(defun f (x)\n  (+ x 1))") {
+        Ok(nodes) => {
+            if let SymbolicExpr::ListExpr(ref items) = nodes[0].expr {
+                for item in items.iter() {
+                    println!("{:?}", item);
+                }
+            }
+        }
+        Err(e) => println!("{}", e)
+    }
+
+    print_read(read("(+ 1 2))"));
+    print_read(read("(+ 1\n  (* 2 3)"));
+    print_read(read("(+ 1 2x)"));
+
+    let (forms, errors) = read_with_recovery("(defun f (x) 1x)\n(defun g (y) y)\n)\n(defun h (z\n");
+    for form in forms.iter() {
+        println!("{}", form);
+    }
+    for error in errors.iter() {
+        println!("{}", error);
+    }
+
+    for form in read_iter("(defun one () 1) (one) 2x") {
+        match form {
+            Ok(node) => println!("{}", node),
+            Err(e) => println!("{}", e)
+        }
+    }
+
+    for input in ["(defun f (x", "(defun f (x) x)", "(defun f (x) x))"].iter() {
+        match read_partial(*input) {
+            Ok(ParseOutcome::Complete(forms)) => println!("Complete: {} forms", forms.len()),
+            Ok(ParseOutcome::NeedMore) => println!("Need more: {}", input),
+            Err(e) => println!("{}", e)
+        }
+    }
+
+    print_read(read("'a '(1 (2 3)) ''b"));
+    print_read(read("(f ')"));
+    print_read(read("`(a ,b ,@c , d)"));
+
+    print_read(read("[1 2 3] [] [H | T] [1 [2]]"));
+    print_read(read("[1 | 2 3]"));
+    print_read(read("(1 2]"));
+
+    match read("(λ über 名前\u{00a0}x≤y)") {
+        Ok(nodes) => {
+            if let SymbolicExpr::ListExpr(ref items) = nodes[0].expr {
+                for item in items.iter() {
+                    println!("{:?}", item);
+                }
+            }
+        }
+        Err(e) => println!("{}", e)
+    }
+    print_read(read("(a \u{7} b)"));
+
+    print_read(read(r#"(cn "say \"hi\"\n" "tab\there" "\u{3bb}\\")"#));
+    print_read(read(r#"("bad \q escape")"#));
+    print_read(read(r#""unterminated"#));
+
+    print_read(read(r"(#\a #\λ #\space #\( #\) #\#)"));
+    print_read(read(r"#\bogus"));
+
+    let mut options = ReaderOptions::new();
+    options.max_depth = 3;
+    print_read(read_with_options("(1 (2 (3)))", options));
+    print_read(read_with_options("(1 (2 ('3)))", options));
+    let hostile: String = (0..1000000).map(|_| '(').collect();
+    print_read(read(&*hostile));
+
+    let source = "\\* header *\\\n(defun f (X)  \\\\ comment\n  [X | '(0xFF \"s\")] ) \n,@x\n";
+    match cst::read_lossless(source) {
+        Ok(tree) => {
+            println!("{} forms, round trip {}", tree.forms.len(), tree.to_string() == source);
+            for form in tree.ast.iter() {
+                println!("{}", form);
+            }
+        }
+        Err(e) => println!("{}", e)
+    }
+
+    match borrowed::read_borrowed(r#"(defun greet (Name) [(cn "hi " Name) "\t" - #\a 'x])"#) {
+        Ok(forms) => {
+            for form in forms.iter() {
+                println!("{}", form);
+                println!("{}", form.to_node());
+            }
+        }
+        Err(e) => println!("{}", e)
+    }
+
+    let printable = r#"(defun f [X | Y] '("a\"b" #\space -1/3 2.0 1e-7 0xFFFFFFFFFFFFFFFFF true))"#;
+    match read(printable) {
+        Ok(forms) => {
+            let printed: Vec<String> = forms.iter().map(|form| form.to_sexpr_string()).collect();
+            println!("{}", printed.connect(" "));
+            match read(&*printed.connect(" ")) {
+                Ok(reread) => println!("round trip {}", reread == forms),
+                Err(e) => println!("{}", e)
+            }
+        }
+        Err(e) => println!("{}", e)
+    }
+
+    let long = "(define fold F Acc [] -> Acc F Acc [X | Xs] -> (fold F (F Acc X) Xs)) (+ 1 2)";
+    match read(long) {
+        Ok(forms) => {
+            for width in [pretty::DEFAULT_WIDTH, 30].iter() {
+                for form in forms.iter() {
+                    println!("{}", pretty::pretty(&form.expr, *width));
+                }
+            }
+        }
+        Err(e) => println!("{}", e)
+    }
+
+    let lenient = Reader::new().case_sensitive(false).strict(false).brackets(false);
+    print_read(lenient.read("(Defun 2x (X) 1e)"));
+    print_read(lenient.read("[1 2]"));
+    print_read(Reader::new().line_comments(false).read("(a \\\\ b)"));
+
+    match read("(a . b) (1 2 . (3 4)) [1 2 | 3] [1 2]") {
+        Ok(forms) => {
+            for form in forms.iter() {
+                println!("{} {}", form.to_sexpr_string(), form.expr.to_data_string());
+            }
+        }
+        Err(e) => println!("{}", e)
+    }
+    print_read(read("(a . b c)"));
+    print_read(read("(. a)"));
+    match borrowed::read_borrowed("(a . b) [a | b] [a b]") {
+        Ok(forms) => {
+            for form in forms.iter() {
+                println!("{} {}", form, form.to_node().to_sexpr_string());
+            }
+        }
+        Err(e) => println!("{}", e)
+    }
+
+    print_read(read("(defun f (X) #;(print (debug X)) X) #; #;a b c '#;d e"));
+    print_read(read("(f #;)"));
+
+    let mut embedded = "(+ 1 2) then [3 4])) and more";
+    loop {
+        match parse_one(embedded) {
+            Ok(Some((form, rest))) => {
+                println!("{} rest {}", form, reader::escape_string(rest));
+                embedded = rest;
+            }
+            Ok(None) => break,
+            Err(e) => {
+                println!("{}", e);
+                break
+            }
+        }
+    }
+
+    let mut session = MemReader::new("\\\\ a session\n\n(defun f\n  (X) X) (f\n 1)\n(f 2)\n(f\n".as_bytes().to_vec());
+    loop {
+        match reader::lineread(&mut session) {
+            Ok(forms) => println!("{} forms from {}", forms.len(), forms[0]),
+            Err(e) => {
+                println!("{}", e);
+                break
+            }
+        }
+    }
+
+    match (read("(f X)"), read("[f X]")) {
+        (Ok(a), Ok(b)) => {
+            match (&a[0].expr, &b[0].expr) {
+                (&SymbolicExpr::ListExpr(ref x), &SymbolicExpr::ListExpr(ref y)) => {
+                    println!("same symbol {}, {} symbols", x[0] == y[1], symbol::interned());
+                }
+                _ => {}
+            }
+        }
+        _ => {}
+    }
+
+    let mut table = ReadTable::new();
+    table.register('s', Box::new(|node: Node| {
+        match node.expr {
+            SymbolicExpr::ListExpr(items) => {
+                let mut set = vec![Node::new(SymbolicExpr::Symbol(symbol::intern("set")),
+                                             node.span.start, node.span.start)];
+                set.extend(items.into_iter());
+                Ok(SymbolicExpr::ListExpr(set))
+            }
+            _ => Err("#s expects a list".to_string())
+        }
+    }));
+    print_read(read_with_table("(union #s(1 2) #s(2 3) #x)", &table));
+    print_read(read_with_table("#s 3", &table));
+
+    let rationals = "(1/3 -2/4 6/3)";
+    print_read(read(rationals));
+
+    match (read("1/3"), read("1/6")) {
+        (Ok(a), Ok(b)) => {
+            match (Number::from_sexpr(&a[0].expr), Number::from_sexpr(&b[0].expr)) {
+                (Some(x), Some(y)) => println!("{}", number::add(x, y)),
+                _ => {}
+            }
+        }
+        _ => {}
+    }
+
+    let mut interpreter = Interpreter::new();
+    print_eval(&mut interpreter, "((lambda X X) 41)");
+    print_eval(&mut interpreter, "(((lambda X (lambda Y X)) 1) 2)");
+    print_eval(&mut interpreter, "((/. X Y Y) 1 2) (lambda X X) foo");
+    print_eval(&mut interpreter, "(lambda X) (foo 1) (1 2)");
+    print_eval(&mut interpreter, "(defun second (X Y) Y) (defun const (X) (lambda Y X)) (second 1 2) (const 1 2)");
+    print_eval(&mut interpreter, "(defun bad X X)");
+    print_eval(&mut interpreter, "(if true yes no) (cond (false 1) ((or false true) 2)) (and true false 1)");
+    print_eval(&mut interpreter, "(if 1 yes no) (cond (false 1)) (or true undefined-call)");
+    print_eval(&mut interpreter, "(let X 1 Y (let X 2 X) (second X Y)) (let X 1 (let X (const X) (X 3)))");
+    print_eval(&mut interpreter, "(let X 1 X X) (let 1 2 3)");
+    print_eval(&mut interpreter, "(second 1) ((second 1) 2) ((/. F (F 1)) (second 0)) ((lambda X (second X)) 1 2)");
+    print_eval(&mut interpreter, "(let X 1 (freeze (second 0 X))) (thaw (let X 1 (freeze (second 0 X)))) (thaw 1)");
+    print_eval(&mut interpreter, "(trap-error (simple-error \"oops\") (lambda E (error-to-string E))) (trap-error ok (lambda E E))");
+    print_eval(&mut interpreter, "(trap-error (undefined 1) (/. E E)) (simple-error \"uncaught\") (error-to-string 1)");
+    print_eval(&mut interpreter, "(set *counter* 1) (value *counter*) (let X *counter* (value X)) (value *unset*)");
+    print_eval(&mut interpreter, "[1 [2 3] []] [1 | 2] (hd [a b]) (tl [a b]) (cons? []) (cons? (cons 1 ())) (hd [])");
+    print_eval(&mut interpreter, "(+ 1 2) ((+ 1) 2) ((lambda X (+ X 1)) 41) (* 9223372036854775807 2) (- 1 0.5) (/ 1 3) (/ 1 0) (+ a 1)");
+    print_eval(&mut interpreter, "(= 1 1.0) (= [1 [2 \"a\"]] [1.0 [2 \"a\"]]) (= [1 2] [1 | 2]) (= 1/2 0.5) (< 1 2.5) (>= 2 2) (> a 1)");
+    print_eval(&mut interpreter, "(defun count (N Acc) (if (= N 0) Acc (count (- N 1) (+ Acc 1)))) (count 100000 0)");
+    print_eval(&mut interpreter, "(cn \"ab\" \"cd\") (pos \"λx\" 1) (tlstr \"λx\") (str [a \"b\" 1.0]) (string->n \"a\") (n->string 955) (pos \"a\" 3)");
+    print_eval(&mut interpreter, "(read-from-string \"(+ 1 2) [a]\") (hd (hd (read-from-string \"(+ 1 2)\")))");
+    print_eval(&mut interpreter, "(intern \"foo\") (= (intern \"foo\") foo) (symbol? (intern \"true\")) V2 (gensym V) (gensym V) (= (gensym V) (gensym V))");
+    print_eval(&mut interpreter, "(let V (absvector 2) (address-> (address-> V 0 a) 1 b)) (let V (absvector 2) (<-address (address-> V 1 b) 1))");
+    print_eval(&mut interpreter, "(address-> (absvector 2) 0 x) (<-address (absvector 2) 2) (absvector? (absvector 0)) (= (absvector 1) (absvector 1)) (absvector 100000000)");
+    print_eval(&mut interpreter, "(put fib arity 1) (get fib arity) (put fib arity 2) (get fib arity) (unput fib arity) (get fib arity) (get 1 arity)");
+    print_eval(&mut interpreter, "(eval-kl (cons + (cons 1 (cons 2 ())))) (eval-kl (cons defun (cons twice (cons (cons x ()) (cons (cons * (cons x (cons 2 ()))) ()))))) (twice 21) (eval-kl 5)");
+    print_eval(&mut interpreter, "(defmacro inc-macro X (if (and (cons? X) (= (hd X) inc)) (cons + (cons 1 (tl X))) X)) (inc 41) (+ (inc 1) (inc 2)) (value *macros*) (macroexpand (cons inc (cons 5 ()))) (undefmacro inc-macro) (inc 1)");
+    print_eval(&mut interpreter, "(defmacro grow-macro X (if (and (cons? X) (= (hd X) grow)) (cons grow X) X)) (grow 1) (undefmacro grow-macro)");
+    print_eval(&mut interpreter, "(define len [] -> 0 [_ | T] -> (+ 1 (len T))) (len [a b c]) (define same X X -> true _ _ -> false) (same 1 1) (same [1 a] [1 a]) (same 1 2)");
+    print_eval(&mut interpreter, "(define second [_ Y | _] -> Y) (second [1 2 3]) (second [1]) (define both-a [a a] -> yes) (both-a [a a]) (define f X -> ) (define f X -> 1 X Y -> 2)");
+    print_eval(&mut interpreter, "(define sign X -> neg where (< X 0) 0 -> zero _ -> pos) (sign -3) (sign 0) (sign 7) (define positive [] -> [] [X | Y] -> [X | (positive Y)] where (> X 0) [_ | Y] -> (positive Y)) (positive [1 -2 3 -4]) (define g X -> 1 where)");
+    print_eval(&mut interpreter, "(@p 1 (@p a b)) (fst (@p 1 2)) (snd (@p 1 2)) (tuple? (@p 1 2)) (tuple? [1 2]) (= (@p 1 2) (@p 1.0 2)) (fst 3)");
+    print_eval(&mut interpreter, "(define swap (@p X Y) -> (@p Y X)) (swap (@p 1 2)) (define middle (@p _ M _) -> M) (middle (@p 1 (@p 2 3))) (swap 5)");
+    print_eval(&mut interpreter, "(@s \"ab\" \"cd\") (define initial (@s C _) -> C \"\" -> none) (initial \"hello\") (initial \"\") (define drop-prefix (@s \"re\" S) -> S S -> S) (drop-prefix \"reread\") (drop-prefix \"read\") (define count-a \"\" -> 0 (@s \"a\" S) -> (+ 1 (count-a S)) (@s _ S) -> (count-a S)) (count-a \"banana\") (@s \"a\" 1)");
+    print_eval(&mut interpreter, "<> (@v 1 (@v 2 <>)) (= <> <>) (define sum-v <> -> 0 (@v X V) -> (+ X (sum-v V))) (sum-v (@v 1 (@v 2 (@v 3 <>)))) (define second-v (@v _ X _) -> X) (second-v (@v 1 (@v 2 <>))) (@v 1 2)");
+    print_eval(&mut interpreter, "(define double {number --> number} X -> (* 2 X)) (double 4) (tc +) (double 4) (+ 1 a) 42 [1 2] (@p 1 \"a\") (lambda X (+ X 1)) (fst (@p 1 2))");
+    print_eval(&mut interpreter, "(define len {(list A) --> number} [] -> 0 [_ | T] -> (+ 1 (len T))) (len [a b c]) (define bad {A --> A} X -> 1) (define untyped X -> X) (define pair-swap {(A * B) --> (B * A)} (@p X Y) -> (@p Y X)) (pair-swap (@p 1 a)) (define only-positive {number --> number} X -> X where (> X 0)) (tc -) (+ 1 a)");
+    print_eval(&mut interpreter, "(tc +) (datatype colour if (or (= X red) (= X green)) ____ X : colour;) (datatype point X : number; Y : number; ==== [X Y] : point;) (define px {point --> number} [X Y] -> X) (px [3 4]) (px [3 a]) (define warm {colour --> boolean} red -> true _ -> false) (warm red) (warm blue) (define origin {--> point} -> [0 0]) (tc -)");
+    print_eval(&mut interpreter, "(defprolog member X [X | _] <--; X [_ | Y] <-- (member X Y);) (prolog? (member 2 [1 2 3])) (prolog? (member 4 [1 2 3])) (prolog? (member X [a b]) (return X)) (let L [5 6] (prolog? (receive L) (member X L) (when (> X 5)) (return X)))");
+    print_eval(&mut interpreter, "(defprolog app [] X X <--; [H | T] Y [H | Z] <-- (app T Y Z);) (prolog? (app X Y [1 2]) (when (= X [1])) (return Y)) (defprolog first X [X | _] <-- !; X [_ | Y] <-- (first X Y);) (prolog? (first X [p q]) (is Y (cn (str X) \"!\")) (return Y)) (defprolog len [] 0 <--; [_ | T] N <-- (len T M) (is N (+ M 1));) (prolog? (len [a b c] N) (return N)) (prolog? (nope 1))");
+    print_eval(&mut interpreter, "(defcc <digit> X := X where (and (= (symbol? X) false) (> X -1) (< X 10));) (defcc <digits> <digit> <digits> := [<digit> | <digits>]; <digit> := [<digit>];) (compile <digits> [1 2 3]) (compile <digits> [1 2 x]) (compile <digits> [x])");
+    print_eval(&mut interpreter, "(defcc <greeting> hello <name> := (cn \"hi \" <name>); bye <!> := <!>;) (defcc <name> X := (str X);) (compile <greeting> [hello world]) (compile <greeting> [bye now then]) (defcc <as> a <as>; <e>;) (compile <as> [a a])");
+    print_eval(&mut interpreter, "(map (lambda X (* X X)) [1 2 3]) (filter (lambda X (> X 1)) [1 2 3]) (reverse [1 2 3]) (append [1] [2 3]) (length [a b c]) (element? b [a b]) (assoc b [[a 1] [b 2]]) (nth 2 [a b c]) (sum [1 2 3.5]) (union [1 2] [2 3]) (explode \"abc\") (not (empty? [])) (fold-right (/. X Acc [X | Acc]) [1 2] [3])");
+    print_eval(&mut interpreter, "(tc +) (map (lambda X (+ X 1)) [1 2]) (fold-left (/. Acc X (cn Acc X)) \"\" [\"a\" \"b\"]) (length 3) (tc -)");
+    print_eval(&mut interpreter, "(package geometry [area] (define square X -> (* X X)) (define area R -> (* 3 (square R)))) (area 2) (geometry.square 3) (square 3) (external geometry) (package null [] (define plain -> done)) (plain) (external nowhere)");
+    print_eval(&mut interpreter, "(function length) ((function length) [1 2]) (map (function abs) [-1 2]) (map abs [-3]) (let F reverse (F [1 2])) (arity map) (arity cons) (arity nothing) (function nothing) (tc +) (map (function abs) [-1]) (tc -)");
+    print_eval(&mut interpreter, "(defun build (N) (if (= N 0) () (cons N (build (- N 1))))) (length (build 100000))");
+    interpreter.set_max_depth(1000);
+    print_eval(&mut interpreter, "(length (build 2000)) (length (build 100))");
+    print_eval(&mut interpreter, "(+ 1 (reset (* 2 (shift K (K (K 5)))))) (reset (+ 1 (shift K 10))) (shift K 1) (tc +) (reset (+ 1 (shift K (K (K 2))))) (reset (+ 1 (shift K (K a)))) (tc -)");
+    print_eval(&mut interpreter, "(defun yield (X) (shift K (@p X K))) (defun walk (L) (if (empty? L) done (let Y (yield (hd L)) (walk (tl L))))) (defun collect (G) (if (tuple? G) [(fst G) | (collect ((snd G) ok))] [])) (collect (reset (walk [1 2 3])))");
+    print_eval(&mut interpreter, "(defun choose (L) (shift K (append-all (map K L)))) (defun append-all (L) (if (empty? L) [] (append (hd L) (append-all (tl L))))) (reset (let X (choose [1 2 3]) Y (choose [1 2 3]) (if (= (+ X Y) 4) [[X Y]] [])))");
+    print_eval(&mut interpreter, "(unwind-protect 1 (set *cleaned* normal)) (value *cleaned*) (trap-error (unwind-protect (simple-error \"boom\") (set *cleaned* error)) (lambda E (error-to-string E))) (value *cleaned*)");
+    print_eval(&mut interpreter, "(unwind-protect (unwind-protect (simple-error \"inner\") (set *order* [inner])) (set *order* [outer | (value *order*)])) (value *order*)");
+    print_eval(&mut interpreter, "(defun inner (X) (+ (hd X) 1)) (defun middle (X) (* 2 (inner X))) (defun outer (X) (middle X)) (outer [1]) (outer [])");
+    interpreter.set_max_steps(Some(10000));
+    print_eval(&mut interpreter, "(defun forever (N) (forever (+ N 1))) (forever 0) (trap-error (forever 0) (lambda E trapped)) (length (build 100))");
+    print_eval(&mut interpreter, "(defmacro busy-macro X (do (build 100) X)) [1 [2 [3 [4 [5 [6 [7 [8 [9]]]]]]]]] (undefmacro busy-macro)");
+    interpreter.set_max_steps(None);
+    print_eval(&mut interpreter, "(defun nest (N) (if (= N 0) 0 (eval-kl [nest (- N 1)]))) (nest 50) (nest 500)");
+    print_eval(&mut interpreter, "(do (set *log* []) (set *log* [a | (value *log*)]) (set *log* [b | (value *log*)]) (value *log*)) (do 1) (do) (tc +) (do (set *n* 1) \"done\") (tc -)");
+    print_eval(&mut interpreter, "(defun count-down (N) (if (= N 0) done (do (set *last* N) (count-down (- N 1))))) (count-down 100000) (value *last*)");
+    print_eval(&mut interpreter, "(defun sign (N) (cases (> N 0) positive (< N 0) negative true zero)) (map sign [3 -1 0]) (cases false 1) (cases true) (tc +) (cases (= 1 2) \"no\" true \"yes\") (cases true 1 true a) (tc -)");
+    // The numeric tower's rules for mixing integers, ratios and floats
+    check_eval(&mut interpreter, &[
+        ("(+ 1 2)", "3"),
+        ("(+ 1 2.0)", "3.0"),
+        ("(+ 1/2 1/2)", "1"),
+        ("(+ 1/2 0.5)", "1.0"),
+        ("(* 9223372036854775807 2)", "18446744073709551614"),
+        ("(- (* 9223372036854775807 2) 9223372036854775807)", "9223372036854775807"),
+        ("(* 1/3 3.0)", "1.0"),
+        ("(/ 1 3)", "1/3"),
+        ("(/ 4 2)", "2"),
+        ("(/ 4.0 2)", "2.0"),
+        ("(/ 1 2.0)", "0.5"),
+        ("(/ 9223372036854775808 2)", "4611686018427387904"),
+        ("(/ 1 0)", "Division by zero"),
+        ("(/ 1.0 0)", "Division by zero"),
+        ("(= 1 1.0)", "true"),
+        ("(= 1/2 0.5)", "true"),
+        ("(= 1/3 0.3333333333333333)", "false"),
+        ("(< 9007199254740992 9007199254740993.0)", "false"),
+        ("(= 9007199254740993 9007199254740992.0)", "false"),
+        ("(> 1/3 0.3333333333333333)", "true"),
+        ("(integer? 2.0)", "true"),
+        ("(integer? 1/2)", "false"),
+        ("(number? 1/2)", "true"),
+        ("(number? a)", "false"),
+    ]);
+    print_eval(&mut interpreter, "(div 7 2) (mod 7 2) (div -7 2) (mod -7 2) (mod 7 -2) (div 100000000000000000000 3) (div 1 0) (mod 1.5 1)");
+    print_eval(&mut interpreter, "(bitwise-and 12 10) (bitwise-or 12 10) (bitwise-xor 12 10) (bitwise-and -1 255) (shift-left 1 70) (shift-right (shift-left 1 70) 69) (shift-right -5 1) (shift-left 1 -1) (shift-left 1 100000000)");
+    print_eval(&mut interpreter, "(sqrt 16) (sqrt 9/4) (sqrt 2) (sqrt 2.25) (sqrt 0) (sqrt -1) (exp 0) (log 1) (log -1) (sin 0) (atan2 1 1) (asin 2)");
+    print_eval(&mut interpreter, "(floor 7/2) (ceiling 7/2) (round 7/2) (round -7/2) (truncate -7/2) (floor -2.5) (round 2.5) (floor 3) (floor 1e20) (floor (/ 1.0 0.0))");
+    print_eval(&mut interpreter, "(seed-random 42) (random 10) (random 10) (random 10) (seed-random 42) (random 10) (random 10) (random 10) (random 1.0) (random 100000000000000000000) (random 0)");
+    print_eval(&mut interpreter, "(> (get-time unix) 1400000000) (number? (get-time real)) (let Start (get-time run) Sum (sum (map (lambda X (* X X)) [1 2 3])) (>= (- (get-time run) Start) 0)) (get-time never)");
+    print_eval(&mut interpreter, "(set *d* (shen.dict 16)) (shen.dict-> (value *d*) a 1) (shen.dict-> (value *d*) [1 2] pair) (shen.dict-> (value *d*) 2 two) (shen.<-dict (value *d*) [1 2]) (shen.<-dict (value *d*) 2.0) (shen.dict-count (value *d*)) (shen.dict-rm (value *d*) a) (shen.<-dict (value *d*) a) (shen.dict? (value *d*))");
+    print_eval(&mut interpreter, "(shen.dict-fold (/. K V N (+ N 1)) (value *d*) 0) (length (shen.dict-keys (value *d*))) (value *d*)");
+    print_eval(&mut interpreter, "(defun nest (N X) (if (= N 0) X (nest (- N 1) [X]))) (= (nest 20000 a) (nest 20000 a)) (= (nest 20000 a) (nest 20000 b)) (= (@p 1 [2 3]) (@p 1.0 [2 3])) (= (@v 1 <>) (@v 1 <>))");
+    print_eval(&mut interpreter, "(set *a* (absvector 2)) (set *b* (absvector 2)) (do (address-> (value *a*) 0 (value *a*)) (address-> (value *b*) 0 (value *b*)) (= (value *a*) (value *b*))) (do (address-> (value *b*) 1 x) (= (value *a*) (value *b*)))");
+    // A vector holding itself is printed with <...> where it's met again
+    check_eval(&mut interpreter, &[
+        ("(value *a*)", "<<...> []>"),
+        ("(let V (absvector 2) (address-> V 1 [V (@p 1 V)]))", "<[] [<...> (@p 1 <...>)]>"),
+    ]);
+
+    // An image restores the functions, datatypes and signatures of a typed
+    // session, so that code is checked as it was before saving
+    let image = os::tmpdir().join("burton-demo.image").display().to_string();
+    let mut saved = Interpreter::new();
+    print_eval(&mut saved, &*format!("(tc +) (datatype point X : number; Y : number; ==== [X Y] : point;) \
+                                     (define px {{point --> number}} [X Y] -> X) (save-image \"{}\")", image));
+    let mut restored = Interpreter::new();
+    print_eval(&mut restored, &*format!("(load-image \"{}\") (px [3 4]) (px [3 a]) (tc -) (px [a 4])", image));
+    let _ = fs::unlink(&Path::new(&*image));
+}
@@ -0,0 +1,131 @@
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher, SipHasher};
+
+use value::Value;
+
+// Hash tables keyed by any value, keys being the same when they're equal by
+// =.  Values don't implement Hash, as equal numbers such as 1 and 1.0 must
+// hash alike, so entries are kept in buckets by a hash of our own.
+pub struct Dict {
+    buckets: HashMap<u64, Vec<(Value, Value)>>,
+    count: usize,
+}
+
+// Structures are only hashed this many levels deep, which is enough to tell
+// most keys apart and means vectors containing themselves can still be keys
+const HASH_DEPTH: usize = 4;
+
+impl Dict {
+    pub fn new() -> Dict {
+        Dict { buckets: HashMap::new(), count: 0 }
+    }
+
+    pub fn len(&self) -> usize {
+        self.count
+    }
+
+    pub fn get(&self, key: &Value) -> Option<&Value> {
+        self.buckets.get(&hash(key)).and_then(|bucket| {
+            bucket.iter().find(|&&(ref k, _)| k == key).map(|&(_, ref v)| v)
+        })
+    }
+
+    pub fn insert(&mut self, key: Value, value: Value) {
+        let bucket = self.buckets.entry(hash(&key)).or_insert_with(Vec::new);
+        match bucket.iter().position(|&(ref k, _)| *k == key) {
+            Some(i) => bucket[i].1 = value,
+            None => {
+                bucket.push((key, value));
+                self.count += 1;
+            }
+        }
+    }
+
+    // Remove the key, giving the value it had
+    pub fn remove(&mut self, key: &Value) -> Option<Value> {
+        let h = hash(key);
+        let (removed, empty) = match self.buckets.get_mut(&h) {
+            Some(bucket) => {
+                match bucket.iter().position(|&(ref k, _)| k == key) {
+                    Some(i) => (Some(bucket.remove(i).1), bucket.is_empty()),
+                    None => (None, false)
+                }
+            }
+            None => return None
+        };
+        if empty {
+            self.buckets.remove(&h);
+        }
+        if removed.is_some() {
+            self.count -= 1;
+        }
+        removed
+    }
+
+    // Every entry, in no particular order
+    pub fn entries(&self) -> Vec<(Value, Value)> {
+        self.buckets.values().flat_map(|bucket| bucket.iter().cloned()).collect()
+    }
+}
+
+fn hash(key: &Value) -> u64 {
+    let mut state = SipHasher::new();
+    hash_value(key, HASH_DEPTH, &mut state);
+    state.finish()
+}
+
+fn hash_value(value: &Value, depth: usize, state: &mut SipHasher) {
+    if depth == 0 {
+        return
+    }
+    match *value {
+        // Numbers equal in any representation have the same floor
+        Value::Num(ref n) => {
+            0u8.hash(state);
+            (n.to_f64().floor() as i64).hash(state);
+        }
+        Value::Bool(b) => {
+            1u8.hash(state);
+            b.hash(state);
+        }
+        Value::Str(ref s) => {
+            2u8.hash(state);
+            s.hash(state);
+        }
+        Value::Symbol(s) => {
+            3u8.hash(state);
+            s.hash(state);
+        }
+        Value::Nil => 4u8.hash(state),
+        Value::Cons(ref cons) => {
+            5u8.hash(state);
+            hash_value(&cons.head, depth - 1, state);
+            let mut tail = &cons.tail;
+            while let Value::Cons(ref cons) = *tail {
+                hash_value(&cons.head, depth - 1, state);
+                tail = &cons.tail;
+            }
+            hash_value(tail, depth - 1, state);
+        }
+        Value::List(ref items) => {
+            6u8.hash(state);
+            for item in items.iter() {
+                hash_value(item, depth - 1, state);
+            }
+        }
+        Value::Tuple(ref tuple) => {
+            7u8.hash(state);
+            hash_value(&tuple.first, depth - 1, state);
+            hash_value(&tuple.second, depth - 1, state);
+        }
+        Value::Vector(ref items) => {
+            8u8.hash(state);
+            for item in items.borrow().iter() {
+                hash_value(item, depth - 1, state);
+            }
+        }
+        // Functions and the like are only equal to themselves, so all
+        // sharing a bucket is no great loss
+        _ => 9u8.hash(state),
+    }
+}
@@ -0,0 +1,435 @@
+use std::mem;
+use std::old_io::{self, Buffer, File, IoErrorKind, IoResult, Reader, StdinReader, Writer};
+use std::old_io::fs::{self, PathExtensions};
+use std::old_path::Path;
+use libc;
+
+// Lines of history kept, both in memory and in the history file
+const HISTORY_LIMIT: usize = 1000;
+
+// Reads lines from the terminal with Emacs style editing and a history
+// which is kept between sessions:
+//
+//   Left, Right, C-b, C-f   move by a character
+//   Home, End, C-a, C-e     move to the start or end of the line
+//   Backspace, Delete, C-d  delete before or under the cursor
+//   C-k, C-u, C-w           kill to the end, to the start, or the word before
+//   C-y                     yank what was last killed
+//   Up, Down, C-p, C-n      step through the history
+//   Tab                     complete the word before the cursor
+//   C-c                     discard the line
+//
+// When standard input isn't a terminal lines are read as they are.
+pub struct Editor {
+    history: Vec<String>,
+    file: Option<Path>,
+    killed: Vec<char>,
+    // Words offered as completions, set afresh before each line is read
+    words: Vec<String>,
+    terminal: bool,
+    stdin: StdinReader,
+}
+
+// The terminal's settings from before the editor changed them, restored
+// when dropped
+struct Raw {
+    saved: libc::termios,
+}
+
+impl Raw {
+    // Take keys as they're typed rather than a line at a time, without
+    // echoing them.  C-c is taken as a key rather than interrupting, while
+    // other signals such as C-z's still work.
+    fn enter() -> Option<Raw> {
+        unsafe {
+            let mut saved: libc::termios = mem::zeroed();
+            if libc::tcgetattr(0, &mut saved) != 0 {
+                return None
+            }
+            let mut raw = saved;
+            raw.c_lflag &= !(libc::ICANON | libc::ECHO);
+            raw.c_cc[libc::VMIN] = 1;
+            raw.c_cc[libc::VTIME] = 0;
+            raw.c_cc[libc::VINTR] = 0;
+            if libc::tcsetattr(0, libc::TCSANOW, &raw) != 0 {
+                return None
+            }
+            Some(Raw { saved: saved })
+        }
+    }
+}
+
+impl Drop for Raw {
+    fn drop(&mut self) {
+        unsafe {
+            libc::tcsetattr(0, libc::TCSANOW, &self.saved);
+        }
+    }
+}
+
+// A key read from the terminal
+enum Key {
+    Char(char),
+    Control(char),
+    Up,
+    Down,
+    Left,
+    Right,
+    Home,
+    End,
+    Delete,
+    Backspace,
+    Enter,
+    Unknown,
+}
+
+fn read_byte() -> Option<u8> {
+    let mut byte = 0u8;
+    let read = unsafe { libc::read(0, &mut byte as *mut u8 as *mut libc::c_void, 1) };
+    if read == 1 { Some(byte) } else { None }
+}
+
+// None once the input has ended
+fn read_key() -> Option<Key> {
+    let byte = match read_byte() {
+        Some(byte) => byte,
+        None => return None
+    };
+    Some(match byte {
+        b'\r' | b'\n' => Key::Enter,
+        127 | 8 => Key::Backspace,
+        27 => {
+            // Escape sequences for the arrow keys and the like, as
+            // ESC [ A or ESC O A, with ESC [ 3 ~ for delete
+            match read_byte() {
+                Some(b'[') | Some(b'O') => {}
+                _ => return Some(Key::Unknown)
+            }
+            match read_byte() {
+                Some(b'A') => Key::Up,
+                Some(b'B') => Key::Down,
+                Some(b'C') => Key::Right,
+                Some(b'D') => Key::Left,
+                Some(b'H') => Key::Home,
+                Some(b'F') => Key::End,
+                Some(digit @ b'0' ... b'9') => {
+                    let mut last = read_byte();
+                    while let Some(b'0' ... b'9') = last {
+                        last = read_byte();
+                    }
+                    match (digit, last) {
+                        (b'3', Some(b'~')) => Key::Delete,
+                        (b'1', Some(b'~')) | (b'7', Some(b'~')) => Key::Home,
+                        (b'4', Some(b'~')) | (b'8', Some(b'~')) => Key::End,
+                        _ => Key::Unknown
+                    }
+                }
+                _ => Key::Unknown
+            }
+        }
+        1 ... 26 => Key::Control((b'a' + byte - 1) as char),
+        0 ... 31 => Key::Unknown,
+        _ => {
+            // The rest of a character's UTF-8 encoding
+            let length = if byte >= 0xF0 { 4 } else if byte >= 0xE0 { 3 } else if byte >= 0xC0 { 2 } else { 1 };
+            let mut bytes = vec![byte];
+            for _ in 1..length {
+                match read_byte() {
+                    Some(byte) => bytes.push(byte),
+                    None => break
+                }
+            }
+            match String::from_utf8(bytes) {
+                Ok(s) => Key::Char(s.chars().next().unwrap()),
+                Err(_) => Key::Unknown
+            }
+        }
+    })
+}
+
+impl Editor {
+    // An editor keeping its history in the file, if any, loading what's
+    // there already
+    pub fn new(file: Option<Path>) -> Editor {
+        let mut history = Vec::new();
+        match file {
+            Some(ref path) if path.exists() => {
+                match File::open(path).and_then(|mut file| file.read_to_string()) {
+                    Ok(text) => history.extend(text.lines().filter(|line| !line.is_empty()).map(|line| line.to_string())),
+                    Err(_) => {}
+                }
+            }
+            _ => {}
+        }
+        let terminal = unsafe { libc::isatty(0) != 0 };
+        Editor {
+            history: history,
+            file: file,
+            killed: Vec::new(),
+            words: Vec::new(),
+            terminal: terminal,
+            stdin: old_io::stdin(),
+        }
+    }
+
+    // Whether lines are read from a terminal rather than a file or pipe
+    pub fn is_terminal(&self) -> bool {
+        self.terminal
+    }
+
+    // Words to complete with Tab, such as the names of the functions and
+    // globals defined
+    pub fn set_words(&mut self, words: Vec<String>) {
+        self.words = words;
+    }
+
+    // Read a line after showing the prompt, without its line ending.  None
+    // once the input has ended.
+    pub fn read_line(&mut self, prompt: &str) -> IoResult<Option<String>> {
+        let mut out = old_io::stdout();
+        try!(out.write_str(prompt));
+        try!(out.flush());
+        if !self.terminal {
+            return self.plain_line()
+        }
+        match Raw::enter() {
+            Some(raw) => {
+                let line = self.edit(prompt);
+                drop(raw);
+                try!(out.write_str("\n"));
+                line
+            }
+            None => self.plain_line()
+        }
+    }
+
+    fn edit(&mut self, prompt: &str) -> IoResult<Option<String>> {
+        let mut line: Vec<char> = Vec::new();
+        let mut cursor = 0;
+        // Position in the history, which is its length while editing a new
+        // line, and the new line put aside while looking through it
+        let mut index = self.history.len();
+        let mut draft: Vec<char> = Vec::new();
+        loop {
+            let key = match read_key() {
+                Some(key) => key,
+                None if line.is_empty() => return Ok(None),
+                None => return Ok(Some(line.into_iter().collect()))
+            };
+            match key {
+                Key::Enter => return Ok(Some(line.into_iter().collect())),
+                Key::Char(c) => {
+                    line.insert(cursor, c);
+                    cursor += 1;
+                }
+                Key::Control('d') if line.is_empty() => return Ok(None),
+                Key::Delete | Key::Control('d') => {
+                    if cursor < line.len() {
+                        line.remove(cursor);
+                    }
+                }
+                Key::Backspace | Key::Control('h') => {
+                    if cursor > 0 {
+                        cursor -= 1;
+                        line.remove(cursor);
+                    }
+                }
+                Key::Left | Key::Control('b') => {
+                    if cursor > 0 {
+                        cursor -= 1;
+                    }
+                }
+                Key::Right | Key::Control('f') => {
+                    if cursor < line.len() {
+                        cursor += 1;
+                    }
+                }
+                Key::Home | Key::Control('a') => cursor = 0,
+                Key::End | Key::Control('e') => cursor = line.len(),
+                Key::Control('k') => self.killed = line.split_off(cursor),
+                Key::Control('u') => {
+                    let rest = line.split_off(cursor);
+                    self.killed = mem::replace(&mut line, rest);
+                    cursor = 0;
+                }
+                Key::Control('w') => {
+                    let mut start = cursor;
+                    while start > 0 && line[start - 1].is_whitespace() {
+                        start -= 1;
+                    }
+                    while start > 0 && !line[start - 1].is_whitespace() {
+                        start -= 1;
+                    }
+                    let rest = line.split_off(cursor);
+                    self.killed = line.split_off(start);
+                    line.extend(rest.into_iter());
+                    cursor = start;
+                }
+                Key::Control('y') => {
+                    for (i, &c) in self.killed.iter().enumerate() {
+                        line.insert(cursor + i, c);
+                    }
+                    cursor += self.killed.len();
+                }
+                Key::Up | Key::Control('p') => {
+                    if index > 0 {
+                        if index == self.history.len() {
+                            draft = line.clone();
+                        }
+                        index -= 1;
+                        line = self.history[index].chars().collect();
+                        cursor = line.len();
+                    }
+                }
+                Key::Control('c') => {
+                    try!(old_io::stdout().write_str("^C\n"));
+                    line.clear();
+                    cursor = 0;
+                    index = self.history.len();
+                }
+                Key::Control('i') => try!(self.complete(&mut line, &mut cursor)),
+                Key::Down | Key::Control('n') => {
+                    if index < self.history.len() {
+                        index += 1;
+                        line = if index == self.history.len() {
+                            draft.clone()
+                        } else {
+                            self.history[index].chars().collect()
+                        };
+                        cursor = line.len();
+                    }
+                }
+                _ => {}
+            }
+            try!(redraw(prompt, &line, cursor));
+        }
+    }
+
+    // Complete the word before the cursor: the name of a file in the string
+    // of a (load "..."), or else one of the words set.  What every
+    // completion starts with is inserted, and if that adds nothing the
+    // completions are listed.
+    fn complete(&self, line: &mut Vec<char>, cursor: &mut usize) -> IoResult<()> {
+        let before: String = line[..*cursor].iter().cloned().collect();
+        let quotes = before.chars().filter(|&c| c == '"').count();
+        let (start, completions) = if quotes % 2 == 1 {
+            let open = before.rfind('"').unwrap();
+            if !before[..open].trim_right().ends_with("(load") {
+                return Ok(())
+            }
+            let start = before[..open].chars().count() + 1;
+            (start, file_completions(&before[open + 1..]))
+        } else {
+            let start = before.chars().rev().take_while(|&c| !is_delimiter(c)).count();
+            let start = *cursor - start;
+            let prefix: String = line[start..*cursor].iter().cloned().collect();
+            let mut words: Vec<String> = self.words.iter().filter(|word| word.starts_with(&*prefix)).cloned().collect();
+            words.sort();
+            words.dedup();
+            (start, words)
+        };
+        if completions.is_empty() {
+            return Ok(())
+        }
+        let typed = *cursor - start;
+        let shared = shared_prefix(&*completions);
+        if shared.len() > typed {
+            for (i, &c) in shared[typed..].iter().enumerate() {
+                line.insert(*cursor + i, c);
+            }
+            *cursor += shared.len() - typed;
+        } else if completions.len() > 1 {
+            let mut out = old_io::stdout();
+            try!(out.write_str(&*format!("\n{}\n", completions.connect("  "))));
+        }
+        Ok(())
+    }
+
+    fn plain_line(&mut self) -> IoResult<Option<String>> {
+        match self.stdin.read_line() {
+            Ok(mut line) => {
+                while line.ends_with("\n") || line.ends_with("\r") {
+                    line.pop();
+                }
+                Ok(Some(line))
+            }
+            Err(ref e) if e.kind == IoErrorKind::EndOfFile => Ok(None),
+            Err(e) => Err(e)
+        }
+    }
+
+    // Remember a line entered, saving the history to its file.  Blank lines,
+    // repeats of the line before and lines not typed at a terminal aren't
+    // kept.
+    pub fn add_history(&mut self, line: &str) {
+        if !self.terminal || line.trim().is_empty() || self.history.last().map(|last| &**last == line).unwrap_or(false) {
+            return
+        }
+        self.history.push(line.to_string());
+        if self.history.len() > HISTORY_LIMIT {
+            let excess = self.history.len() - HISTORY_LIMIT;
+            self.history = self.history.split_off(excess);
+        }
+        match self.file {
+            Some(ref path) => {
+                let mut text = self.history.connect("\n");
+                text.push('\n');
+                // History is a convenience, so failing to save it isn't
+                // worth interrupting the session for
+                let _ = File::create(path).and_then(|mut file| file.write_str(&*text));
+            }
+            None => {}
+        }
+    }
+}
+
+
+// Characters ending a word to complete
+fn is_delimiter(c: char) -> bool {
+    c.is_whitespace() || "()[]{}\"|".contains(c)
+}
+
+// The characters the strings all start with
+fn shared_prefix(strings: &[String]) -> Vec<char> {
+    let mut shared: Vec<char> = strings[0].chars().collect();
+    for string in strings[1..].iter() {
+        let same = shared.iter().zip(string.chars()).take_while(|&(&a, b)| a == b).count();
+        shared.truncate(same);
+    }
+    shared
+}
+
+// Names of the files and directories starting with the path, directories
+// ending with a slash
+fn file_completions(path: &str) -> Vec<String> {
+    let (directory, name) = match path.rfind('/') {
+        Some(slash) => (&path[..slash + 1], &path[slash + 1..]),
+        None => ("", path)
+    };
+    let entries = match fs::readdir(&Path::new(if directory.is_empty() { "." } else { directory })) {
+        Ok(entries) => entries,
+        Err(_) => return Vec::new()
+    };
+    let mut completions: Vec<String> = entries.iter().filter_map(|entry| {
+        entry.filename_str().and_then(|file| {
+            if !file.starts_with(name) || (file.starts_with(".") && !name.starts_with(".")) {
+                return None
+            }
+            let slash = if entry.is_dir() { "/" } else { "" };
+            Some(format!("{}{}{}", directory, file, slash))
+        })
+    }).collect();
+    completions.sort();
+    completions
+}
+
+// Redraw the line and put the cursor back where it was
+fn redraw(prompt: &str, line: &[char], cursor: usize) -> IoResult<()> {
+    let mut out = old_io::stdout();
+    let text: String = line.iter().cloned().collect();
+    try!(out.write_str(&*format!("\r{}{}\x1b[K", prompt, text)));
+    if cursor < line.len() {
+        try!(out.write_str(&*format!("\x1b[{}D", line.len() - cursor)));
+    }
+    out.flush()
+}
@@ -0,0 +1,1287 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::rc::Rc;
+
+use symbol::{self, SymbolId};
+use define;
+use interrupt;
+use primitives;
+use stdlib;
+use profile::Profile;
+use prolog;
+use random::{self, Random};
+use stats::{self, Stats};
+use types::{self, Rule};
+use yacc;
+use time;
+use value::{Closure, Env, Frozen, Partial, Value};
+
+// Errors are ordinary results rather than panics, so that trap-error can
+// recover from any of them
+#[derive(Clone)]
+pub enum EvalError {
+    // Raised by simple-error with the message given
+    Simple(String),
+    // A symbol in function position which isn't bound to a function
+    UndefinedFunction(SymbolId),
+    // Something other than a function was applied
+    NotAFunction(Value),
+    // A special form without the parts it requires
+    Malformed(SymbolId, Value),
+    // A condition of the form evaluated to something other than a boolean
+    NotABoolean(SymbolId, Value),
+    // No clause of a cond had a true condition
+    CondFailure,
+    // value called on a symbol which was never set
+    Unbound(SymbolId),
+    // A primitive given an argument of the wrong type, with what it expected
+    WrongType(SymbolId, &'static str, Value),
+    // Evaluation went beyond one of the interpreter's limits, given with
+    // the limit's value
+    LimitExceeded(Limit, u64),
+    // Code rejected by the type checker, before being evaluated
+    TypeError(String),
+    // shift evaluated without a reset around it
+    NoReset,
+    // (exit Status) ending the program, passed up to whatever runs it
+    Exit(i32),
+    // The evaluation was given up while stepping through it
+    Abandoned,
+    // C-c was pressed during the evaluation
+    Interrupted,
+    // An error along with the functions it was raised through, innermost
+    // first
+    Traced(Box<EvalError>, Vec<SymbolId>),
+    // An error in code read from a source, given with where in the source
+    // it was, as file:line:column, and an excerpt of the source there
+    Located(String, String),
+}
+
+impl EvalError {
+    // The error as raised through the function too
+    pub fn through(self, name: SymbolId) -> EvalError {
+        match self {
+            EvalError::Traced(e, mut trace) => {
+                trace.push(name);
+                EvalError::Traced(e, trace)
+            }
+            e => EvalError::Traced(Box::new(e), vec![name])
+        }
+    }
+
+    // The error itself, without the functions it was raised through
+    pub fn cause(&self) -> &EvalError {
+        match *self {
+            EvalError::Traced(ref e, _) => &**e,
+            ref e => e
+        }
+    }
+
+    // The functions the error was raised through, innermost first.  Calls
+    // in tail position have been replaced by the function called, so only
+    // those still waiting on a result appear.
+    pub fn trace(&self) -> &[SymbolId] {
+        match *self {
+            EvalError::Traced(_, ref trace) => &**trace,
+            _ => &[]
+        }
+    }
+
+    // The excerpt of the source the error was in, if it was located there
+    pub fn excerpt(&self) -> Option<&str> {
+        match *self.cause() {
+            EvalError::Located(_, ref excerpt) => Some(&**excerpt),
+            _ => None
+        }
+    }
+}
+
+impl fmt::Display for EvalError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            EvalError::Simple(ref message) => f.write_str(&**message),
+            EvalError::UndefinedFunction(name) => write!(f, "{} is undefined", name),
+            EvalError::NotAFunction(ref value) => write!(f, "{} is not a function", value),
+            EvalError::Malformed(form, ref expr) => write!(f, "Malformed {}: {}", form, expr),
+            EvalError::NotABoolean(form, ref value) => write!(f, "{}: boolean expected, not {}", form, value),
+            EvalError::CondFailure => f.write_str("cond failure: no default"),
+            EvalError::Unbound(name) => write!(f, "variable {} has no value", name),
+            EvalError::WrongType(function, expected, ref value) => {
+                write!(f, "{}: {} is not {}", function, value, expected)
+            }
+            EvalError::TypeError(ref message) => f.write_str(&**message),
+            EvalError::LimitExceeded(Limit::Depth, depth) => write!(f, "evaluation nested deeper than {}", depth),
+            EvalError::LimitExceeded(Limit::Steps, steps) => write!(f, "evaluation took more than {} steps", steps),
+            EvalError::LimitExceeded(Limit::Nesting, nesting) => {
+                write!(f, "evaluations nested more than {} deep within primitives", nesting)
+            }
+            EvalError::NoReset => f.write_str("shift outside of any reset"),
+            EvalError::Exit(status) => write!(f, "exit {}", status),
+            EvalError::Abandoned => f.write_str("evaluation abandoned"),
+            EvalError::Interrupted => f.write_str("interrupted"),
+            EvalError::Traced(ref e, _) => fmt::Display::fmt(&**e, f),
+            EvalError::Located(ref message, _) => f.write_str(&**message),
+        }
+    }
+}
+
+impl fmt::Debug for EvalError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
+
+// The bounds put on an evaluation, so that untrusted code can be run
+// without it running forever
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Limit {
+    // Frames on the evaluation stack, which non-tail calls add to
+    Depth,
+    // Expressions evaluated by one evaluation from the top level
+    Steps,
+    // Evaluations made by primitives within evaluations, each of which uses
+    // Rust's stack
+    Nesting,
+}
+
+// A function defined at the top level with defun
+pub struct Defun {
+    pub name: SymbolId,
+    pub params: Vec<SymbolId>,
+    pub body: Value,
+}
+
+// The next thing for the evaluator to do
+enum Step {
+    // Return the value to the innermost frame
+    Done(Value),
+    // Evaluate the expression in place of the current one
+    Eval(Value, Env),
+    // Evaluate the expression, then resume the frame with its value
+    Push(Frame, Value, Env),
+    // Capture the frames up to the innermost reset as a continuation, and
+    // evaluate the body with the variable bound to it
+    Shift(SymbolId, Value, Env),
+    // Continue the captured frames with the value, applying the result to
+    // any extra arguments
+    Resume(Rc<Continuation>, Value, Vec<Value>),
+    // Evaluate the body of the named function, applying the result to any
+    // extra arguments
+    Call(SymbolId, Value, Env, Vec<Value>),
+}
+
+// Work suspended while a subexpression is evaluated, kept on a stack on the
+// heap rather than in Rust's own
+#[derive(Clone)]
+enum Frame {
+    // Evaluating the arguments of an application, those done so far held
+    Args(Rc<Vec<Value>>, Env, Vec<Value>),
+    // Apply the value to the arguments
+    Apply(Vec<Value>),
+    // Choose the branch of (if C T E) by its condition
+    If(Rc<Vec<Value>>, Env),
+    // The condition of the cond clause at the index
+    Cond(Rc<Vec<Value>>, Env, usize),
+    // The condition of an and or an or at the index
+    Logic(Rc<Vec<Value>>, Env, usize),
+    // The value of the let binding at the index, with the scope so far
+    Let(Rc<Vec<Value>>, Env, usize),
+    // The handler of a trap-error, applied to any error raised beneath it
+    Trap(Value, Env),
+    // The expression of a do at the index, whose value is discarded
+    Do(Rc<Vec<Value>>, Env, usize),
+    // The delimiter of the continuations captured by shift
+    Reset,
+    // The body of the named function, there for the sake of stack traces
+    Body(SymbolId),
+    // A call of a tracked function, at the depth given, whose value is
+    // shown when it returns
+    Tracked(SymbolId, usize),
+    // The body of an unwind-protect, whose cleanup forms follow it
+    Protect(Rc<Vec<Value>>, Env),
+    // The cleanup form at the index, with the outcome of the body to give
+    // once every cleanup form is done
+    Cleanup(Rc<Vec<Value>>, Env, usize, Result<Value, EvalError>),
+}
+
+// The rest of the evaluation up to a reset, captured by shift, which when
+// applied to a value continues from where shift was evaluated
+pub struct Continuation {
+    frames: Vec<Frame>,
+}
+
+// Where stepping has paused: the expression about to be evaluated, the
+// variables bound there, and the functions called to get there, innermost
+// first.  On entering a function with a breakpoint the expression is its
+// body, and the variables its arguments.
+pub struct Pause<'a> {
+    pub expr: &'a Value,
+    pub env: &'a Env,
+    pub calls: Vec<SymbolId>,
+    pub breakpoint: bool,
+}
+
+// How to go on from a pause
+pub enum Resumption {
+    // Pause at the next application evaluated
+    Into,
+    // Pause at the next application once the one paused at is evaluated
+    Over,
+    // Evaluate the rest without pausing
+    Continue,
+    // Give up the evaluation
+    Abandon,
+}
+
+// Decides how to go on each time stepping pauses, as the REPL's debugger
+// does by asking
+pub trait Stepper {
+    fn pause(&mut self, pause: &Pause) -> Resumption;
+}
+
+// Where stepping next pauses, at the next application, at the next no
+// deeper than the evaluation and stack depth given, or at the body of a
+// function with a breakpoint just entered
+enum Stepping {
+    Into,
+    Over(usize, usize),
+    Break,
+}
+
+// Depth of the evaluation stack allowed by default, enough for deep
+// non-tail recursion while catching runaway recursion long before memory
+// runs out
+pub const DEFAULT_MAX_DEPTH: usize = 1000000;
+
+// Most evaluations which may be under way at once, as when eval-kl evaluates
+// code calling eval-kl.  Each is run on Rust's stack rather than the
+// evaluation stack, so their nesting is bounded to keep that from
+// overflowing.
+const MAX_NESTING: usize = 100;
+
+// (cases Test1 Result1 ... TestN ResultN) as nested ifs, the last raising
+// an error when every test fails
+pub fn cases(expr: &Value, items: &[Value]) -> Result<Value, EvalError> {
+    if items.len() < 3 || items.len() % 2 == 0 {
+        return Err(EvalError::Malformed(symbol::CASES, expr.clone()))
+    }
+    let exhausted = vec![Value::Symbol(symbol::intern("simple-error")),
+                         Value::Str(Rc::new("error: cases exhausted".to_string()))];
+    let mut result = Value::list(exhausted);
+    for case in items[1..].chunks(2).rev() {
+        result = Value::list(vec![Value::Symbol(symbol::IF), case[0].clone(), case[1].clone(), result]);
+    }
+    Ok(result)
+}
+
+// A function implemented in Rust, given exactly as many arguments as it takes
+pub type Primitive = fn(&mut Interpreter, Vec<Value>) -> Result<Value, EvalError>;
+
+pub enum Function {
+    Defined(Defun),
+    Primitive(usize, Primitive),
+}
+
+impl Function {
+    pub fn arity(&self) -> usize {
+        match *self {
+            Function::Defined(ref defun) => defun.params.len(),
+            Function::Primitive(arity, _) => arity,
+        }
+    }
+}
+
+// Global state shared by everything evaluated in a session
+pub struct Interpreter {
+    functions: HashMap<SymbolId, Rc<Function>>,
+    // Global variables assigned with set
+    globals: HashMap<SymbolId, Value>,
+    // Functions defined by the system itself, whose names packages leave be
+    system: HashSet<SymbolId>,
+    // Type signatures of functions, as the items written between braces
+    signatures: HashMap<SymbolId, Value>,
+    // Rules of the types declared with datatype, in the order declared, with
+    // the datatype forms they were read from
+    datatypes: Vec<(SymbolId, Value, Rc<Vec<Rule>>)>,
+    // Clauses of the predicates defined with defprolog
+    predicates: HashMap<SymbolId, Rc<Vec<prolog::Clause>>>,
+    // Whether top level code is type checked, switched by tc
+    typecheck: bool,
+    // Property lists stored with put, keyed by symbol and then by pointer
+    properties: HashMap<(SymbolId, SymbolId), Value>,
+    // Last number used by gensym
+    gensym_counter: u64,
+    // Source of the numbers given by random
+    random: Random,
+    // Seconds on the precise timer when the interpreter was made, which
+    // run time is measured from
+    started: f64,
+    // Most frames the evaluation stack may hold
+    max_depth: usize,
+    // Most expressions one evaluation may evaluate, if limited
+    max_steps: Option<u64>,
+    // Expressions evaluated so far by the current evaluation
+    steps: u64,
+    // Expressions evaluated since the interpreter was made
+    steps_taken: u64,
+    // Evaluations under way, more than one when a primitive evaluates
+    running: usize,
+    // Functions whose calls and returns are shown, switched by track and
+    // untrack
+    tracked: HashSet<SymbolId>,
+    // Calls of tracked functions that haven't yet returned
+    track_depth: usize,
+    // What's asked how to go on when stepping pauses
+    stepper: Option<Box<Stepper>>,
+    // Where stepping next pauses, if stepping
+    stepping: Option<Stepping>,
+    // Whether evaluations are stepped through from the start, rather than
+    // only from a breakpoint
+    step_all: bool,
+    // Functions whose calls pause as stepping does, set by break
+    breakpoints: HashSet<SymbolId>,
+    // Calls and time spent in each function, while profiling
+    profile: Option<Profile>,
+}
+
+// (lambda X Body) makes a closure over X.  (/. X Y Body) takes several
+// parameters, being shorthand for (lambda X (lambda Y Body)).
+fn lambda(form: SymbolId, expr: &Value, items: &[Value], env: &Env) -> Result<Value, EvalError> {
+    if items.len() < 3 || (form == symbol::LAMBDA && items.len() != 3) {
+        return Err(EvalError::Malformed(form, expr.clone()))
+    }
+    let param = match items[1] {
+        Value::Symbol(param) => param,
+        _ => return Err(EvalError::Malformed(form, expr.clone()))
+    };
+    let body = if items.len() == 3 {
+        items[2].clone()
+    } else {
+        let mut rest = vec![Value::Symbol(symbol::SLASH_DOT)];
+        rest.extend(items[2..].iter().map(|item| item.clone()));
+        Value::list(rest)
+    };
+    Ok(Value::Closure(Rc::new(Closure { param: param, body: body, env: env.clone() })))
+}
+
+// (defun Name (Params...) Body), which must take the parameters as symbols
+fn defun(expr: &Value, items: &[Value]) -> Result<Defun, EvalError> {
+    let malformed = EvalError::Malformed(symbol::DEFUN, expr.clone());
+    if items.len() != 4 {
+        return Err(malformed)
+    }
+    let name = match items[1] {
+        Value::Symbol(name) => name,
+        _ => return Err(malformed)
+    };
+    let mut params = Vec::new();
+    match items[2] {
+        Value::Nil => {}
+        Value::List(ref list) => {
+            for param in list.iter() {
+                match *param {
+                    Value::Symbol(param) => params.push(param),
+                    _ => return Err(malformed)
+                }
+            }
+        }
+        _ => return Err(malformed)
+    }
+    Ok(Defun { name: name, params: params, body: items[3].clone() })
+}
+
+impl Interpreter {
+    pub fn new() -> Interpreter {
+        let mut interpreter = Interpreter {
+            functions: HashMap::new(),
+            globals: HashMap::new(),
+            system: HashSet::new(),
+            signatures: HashMap::new(),
+            datatypes: Vec::new(),
+            predicates: HashMap::new(),
+            typecheck: false,
+            properties: HashMap::new(),
+            gensym_counter: 0,
+            random: Random::new(random::DEFAULT_SEED),
+            started: time::precise_time_s(),
+            max_depth: DEFAULT_MAX_DEPTH,
+            max_steps: None,
+            steps: 0,
+            steps_taken: 0,
+            running: 0,
+            tracked: HashSet::new(),
+            track_depth: 0,
+            stepper: None,
+            stepping: None,
+            step_all: false,
+            breakpoints: HashSet::new(),
+            profile: None,
+        };
+        primitives::install(&mut interpreter);
+        types::install(&mut interpreter);
+        stdlib::load(&mut interpreter);
+        interpreter.system = interpreter.functions.keys().map(|name| *name).collect();
+        interpreter
+    }
+
+    pub fn function(&self, name: SymbolId) -> Option<Rc<Function>> {
+        self.functions.get(&name).map(|function| function.clone())
+    }
+
+    pub fn is_system(&self, name: SymbolId) -> bool {
+        self.system.contains(&name)
+    }
+
+    // Names of every function, built in or defined
+    pub fn function_names(&self) -> Vec<SymbolId> {
+        self.functions.keys().cloned().collect()
+    }
+
+    // The functions defined since the interpreter was made, by name
+    pub fn defined_functions(&self) -> Vec<(SymbolId, Rc<Function>)> {
+        let mut functions: Vec<(SymbolId, Rc<Function>)> = self.functions.iter()
+            .filter(|&(name, _)| !self.system.contains(name))
+            .map(|(name, function)| (*name, function.clone()))
+            .collect();
+        functions.sort_by(|a, b| a.0.to_string().cmp(&b.0.to_string()));
+        functions
+    }
+
+    // Every global with a value, by name
+    pub fn globals(&self) -> Vec<(SymbolId, Value)> {
+        let mut globals: Vec<(SymbolId, Value)> = self.globals.iter()
+            .map(|(name, value)| (*name, value.clone()))
+            .collect();
+        globals.sort_by(|a, b| a.0.to_string().cmp(&b.0.to_string()));
+        globals
+    }
+
+    pub fn global(&self, name: SymbolId) -> Result<Value, EvalError> {
+        match self.globals.get(&name) {
+            Some(value) => Ok(value.clone()),
+            None => Err(EvalError::Unbound(name))
+        }
+    }
+
+    pub fn set_global(&mut self, name: SymbolId, value: Value) {
+        self.globals.insert(name, value);
+    }
+
+    pub fn signature(&self, name: SymbolId) -> Option<Value> {
+        self.signatures.get(&name).map(|signature| signature.clone())
+    }
+
+    pub fn declare(&mut self, name: SymbolId, signature: Value) {
+        self.signatures.insert(name, signature);
+    }
+
+    // Declaring a datatype again replaces its rules
+    pub fn define_datatype(&mut self, name: SymbolId, form: Value, rules: Vec<Rule>) {
+        self.datatypes.retain(|&(other, _, _)| other != name);
+        self.datatypes.push((name, form, Rc::new(rules)));
+    }
+
+    pub fn datatypes(&self) -> Vec<Rc<Vec<Rule>>> {
+        self.datatypes.iter().map(|&(_, _, ref rules)| rules.clone()).collect()
+    }
+
+    // The forms declaring the datatypes, in the order declared
+    pub fn datatype_forms(&self) -> Vec<Value> {
+        self.datatypes.iter().map(|&(_, ref form, _)| form.clone()).collect()
+    }
+
+    // The signatures declared since the interpreter was made, by name
+    pub fn declared(&self) -> Vec<(SymbolId, Value)> {
+        let mut signatures: Vec<(SymbolId, Value)> = self.signatures.iter()
+            .filter(|&(name, _)| !self.system.contains(name))
+            .map(|(name, signature)| (*name, signature.clone()))
+            .collect();
+        signatures.sort_by(|a, b| a.0.to_string().cmp(&b.0.to_string()));
+        signatures
+    }
+
+    pub fn predicate(&self, name: SymbolId) -> Option<Rc<Vec<prolog::Clause>>> {
+        self.predicates.get(&name).map(|clauses| clauses.clone())
+    }
+
+    pub fn typechecking(&self) -> bool {
+        self.typecheck
+    }
+
+    pub fn set_typechecking(&mut self, typecheck: bool) {
+        self.typecheck = typecheck;
+    }
+
+    // Every property stored with put, as name, pointer and value
+    pub fn properties(&self) -> Vec<(SymbolId, SymbolId, Value)> {
+        let mut properties: Vec<(SymbolId, SymbolId, Value)> = self.properties.iter()
+            .map(|(&(name, pointer), value)| (name, pointer, value.clone()))
+            .collect();
+        properties.sort_by(|a, b| (a.0.to_string(), a.1.to_string()).cmp(&(b.0.to_string(), b.1.to_string())));
+        properties
+    }
+
+    pub fn property(&self, name: SymbolId, pointer: SymbolId) -> Option<Value> {
+        self.properties.get(&(name, pointer)).map(|value| value.clone())
+    }
+
+    pub fn put_property(&mut self, name: SymbolId, pointer: SymbolId, value: Value) {
+        self.properties.insert((name, pointer), value);
+    }
+
+    pub fn remove_property(&mut self, name: SymbolId, pointer: SymbolId) -> Option<Value> {
+        self.properties.remove(&(name, pointer))
+    }
+
+    pub fn set_max_depth(&mut self, depth: usize) {
+        self.max_depth = depth;
+    }
+
+    // Limit the steps each evaluation may take, or with None let them run
+    // for as long as they take
+    pub fn set_max_steps(&mut self, steps: Option<u64>) {
+        self.max_steps = steps;
+    }
+
+    // Fail once the stack would grow beyond the depth limit
+    fn check_depth(&self, depth: usize) -> Result<(), EvalError> {
+        if depth > self.max_depth {
+            return Err(EvalError::LimitExceeded(Limit::Depth, self.max_depth as u64))
+        }
+        Ok(())
+    }
+
+    // Count a step, failing once there have been more than the limit or if
+    // C-c has been pressed
+    fn tick(&mut self) -> Result<(), EvalError> {
+        self.steps += 1;
+        self.steps_taken += 1;
+        if interrupt::take() {
+            return Err(EvalError::Interrupted)
+        }
+        match self.max_steps {
+            Some(max) if self.steps > max => Err(EvalError::LimitExceeded(Limit::Steps, max)),
+            _ => Ok(())
+        }
+    }
+
+    // Expressions evaluated by all evaluations so far, for measuring how
+    // much work code does
+    pub fn steps_taken(&self) -> u64 {
+        self.steps_taken
+    }
+
+    // Show the arguments of each call of a defined function and the value
+    // it returns, giving false if there's no such function
+    pub fn track(&mut self, name: SymbolId) -> bool {
+        match self.function(name).as_ref().map(|function| &**function) {
+            Some(&Function::Defined(_)) => {
+                self.tracked.insert(name);
+                true
+            }
+            _ => false
+        }
+    }
+
+    pub fn untrack(&mut self, name: SymbolId) {
+        self.tracked.remove(&name);
+    }
+
+    pub fn set_stepper(&mut self, stepper: Box<Stepper>) {
+        self.stepper = Some(stepper);
+    }
+
+    // Start or stop pausing before applications, in the bodies of tracked
+    // functions or anywhere if none are tracked, to ask the stepper how to
+    // go on
+    pub fn set_stepping(&mut self, on: bool) {
+        self.step_all = on;
+        self.stepping = if on { Some(Stepping::Into) } else { None };
+    }
+
+    // Pause on entering the defined function, as stepping does, giving
+    // false if there's no such function
+    pub fn set_breakpoint(&mut self, name: SymbolId) -> bool {
+        match self.function(name).as_ref().map(|function| &**function) {
+            Some(&Function::Defined(_)) => {
+                self.breakpoints.insert(name);
+                true
+            }
+            _ => false
+        }
+    }
+
+    pub fn clear_breakpoint(&mut self, name: SymbolId) {
+        self.breakpoints.remove(&name);
+    }
+
+    // While stepping, pause before the expression if it's where stepping
+    // next pauses
+    fn check_pause(&mut self, stack: &[Frame], expr: &Value, env: &Env) -> Result<(), EvalError> {
+        let due = match self.stepping {
+            None => return Ok(()),
+            Some(Stepping::Break) => return self.pause(stack, expr, env, true),
+            Some(Stepping::Into) => true,
+            Some(Stepping::Over(running, depth)) => {
+                self.running < running || (self.running == running && stack.len() <= depth)
+            }
+        };
+        let application = match *expr {
+            Value::List(_) => true,
+            _ => false
+        };
+        if !due || !application {
+            return Ok(())
+        }
+        let innermost = stack.iter().rev().filter_map(|frame| match *frame {
+            Frame::Body(name) => Some(name),
+            _ => None
+        }).next();
+        if !self.tracked.is_empty() && !innermost.map_or(false, |name| self.tracked.contains(&name)) {
+            return Ok(())
+        }
+        self.pause(stack, expr, env, false)
+    }
+
+    // Ask the stepper how to go on from the expression
+    fn pause(&mut self, stack: &[Frame], expr: &Value, env: &Env, breakpoint: bool) -> Result<(), EvalError> {
+        let calls: Vec<SymbolId> = stack.iter().rev().filter_map(|frame| match *frame {
+            Frame::Body(name) => Some(name),
+            _ => None
+        }).collect();
+        // Without a stepper there's nothing to pause for
+        let mut stepper = match self.stepper.take() {
+            Some(stepper) => stepper,
+            None => {
+                self.stepping = None;
+                return Ok(())
+            }
+        };
+        let resumption = stepper.pause(&Pause { expr: expr, env: env, calls: calls, breakpoint: breakpoint });
+        self.stepper = Some(stepper);
+        self.stepping = match resumption {
+            Resumption::Into => Some(Stepping::Into),
+            Resumption::Over => Some(Stepping::Over(self.running, stack.len())),
+            Resumption::Continue => None,
+            Resumption::Abandon => {
+                self.stepping = None;
+                return Err(EvalError::Abandoned)
+            }
+        };
+        Ok(())
+    }
+
+    // Count the calls of each function and the time spent in them, until
+    // the profile is taken
+    pub fn start_profiling(&mut self) {
+        self.profile = Some(Profile::new());
+    }
+
+    pub fn take_profile(&mut self) -> Option<Profile> {
+        self.profile.take()
+    }
+
+    // What the session is holding on to
+    pub fn stats(&self) -> Stats {
+        let mut roots: Vec<Value> = Vec::new();
+        for function in self.functions.values() {
+            match **function {
+                Function::Defined(ref defun) => roots.push(defun.body.clone()),
+                Function::Primitive(_, _) => {}
+            }
+        }
+        roots.extend(self.globals.values().cloned());
+        roots.extend(self.signatures.values().cloned());
+        roots.extend(self.properties.values().cloned());
+        let (values, bytes) = stats::measure(roots);
+        Stats {
+            symbols: symbol::interned(),
+            functions: self.functions.len(),
+            globals: self.globals.len(),
+            properties: self.properties.len(),
+            values: values,
+            bytes: bytes,
+        }
+    }
+
+    // A symbol distinct from any read or made so far
+    pub fn gensym(&mut self, prefix: &str) -> SymbolId {
+        symbol::fresh(prefix, &mut self.gensym_counter)
+    }
+
+    // Seconds since the interpreter was made
+    pub fn run_time(&self) -> f64 {
+        time::precise_time_s() - self.started
+    }
+
+    pub fn random(&mut self) -> &mut Random {
+        &mut self.random
+    }
+
+    // Restart the random numbers from a seed, to have them repeat
+    pub fn seed_random(&mut self, seed: u64) {
+        self.random = Random::new(seed);
+    }
+
+    pub fn define_primitive(&mut self, name: &str, arity: usize, primitive: Primitive) {
+        self.functions.insert(symbol::intern(name), Rc::new(Function::Primitive(arity, primitive)));
+    }
+
+    pub fn eval(&mut self, expr: &Value, env: &Env) -> Result<Value, EvalError> {
+        self.run(Step::Eval(expr.clone(), env.clone()))
+    }
+
+    // Subexpressions are evaluated with the work remaining kept as frames on
+    // a stack, so that neither nesting nor non-tail recursion uses Rust's
+    // stack.  Expressions in tail position replace the one being evaluated
+    // instead of pushing a frame, so tail recursive loops run in constant
+    // space.
+    fn run(&mut self, step: Step) -> Result<Value, EvalError> {
+        try!(self.enter());
+        let result = self.run_stack(step);
+        self.leave();
+        result
+    }
+
+    // Begin an evaluation, to be ended with leave.  Steps are counted from
+    // the outermost evaluation, taking in any evaluations made within it.
+    pub fn enter(&mut self) -> Result<(), EvalError> {
+        if self.running == 0 {
+            self.steps = 0;
+            self.track_depth = 0;
+            // Stepping from a breakpoint lasts only for that evaluation
+            if !self.step_all {
+                self.stepping = None;
+            }
+        }
+        if self.running >= MAX_NESTING {
+            return Err(EvalError::LimitExceeded(Limit::Nesting, MAX_NESTING as u64))
+        }
+        self.running += 1;
+        Ok(())
+    }
+
+    pub fn leave(&mut self) {
+        self.running -= 1;
+    }
+
+    fn run_stack(&mut self, step: Step) -> Result<Value, EvalError> {
+        let mut stack: Vec<Frame> = Vec::new();
+        let mut step = step;
+        loop {
+            let next = match step {
+                Step::Done(value) => {
+                    match stack.pop() {
+                        Some(frame) => self.resume(frame, value),
+                        None => return Ok(value)
+                    }
+                }
+                Step::Eval(expr, env) => {
+                    self.check_pause(&*stack, &expr, &env)
+                        .and_then(|_| self.tick())
+                        .and_then(|_| self.step(&expr, &env))
+                }
+                Step::Push(frame, expr, env) => {
+                    self.check_depth(stack.len() + 1).map(|_| {
+                        stack.push(frame);
+                        Step::Eval(expr, env)
+                    })
+                }
+                Step::Shift(name, body, env) => {
+                    match stack.iter().rposition(|frame| match *frame { Frame::Reset => true, _ => false }) {
+                        Some(reset) => {
+                            let frames = stack.split_off(reset + 1);
+                            let k = Value::Continuation(Rc::new(Continuation { frames: frames }));
+                            Ok(Step::Eval(body, env.bind(name, k)))
+                        }
+                        None => Err(EvalError::NoReset)
+                    }
+                }
+                // A call in tail position replaces its caller's body, so the
+                // stack doesn't grow with tail recursion.  Calls of tracked
+                // functions are kept to show what they return.
+                Step::Call(name, body, env, extra) => {
+                    if !extra.is_empty() {
+                        stack.push(Frame::Apply(extra));
+                    }
+                    let tracked = self.tracked.contains(&name);
+                    match stack.last() {
+                        Some(&Frame::Body(_)) if !tracked => {
+                            stack.pop();
+                            self.profile.as_mut().map(|profile| profile.leave());
+                        }
+                        _ => {}
+                    }
+                    if self.breakpoints.contains(&name) {
+                        self.stepping = Some(Stepping::Break);
+                    }
+                    self.check_depth(stack.len() + 2).map(|_| {
+                        if tracked {
+                            stack.push(Frame::Tracked(name, self.track_depth));
+                        }
+                        stack.push(Frame::Body(name));
+                        let (running, frames) = (self.running, stack.len());
+                        self.profile.as_mut().map(|profile| profile.enter(name, running, frames));
+                        Step::Eval(body, env)
+                    })
+                }
+                // The continuation is delimited by a reset of its own, so
+                // that it returns to where it was applied
+                Step::Resume(k, value, extra) => {
+                    self.check_depth(stack.len() + k.frames.len() + 2).map(|_| {
+                        if !extra.is_empty() {
+                            stack.push(Frame::Apply(extra));
+                        }
+                        stack.push(Frame::Reset);
+                        stack.extend(k.frames.iter().cloned());
+                        Step::Done(value)
+                    })
+                }
+            };
+            step = match next {
+                Ok(next) => next,
+                Err(e) => {
+                    let unwound = unwind(&mut stack, e, &mut self.track_depth);
+                    let (running, frames) = (self.running, stack.len());
+                    self.profile.as_mut().map(|profile| profile.unwind(running, frames));
+                    try!(unwound)
+                }
+            };
+        }
+    }
+
+    // Continue the suspended work with the value of its subexpression
+    fn resume(&mut self, frame: Frame, value: Value) -> Result<Step, EvalError> {
+        match frame {
+            Frame::Args(items, env, mut args) => {
+                args.push(value);
+                self.application(items, env, args)
+            }
+            Frame::Apply(args) => self.apply_step(value, args),
+            Frame::If(items, env) => {
+                let branch = if try!(condition(&items, value)) { &items[2] } else { &items[3] };
+                Ok(Step::Eval(branch.clone(), env))
+            }
+            Frame::Cond(items, env, i) => {
+                match items[i] {
+                    Value::List(ref clause) if try!(condition(&items, value)) => {
+                        return Ok(Step::Eval(clause[1].clone(), env))
+                    }
+                    _ => {}
+                }
+                cond(items, env, i + 1)
+            }
+            Frame::Logic(items, env, i) => {
+                let decisive = items[0] == Value::Symbol(symbol::OR);
+                if try!(condition(&items, value)) == decisive {
+                    return Ok(Step::Done(Value::Bool(decisive)))
+                }
+                if i + 1 == items.len() {
+                    return Ok(Step::Done(Value::Bool(!decisive)))
+                }
+                let next = items[i + 1].clone();
+                Ok(Step::Push(Frame::Logic(items, env.clone(), i + 1), next, env))
+            }
+            Frame::Let(items, scope, i) => {
+                let scope = match items[i] {
+                    Value::Symbol(name) => scope.bind(name, value),
+                    _ => unreachable!()
+                };
+                if i + 2 < items.len() - 1 {
+                    let next = items[i + 3].clone();
+                    return Ok(Step::Push(Frame::Let(items, scope.clone(), i + 2), next, scope))
+                }
+                Ok(Step::Eval(items[items.len() - 1].clone(), scope))
+            }
+            Frame::Trap(_, _) | Frame::Reset => Ok(Step::Done(value)),
+            Frame::Body(_) => {
+                self.profile.as_mut().map(|profile| profile.leave());
+                Ok(Step::Done(value))
+            }
+            Frame::Tracked(name, depth) => {
+                show_tracked(depth, &*format!("<{}> Output from {}", depth, name));
+                show_tracked(depth, &*format!("==> {}", value));
+                self.track_depth = depth - 1;
+                Ok(Step::Done(value))
+            }
+            Frame::Do(items, env, i) => sequence(items, env, i + 1),
+            Frame::Protect(items, env) => cleanup(items, env, 2, Ok(value)),
+            Frame::Cleanup(items, env, i, outcome) => cleanup(items, env, i + 1, outcome),
+        }
+    }
+
+    // Evaluate the next argument of an application, or once all are done
+    // apply the function to them
+    fn application(&mut self, items: Rc<Vec<Value>>, env: Env, args: Vec<Value>) -> Result<Step, EvalError> {
+        if args.len() + 1 < items.len() {
+            let next = items[args.len() + 1].clone();
+            return Ok(Step::Push(Frame::Args(items, env.clone(), args), next, env))
+        }
+        // A local binding of the name takes precedence over a global function
+        match items[0] {
+            Value::Symbol(name) => {
+                match env.lookup(name) {
+                    Some(value) => self.apply_step(value.clone(), args),
+                    None => self.call_step(name, args)
+                }
+            }
+            ref head => Ok(Step::Push(Frame::Apply(args), head.clone(), env))
+        }
+    }
+
+    // Evaluate an expression as far as its first subexpression
+    fn step(&mut self, expr: &Value, env: &Env) -> Result<Step, EvalError> {
+        let items = match *expr {
+            // Symbols evaluate to themselves unless bound locally, except <>
+            // which makes a new empty vector
+            Value::Symbol(name) => {
+                return Ok(Step::Done(match env.lookup(name) {
+                    Some(value) => value.clone(),
+                    None if name == symbol::EMPTY_VECTOR => Value::vector(Vec::new()),
+                    None => expr.clone()
+                }))
+            }
+            Value::List(ref items) if items.len() > 0 => items,
+            _ => return Ok(Step::Done(expr.clone()))
+        };
+
+        match items[0] {
+            Value::Symbol(form) if form == symbol::LAMBDA || form == symbol::SLASH_DOT => {
+                return lambda(form, expr, &**items, env).map(Step::Done)
+            }
+            Value::Symbol(form) if form == symbol::DEFUN => {
+                let function = try!(defun(expr, &**items));
+                let name = function.name;
+                self.functions.insert(name, Rc::new(Function::Defined(function)));
+                return Ok(Step::Done(Value::Symbol(name)))
+            }
+            Value::Symbol(form) if form == symbol::DEFINE => {
+                let definition = try!(define::compile(self, expr, &**items));
+                return Ok(Step::Eval(definition, env.clone()))
+            }
+            Value::Symbol(form) if form == symbol::DATATYPE => {
+                let (name, rules) = try!(types::datatype(expr, &**items));
+                self.define_datatype(name, expr.clone(), rules);
+                return Ok(Step::Done(Value::Symbol(name)))
+            }
+            Value::Symbol(form) if form == symbol::DEFCC => {
+                let definition = try!(yacc::compile(self, expr, &**items));
+                return Ok(Step::Eval(definition, env.clone()))
+            }
+            // The forms of a package are evaluated in turn, giving the last
+            Value::Symbol(form) if form == symbol::PACKAGE => {
+                let forms = try!(self.expand_package(expr, &**items));
+                let mut value = items[1].clone();
+                for form in forms.iter() {
+                    value = try!(self.eval(form, env));
+                }
+                return Ok(Step::Done(value))
+            }
+            Value::Symbol(form) if form == symbol::DEFPROLOG => {
+                let (name, clauses) = try!(prolog::defprolog(expr, &**items));
+                self.predicates.insert(name, Rc::new(clauses));
+                return Ok(Step::Done(Value::Symbol(name)))
+            }
+            Value::Symbol(form) if form == symbol::PROLOG => {
+                return self.prolog(expr, &**items, env).map(Step::Done)
+            }
+            // (defmacro Name X Body) defines a function of the form X and
+            // installs it as a macro
+            Value::Symbol(form) if form == symbol::DEFMACRO => {
+                let params = match items.get(2) {
+                    Some(param @ &Value::Symbol(_)) => Value::list(vec![param.clone()]),
+                    _ => return Err(EvalError::Malformed(form, expr.clone()))
+                };
+                let mut definition = (**items).clone();
+                definition[0] = Value::Symbol(symbol::DEFUN);
+                definition[2] = params;
+                let function = match defun(expr, &definition) {
+                    Ok(function) => function,
+                    Err(_) => return Err(EvalError::Malformed(form, expr.clone()))
+                };
+                let name = function.name;
+                self.functions.insert(name, Rc::new(Function::Defined(function)));
+                self.add_macro(name);
+                return Ok(Step::Done(Value::Symbol(name)))
+            }
+            Value::Symbol(form) if form == symbol::IF => {
+                if items.len() != 4 {
+                    return Err(EvalError::Malformed(form, expr.clone()))
+                }
+                return Ok(Step::Push(Frame::If(items.clone(), env.clone()), items[1].clone(), env.clone()))
+            }
+            // Each clause is (Condition Result), the first true one chosen
+            Value::Symbol(form) if form == symbol::COND => {
+                let wellformed = items[1..].iter().all(|clause| match *clause {
+                    Value::List(ref clause) => clause.len() == 2,
+                    _ => false
+                });
+                if !wellformed {
+                    return Err(EvalError::Malformed(form, expr.clone()))
+                }
+                return cond(items.clone(), env.clone(), 1)
+            }
+            // Conditions are evaluated only until the result is known
+            Value::Symbol(form) if form == symbol::AND || form == symbol::OR => {
+                if items.len() < 3 {
+                    return Err(EvalError::Malformed(form, expr.clone()))
+                }
+                return Ok(Step::Push(Frame::Logic(items.clone(), env.clone(), 1), items[1].clone(), env.clone()))
+            }
+            // (let X Value Body), or (let X V1 Y V2 Body) binding each in
+            // turn so later values may refer to earlier variables
+            Value::Symbol(form) if form == symbol::LET => {
+                let wellformed = items.len() >= 4 && items.len() % 2 == 0 &&
+                    items[1..items.len() - 1].chunks(2).all(|binding| match binding[0] {
+                        Value::Symbol(_) => true,
+                        _ => false
+                    });
+                if !wellformed {
+                    return Err(EvalError::Malformed(form, expr.clone()))
+                }
+                return Ok(Step::Push(Frame::Let(items.clone(), env.clone(), 1), items[2].clone(), env.clone()))
+            }
+            // (trap-error Expr Handler) applies the handler to any error
+            // raised while evaluating the expression
+            Value::Symbol(form) if form == symbol::TRAP_ERROR => {
+                if items.len() != 3 {
+                    return Err(EvalError::Malformed(form, expr.clone()))
+                }
+                let trap = Frame::Trap(items[2].clone(), env.clone());
+                return Ok(Step::Push(trap, items[1].clone(), env.clone()))
+            }
+            Value::Symbol(form) if form == symbol::CASES => {
+                return Ok(Step::Eval(try!(cases(expr, &**items)), env.clone()))
+            }
+            // (do E1 ... En) evaluates the expressions in turn for their
+            // effects, giving the value of the last
+            Value::Symbol(form) if form == symbol::DO => {
+                if items.len() < 2 {
+                    return Err(EvalError::Malformed(form, expr.clone()))
+                }
+                return sequence(items.clone(), env.clone(), 1)
+            }
+            // (unwind-protect Body Cleanup...) evaluates the cleanup forms
+            // once the body is done, whether it returned or raised an error
+            Value::Symbol(form) if form == symbol::UNWIND_PROTECT => {
+                if items.len() < 3 {
+                    return Err(EvalError::Malformed(form, expr.clone()))
+                }
+                return Ok(Step::Push(Frame::Protect(items.clone(), env.clone()), items[1].clone(), env.clone()))
+            }
+            // (reset Expr) delimits the continuations captured while
+            // evaluating the expression
+            Value::Symbol(form) if form == symbol::RESET => {
+                if items.len() != 2 {
+                    return Err(EvalError::Malformed(form, expr.clone()))
+                }
+                return Ok(Step::Push(Frame::Reset, items[1].clone(), env.clone()))
+            }
+            // (shift K Body) evaluates the body with K bound to the
+            // continuation up to the innermost reset, whose value becomes
+            // that of the body
+            Value::Symbol(form) if form == symbol::SHIFT => {
+                return match (items.len(), &items[1]) {
+                    (3, &Value::Symbol(name)) => Ok(Step::Shift(name, items[2].clone(), env.clone())),
+                    _ => Err(EvalError::Malformed(form, expr.clone()))
+                }
+            }
+            Value::Symbol(form) if form == symbol::FREEZE => {
+                if items.len() != 2 {
+                    return Err(EvalError::Malformed(form, expr.clone()))
+                }
+                let frozen = Frozen { body: items[1].clone(), env: env.clone() };
+                return Ok(Step::Done(Value::Frozen(Rc::new(frozen))))
+            }
+            _ => {}
+        }
+
+        self.application(items.clone(), env.clone(), Vec::with_capacity(items.len() - 1))
+    }
+
+    // Call a global function, applying its result to any extra arguments
+    pub fn call(&mut self, name: SymbolId, args: Vec<Value>) -> Result<Value, EvalError> {
+        let step = try!(self.call_step(name, args));
+        self.run(step)
+    }
+
+    // Apply a function to its arguments one at a time
+    pub fn apply(&mut self, function: Value, args: Vec<Value>) -> Result<Value, EvalError> {
+        let step = try!(self.apply_step(function, args));
+        self.run(step)
+    }
+
+    // A call up to evaluating the function's body, or the body of the last
+    // function applied to extra arguments.  Functions are curried, so given
+    // too few arguments the result is the function partially applied.
+    fn call_step(&mut self, name: SymbolId, args: Vec<Value>) -> Result<Step, EvalError> {
+        let function = match self.function(name) {
+            Some(function) => function,
+            None => return Err(EvalError::UndefinedFunction(name))
+        };
+        let arity = function.arity();
+        if args.len() < arity {
+            return Ok(Step::Done(Value::Partial(Rc::new(Partial { function: name, args: args }))))
+        }
+
+        let mut args = args;
+        let extra = args.split_off(arity);
+        let (body, env) = match *function {
+            Function::Defined(ref defun) => {
+                if self.tracked.contains(&name) {
+                    self.track_depth += 1;
+                    let inputs: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+                    show_tracked(self.track_depth, &*format!("<{}> Inputs to {}", self.track_depth, name));
+                    show_tracked(self.track_depth, &*format!("{} ==>", inputs.connect(", ")));
+                }
+                let mut env = Env::new();
+                for (param, arg) in defun.params.iter().zip(args.into_iter()) {
+                    env = env.bind(*param, arg);
+                }
+                return Ok(Step::Call(name, defun.body.clone(), env, extra))
+            }
+            // thaw evaluates the frozen expression as if in its place, so
+            // loops made of frozen expressions don't nest
+            Function::Primitive(_, _) if name == symbol::THAW => {
+                match args[0] {
+                    Value::Frozen(ref frozen) => (frozen.body.clone(), frozen.env.clone()),
+                    ref value => return Err(EvalError::WrongType(name, "a frozen expression", value.clone()))
+                }
+            }
+            Function::Primitive(_, primitive) if self.profile.is_some() => {
+                // Primitives are timed as if they had frames of their own
+                // just beneath the stack's frames
+                let (running, frames) = (self.running, self.max_depth + 1);
+                self.profile.as_mut().map(|profile| profile.enter(name, running, frames));
+                let result = primitive(self, args);
+                self.profile.as_mut().map(|profile| profile.leave());
+                return self.apply_step(try!(result), extra)
+            }
+            Function::Primitive(_, primitive) => {
+                let result = try!(primitive(self, args));
+                return self.apply_step(result, extra)
+            }
+        };
+        if extra.is_empty() {
+            Ok(Step::Eval(body, env))
+        } else {
+            Ok(Step::Push(Frame::Apply(extra), body, env))
+        }
+    }
+
+    // Application up to evaluating the body of the last function applied.
+    // Closures take their arguments one at a time while partially applied
+    // functions take all those remaining.
+    fn apply_step(&mut self, function: Value, args: Vec<Value>) -> Result<Step, EvalError> {
+        if args.is_empty() {
+            return Ok(Step::Done(function))
+        }
+        let mut args = args;
+        match function {
+            Value::Closure(ref closure) => {
+                let rest = args.split_off(1);
+                let env = closure.env.bind(closure.param, args.pop().unwrap());
+                if rest.is_empty() {
+                    Ok(Step::Eval(closure.body.clone(), env))
+                } else {
+                    Ok(Step::Push(Frame::Apply(rest), closure.body.clone(), env))
+                }
+            }
+            Value::Partial(ref partial) => {
+                let mut all = partial.args.clone();
+                all.extend(args.into_iter());
+                self.call_step(partial.function, all)
+            }
+            // A symbol applied names the global function to call, as
+            // when a function's name is passed to map
+            Value::Symbol(name) => self.call_step(name, args),
+            Value::Continuation(ref k) => {
+                let rest = args.split_off(1);
+                Ok(Step::Resume(k.clone(), args.pop().unwrap(), rest))
+            }
+            _ => Err(EvalError::NotAFunction(function.clone()))
+        }
+    }
+}
+
+// The value of a condition of the form, which must be true or false
+fn condition(items: &[Value], value: Value) -> Result<bool, EvalError> {
+    match value {
+        Value::Bool(b) => Ok(b),
+        value => {
+            let form = match items[0] {
+                Value::Symbol(form) => form,
+                _ => symbol::COND
+            };
+            Err(EvalError::NotABoolean(form, value))
+        }
+    }
+}
+
+// Try the cond clauses from the index on
+fn cond(items: Rc<Vec<Value>>, env: Env, i: usize) -> Result<Step, EvalError> {
+    if i == items.len() {
+        return Err(EvalError::CondFailure)
+    }
+    let test = match items[i] {
+        Value::List(ref clause) => clause[0].clone(),
+        _ => unreachable!()
+    };
+    Ok(Step::Push(Frame::Cond(items, env.clone(), i), test, env))
+}
+
+// Unwind to the innermost trap-error, evaluating its handler to apply to the
+// error, or give the error if nothing traps it.  Cleanup forms of
+// unwind-protects passed on the way are evaluated first, after which the
+// error carries on unwinding.  The functions unwound through are added to
+// the error's trace.  Exceeding a limit, exiting, abandoning the evaluation
+// or interrupting it can't be trapped, so that each always ends the
+// evaluation.  The depth of tracked calls is set back to that of the calls
+// left.
+fn unwind(stack: &mut Vec<Frame>, e: EvalError, track_depth: &mut usize) -> Result<Step, EvalError> {
+    let mut e = e;
+    let trappable = match *e.cause() {
+        EvalError::LimitExceeded(_, _) | EvalError::Exit(_) | EvalError::Abandoned |
+        EvalError::Interrupted => false,
+        _ => true
+    };
+    loop {
+        match stack.pop() {
+            Some(Frame::Trap(handler, env)) if trappable => {
+                let error = Value::Error(Rc::new(e));
+                return Ok(Step::Push(Frame::Apply(vec![error]), handler, env))
+            }
+            Some(Frame::Protect(items, env)) => return cleanup(items, env, 2, Err(e)),
+            Some(Frame::Body(name)) => e = e.through(name),
+            Some(Frame::Tracked(_, depth)) => *track_depth = depth - 1,
+            Some(_) => {}
+            None => return Err(e)
+        }
+    }
+}
+
+// A line showing a tracked call, indented by its depth
+fn show_tracked(depth: usize, line: &str) {
+    let indent: String = (0..depth).map(|_| ' ').collect();
+    outln!("{}{}", indent, line);
+}
+
+// Evaluate the expressions of a do from the index on, the last in tail
+// position
+fn sequence(items: Rc<Vec<Value>>, env: Env, i: usize) -> Result<Step, EvalError> {
+    let next = items[i].clone();
+    if i + 1 == items.len() {
+        return Ok(Step::Eval(next, env))
+    }
+    Ok(Step::Push(Frame::Do(items, env.clone(), i), next, env))
+}
+
+// Evaluate the cleanup forms from the index on, then give the outcome of the
+// protected body
+fn cleanup(items: Rc<Vec<Value>>, env: Env, i: usize, outcome: Result<Value, EvalError>) -> Result<Step, EvalError> {
+    if i == items.len() {
+        return outcome.map(Step::Done)
+    }
+    let next = items[i].clone();
+    Ok(Step::Push(Frame::Cleanup(items, env.clone(), i, outcome), next, env))
+}
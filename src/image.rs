@@ -0,0 +1,126 @@
+use std::old_io::{File, Reader, Writer};
+use std::old_path::Path;
+
+use eval::{EvalError, Function, Interpreter};
+use number::Number;
+use reader;
+use value::{Env, Value};
+
+// An image is the state a session has built up, written as code which
+// recreates it: a defun for each function defined, which takes in macros,
+// a set for each global, including *macros*, and a put for each property,
+// then each datatype and a declare for each type signature.  Functions are
+// saved as compiled, so restoring doesn't define them again from their
+// source.
+//
+// Only values with a written form which evaluates to them can be saved,
+// that is numbers, strings, symbols, booleans, and lists and tuples of
+// those.  Globals and properties with other values, such as closures or
+// vectors, are left out, with a comment in the image saying so.  Prolog
+// predicates aren't saved.
+impl Interpreter {
+    pub fn save_image(&self, path: &str) -> Result<(), EvalError> {
+        let mut image = "\\\\ Image of a burton session, restored by burton --image or load-image\n\n".to_string();
+        for (name, function) in self.defined_functions().into_iter() {
+            match *function {
+                Function::Defined(ref defun) => {
+                    let params: Vec<String> = defun.params.iter().map(|param| param.to_string()).collect();
+                    image.push_str(&*format!("(defun {} ({}) {})\n", name, params.connect(" "), defun.body));
+                }
+                Function::Primitive(_, _) => {}
+            }
+        }
+        image.push_str("\n");
+        for (name, value) in self.globals().into_iter() {
+            match readable(&value) {
+                Some(code) => image.push_str(&*format!("(set {} {})\n", name, code)),
+                None => image.push_str(&*format!("\\\\ {} not saved: {}\n", name, value))
+            }
+        }
+        image.push_str("\n");
+        for (name, pointer, value) in self.properties().into_iter() {
+            match readable(&value) {
+                Some(code) => image.push_str(&*format!("(put {} {} {})\n", name, pointer, code)),
+                None => image.push_str(&*format!("\\\\ {} {} not saved: {}\n", name, pointer, value))
+            }
+        }
+        image.push_str("\n");
+        for form in self.datatype_forms().into_iter() {
+            image.push_str(&*format!("{}\n", form));
+        }
+        for (name, signature) in self.declared().into_iter() {
+            image.push_str(&*format!("(declare {} {})\n", name, type_list(&signature)));
+        }
+        if self.typechecking() {
+            image.push_str("\n(tc +)\n");
+        }
+        File::create(&Path::new(path)).and_then(|mut file| file.write_str(&*image))
+            .map_err(|e| EvalError::Simple(format!("{}: {}", path, e)))
+    }
+
+    // Restore the state saved in an image.  The forms are evaluated as they
+    // are, without expanding macros or type checking.
+    pub fn load_image(&mut self, path: &str) -> Result<(), EvalError> {
+        let code = match File::open(&Path::new(path)).and_then(|mut file| file.read_to_string()) {
+            Ok(code) => code,
+            Err(e) => return Err(EvalError::Simple(format!("{}: {}", path, e)))
+        };
+        let forms = match reader::read(&*code) {
+            Ok(forms) => forms,
+            Err(e) => return Err(EvalError::Located(format!("{}:{}", path, e), e.span().excerpt(&*code)))
+        };
+        for form in forms.iter() {
+            try!(self.eval(&Value::from_node(form), &Env::new()));
+        }
+        Ok(())
+    }
+}
+
+// A signature written as a list for declare, its items in brackets
+fn type_list(signature: &Value) -> String {
+    match *signature {
+        Value::List(ref items) => {
+            let items: Vec<String> = items.iter().map(type_list).collect();
+            format!("[{}]", items.connect(" "))
+        }
+        ref item => item.to_string()
+    }
+}
+
+// Code which evaluates to the value, if it has any
+fn readable(value: &Value) -> Option<String> {
+    match *value {
+        Value::Num(Number::Float(x)) if !x.is_finite() => None,
+        Value::Num(_) | Value::Bool(_) | Value::Str(_) | Value::Symbol(_) | Value::Nil => Some(value.to_string()),
+        Value::Cons(_) => {
+            let mut items = Vec::new();
+            let mut rest = value;
+            loop {
+                match *rest {
+                    Value::Cons(ref cons) => {
+                        items.push(match readable(&cons.head) {
+                            Some(code) => code,
+                            None => return None
+                        });
+                        rest = &cons.tail;
+                    }
+                    Value::Nil => return Some(format!("[{}]", items.connect(" "))),
+                    ref last => {
+                        let last = match readable(last) {
+                            Some(code) => code,
+                            None => return None
+                        };
+                        return Some(format!("[{} | {}]", items.connect(" "), last))
+                    }
+                }
+            }
+        }
+        Value::Tuple(ref tuple) => {
+            match (readable(&tuple.first), readable(&tuple.second)) {
+                (Some(first), Some(second)) => Some(format!("(@p {} {})", first, second)),
+                _ => None
+            }
+        }
+        _ => None
+    }
+}
@@ -0,0 +1,30 @@
+use std::sync::atomic::{AtomicBool, ATOMIC_BOOL_INIT, Ordering};
+use libc;
+
+// C-c in the REPL interrupts the evaluation under way rather than ending the
+// program.  The signal handler only sets a flag, which the evaluator checks
+// at each step, raising an error which returns to the prompt.
+
+static INTERRUPTED: AtomicBool = ATOMIC_BOOL_INIT;
+
+extern fn interrupt(_: libc::c_int) {
+    INTERRUPTED.store(true, Ordering::SeqCst);
+}
+
+// Catch C-c from now on, instead of it ending the program
+pub fn install() {
+    unsafe {
+        libc::signal(libc::SIGINT, interrupt as libc::sighandler_t);
+    }
+}
+
+// Forget any C-c pressed before now, so that one pressed between
+// evaluations doesn't interrupt the next
+pub fn clear() {
+    INTERRUPTED.store(false, Ordering::SeqCst);
+}
+
+// Whether C-c has been pressed since this was last asked
+pub fn take() -> bool {
+    INTERRUPTED.load(Ordering::Relaxed) && INTERRUPTED.swap(false, Ordering::SeqCst)
+}
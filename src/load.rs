@@ -0,0 +1,77 @@
+use std::old_io::{File, Reader};
+use std::old_path::Path;
+use time;
+
+use eval::{EvalError, Interpreter};
+use reader;
+use repl;
+use symbol;
+use value::Value;
+
+impl Interpreter {
+    // Evaluate the forms of a file in turn as though entered at the top
+    // level, printing the result of each and lastly the time taken.  Loading
+    // stops at the first error, which is given with where in the file the
+    // form raising it starts.
+    //
+    // A file starting with a #! line is a script, the line being skipped and
+    // the results not printed.  The value of the last form is given.
+    pub fn load(&mut self, path: &str) -> Result<Value, EvalError> {
+        self.load_file(path, false).map(|(last, _)| last)
+    }
+
+    // Load a file without printing the results, as for a script
+    pub fn load_quietly(&mut self, path: &str) -> Result<Value, EvalError> {
+        self.load_file(path, true).map(|(last, _)| last)
+    }
+
+    // Load a file, giving the value of the last form only if the file is a
+    // script, for a script run from the command line to exit with
+    pub fn load_script(&mut self, path: &str) -> Result<Option<Value>, EvalError> {
+        self.load_file(path, false).map(|(last, script)| if script { Some(last) } else { None })
+    }
+
+    // The value of the last form, and whether the file is a script
+    fn load_file(&mut self, path: &str, quiet: bool) -> Result<(Value, bool), EvalError> {
+        let mut code = match File::open(&Path::new(path)).and_then(|mut file| file.read_to_string()) {
+            Ok(code) => code,
+            Err(e) => return Err(EvalError::Simple(format!("{}: {}", path, e)))
+        };
+        let script = code.starts_with("#!");
+        if script {
+            // Keep the line ending, so lines are numbered as in the file
+            let end = code.find('\n').unwrap_or(code.len());
+            code = code[end..].to_string();
+        }
+        let forms = match reader::read(&*code) {
+            Ok(forms) => forms,
+            Err(e) => return Err(EvalError::Located(format!("{}:{}", path, e), e.span().excerpt(&*code)))
+        };
+        let quiet = quiet || script;
+        let start = time::precise_time_s();
+        let mut last = Value::Symbol(symbol::intern("loaded"));
+        for form in forms.iter() {
+            match self.eval_toplevel(&Value::from_node(form)) {
+                Ok((value, type_)) => {
+                    if !quiet {
+                        repl::print_result(Ok((value.clone(), type_)));
+                    }
+                    last = value;
+                }
+                Err(e) => {
+                    match *e.cause() {
+                        EvalError::Exit(_) => return Err(e),
+                        _ => {}
+                    }
+                    let located = EvalError::Located(format!("{}:{}: {}", path, form.span, e),
+                                                     form.span.excerpt(&*code));
+                    return Err(e.trace().iter().fold(located, |located, &name| located.through(name)))
+                }
+            }
+        }
+        if !quiet {
+            outln!("\nrun time: {} secs", time::precise_time_s() - start);
+        }
+        Ok((last, script))
+    }
+}
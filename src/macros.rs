@@ -0,0 +1,96 @@
+use eval::{EvalError, Interpreter};
+use symbol::{self, SymbolId};
+use value::Value;
+
+// Most rounds of applying the macros to a form, beyond which it's taken that
+// they'll never stop changing it
+const MAX_ROUNDS: usize = 10000;
+
+// Macros are functions of one argument registered by name in *macros*.  Each
+// is given a form as list data and returns its expansion, or the form itself
+// when it doesn't apply.
+impl Interpreter {
+    // The installed macros, most recently defined first
+    pub fn macros(&self) -> Vec<SymbolId> {
+        let list = self.global(symbol::MACROS).ok().and_then(|macros| macros.to_vec());
+        let mut names = Vec::new();
+        for name in list.unwrap_or(Vec::new()).into_iter() {
+            match name {
+                Value::Symbol(name) => names.push(name),
+                _ => {}
+            }
+        }
+        names
+    }
+
+    pub fn add_macro(&mut self, name: SymbolId) {
+        let mut names = self.macros();
+        names.retain(|other| *other != name);
+        names.insert(0, name);
+        self.set_macros(names);
+    }
+
+    // Uninstall a macro, false if there was no such macro
+    pub fn remove_macro(&mut self, name: SymbolId) -> bool {
+        let mut names = self.macros();
+        let count = names.len();
+        names.retain(|other| *other != name);
+        let removed = names.len() != count;
+        self.set_macros(names);
+        removed
+    }
+
+    fn set_macros(&mut self, names: Vec<SymbolId>) {
+        let list = Value::from_vec(names.into_iter().map(Value::Symbol).collect());
+        self.set_global(symbol::MACROS, list);
+    }
+
+    // Apply the macros to the form until none changes it, then expand each
+    // subform in the same way.  The whole expansion is one evaluation, so its
+    // steps are counted together.
+    pub fn macroexpand(&mut self, form: Value) -> Result<Value, EvalError> {
+        try!(self.enter());
+        let names = self.macros();
+        let result = self.expand(&*names, form);
+        self.leave();
+        result
+    }
+
+    fn expand(&mut self, names: &[SymbolId], given: Value) -> Result<Value, EvalError> {
+        let mut form = given.clone();
+        let mut rounds = 0;
+        loop {
+            let mut expanded = form.clone();
+            for name in names.iter() {
+                expanded = try!(self.call(*name, vec![expanded]));
+            }
+            if expanded == form {
+                break
+            }
+            rounds += 1;
+            if rounds == MAX_ROUNDS {
+                return Err(EvalError::Simple(format!("macros still expanding {} after {} rounds", given, MAX_ROUNDS)))
+            }
+            form = expanded;
+        }
+        match form.to_vec() {
+            Some(ref items) if !items.is_empty() => {
+                let mut expanded = Vec::with_capacity(items.len());
+                for item in items.iter() {
+                    expanded.push(try!(self.expand(names, item.clone())));
+                }
+                Ok(Value::from_vec(expanded))
+            }
+            _ => Ok(form)
+        }
+    }
+
+    // Expand the macros in code read at the top level
+    pub fn expand_toplevel(&mut self, code: &Value) -> Result<Value, EvalError> {
+        if self.macros().is_empty() {
+            return Ok(code.clone())
+        }
+        let expanded = try!(self.macroexpand(code.to_data()));
+        Ok(expanded.to_code())
+    }
+}
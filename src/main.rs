@@ -1,21 +1,200 @@
 #![feature(core)]
 #![feature(collections)]
+extern crate libc;
+extern crate num;
+extern crate time;
+
 use std::fmt;
+use std::num::Float;
+use num::bigint::BigInt;
+use num::rational::BigRational;
+use symbol::SymbolId;
+use std::old_io::Writer;
+use eval::{EvalError, Interpreter};
+use number::Number;
+use value::Value;
+use reader::{Position, Span};
+
+// As println! and print!, but adding what's shown to the transcript of the
+// REPL session, if one is being kept
+macro_rules! outln {
+    ($($arg:tt)*) => (::transcript::print(&*format!("{}\n", format_args!($($arg)*))))
+}
+
+macro_rules! out {
+    ($($arg:tt)*) => (::transcript::print(&*format!($($arg)*)))
+}
+
+mod borrowed;
+mod color;
+mod cst;
+mod debugger;
+mod define;
+mod demo;
+mod dict;
+mod editor;
+mod eval;
+mod image;
+mod interrupt;
+mod load;
+mod macros;
+mod number;
+mod package;
+mod pretty;
+mod primitives;
+mod profile;
+mod prolog;
+mod random;
+mod reader;
+mod repl;
+mod server;
+mod stats;
+mod stdlib;
+mod symbol;
+mod transcript;
+mod types;
+mod value;
+mod yacc;
 
-static DEFAULT_ATOM_SIZE: usize = 32;
+#[derive(PartialEq)]
+pub enum SymbolicExpr {
+    Int(i64),
+    BigInt(BigInt),
+    Rational(BigRational),
+    Float(f64),
+    Bool(bool),
+    Str(String),
+    Char(char),
+    Symbol(SymbolId),
+    ListExpr(Vec<Node>)
+}
+
+// A SymbolicExpr along with where it came from
+pub struct Node {
+    pub expr: SymbolicExpr,
+    pub span: Span,
+}
+
+impl Node {
+    pub fn new(expr: SymbolicExpr, start: Position, end: Position) -> Node {
+        Node { expr: expr, span: Span { start: start, end: end } }
+    }
+}
 
-enum SymbolicExpr {
-    Number(f64),
-    Symbol(String),
-    ListExpr(Vec<SymbolicExpr>)
+// Nodes are equal when their expressions are, wherever they were read from
+impl PartialEq for Node {
+    fn eq(&self, other: &Node) -> bool {
+        self.expr == other.expr
+    }
 }
 
+impl SymbolicExpr {
+    // Source text which reads back as an equal expression.  Lists are always
+    // printed with parens, so [1 2] prints as the (cons ...) it was read as.
+    // Infinite and NaN floats have no literal syntax.
+    pub fn to_sexpr_string(&self) -> String {
+        let mut out = String::new();
+        self.write_sexpr(&mut out, false);
+        out
+    }
+
+    // As to_sexpr_string but printing cons forms as the lists or dotted pairs
+    // they construct, for exchanging data with other Lisps
+    pub fn to_data_string(&self) -> String {
+        let mut out = String::new();
+        self.write_sexpr(&mut out, true);
+        out
+    }
+
+    // The head and tail of a (cons head tail) form
+    fn as_cons(&self) -> Option<(&SymbolicExpr, &SymbolicExpr)> {
+        match *self {
+            SymbolicExpr::ListExpr(ref items) if items.len() == 3 => {
+                match items[0].expr {
+                    SymbolicExpr::Symbol(s) if s == symbol::intern("cons") => Some((&items[1].expr, &items[2].expr)),
+                    _ => None
+                }
+            }
+            _ => None
+        }
+    }
+
+    fn write_sexpr(&self, out: &mut String, dotted: bool) {
+        if dotted {
+            if let Some((head, tail)) = self.as_cons() {
+                out.push('(');
+                head.write_sexpr(out, dotted);
+                let mut tail = tail;
+                while let Some((head, rest)) = tail.as_cons() {
+                    out.push(' ');
+                    head.write_sexpr(out, dotted);
+                    tail = rest;
+                }
+                match *tail {
+                    // A list in the tail continues the list
+                    SymbolicExpr::ListExpr(ref items) => {
+                        for item in items.iter() {
+                            out.push(' ');
+                            item.expr.write_sexpr(out, dotted);
+                        }
+                    }
+                    _ => {
+                        out.push_str(" . ");
+                        tail.write_sexpr(out, dotted);
+                    }
+                }
+                out.push(')');
+                return
+            }
+        }
+
+        match *self {
+            SymbolicExpr::Int(num) => out.push_str(&*num.to_string()),
+            SymbolicExpr::BigInt(ref num) => out.push_str(&*num.to_string()),
+            SymbolicExpr::Rational(ref num) => out.push_str(&*format!("{}/{}", num.numer(), num.denom())),
+            SymbolicExpr::Float(num) if num.is_finite() && num.fract() == 0.0 => {
+                out.push_str(&*format!("{:.1}", num))
+            }
+            SymbolicExpr::Float(num) => out.push_str(&*num.to_string()),
+            SymbolicExpr::Bool(b) => out.push_str(if b { "true" } else { "false" }),
+            SymbolicExpr::Str(ref s) => out.push_str(&*reader::escape_string(&**s)),
+            SymbolicExpr::Char(c) => out.push_str(&*reader::escape_char(c)),
+            SymbolicExpr::Symbol(sym) => sym.with_name(|name| out.push_str(name)),
+            SymbolicExpr::ListExpr(ref sexprs) => {
+                out.push('(');
+                for (i, s) in sexprs.iter().enumerate() {
+                    if i > 0 {
+                        out.push(' ');
+                    }
+                    s.expr.write_sexpr(out, dotted);
+                }
+                out.push(')');
+            }
+        }
+    }
+}
+
+impl Node {
+    pub fn to_sexpr_string(&self) -> String {
+        self.expr.to_sexpr_string()
+    }
+}
 
 impl fmt::Display for SymbolicExpr {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match *self {
-            SymbolicExpr::Number(num) => write!(f, "(Number {})", num),
-            SymbolicExpr::Symbol(ref sym) => write!(f, "(Symbol {})", sym),
+            SymbolicExpr::Int(num) => write!(f, "(Int {})", num),
+            SymbolicExpr::BigInt(ref num) => write!(f, "(BigInt {})", num),
+            SymbolicExpr::Rational(ref num) => write!(f, "(Rational {}/{})", num.numer(), num.denom()),
+            // Keep a decimal point on whole floats so they aren't mistaken for ints
+            SymbolicExpr::Float(num) if num.is_finite() && num.fract() == 0.0 => {
+                write!(f, "(Float {:.1})", num)
+            }
+            SymbolicExpr::Float(num) => write!(f, "(Float {})", num),
+            SymbolicExpr::Bool(b) => write!(f, "(Bool {})", b),
+            SymbolicExpr::Str(ref s) => write!(f, "(String {})", reader::escape_string(&**s)),
+            SymbolicExpr::Char(c) => write!(f, "(Char {})", reader::escape_char(c)),
+            SymbolicExpr::Symbol(sym) => write!(f, "(Symbol {})", sym),
             SymbolicExpr::ListExpr(ref sexprs) => {
                 try!(f.write_str("(List"));
                 for s in sexprs.iter() {
@@ -34,167 +213,130 @@ impl fmt::Debug for SymbolicExpr {
     }
 }
 
-#[derive(PartialEq, Copy)]
-enum State {
-    Start,
-    Symbol,
-    Integer,
-    IncompleteFloating,
-    Floating,
+impl fmt::Display for Node {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.expr.fmt(f)
+    }
 }
 
-fn to_atom(state: State, accum: String) -> Result<SymbolicExpr, &'static str> {
-    match state {
-        State::Symbol => {Ok(SymbolicExpr::Symbol(accum))}
-        State::Integer | State::Floating => {
-            match accum.parse::<f64>() {
-                Some(i) => {
-                    Ok(SymbolicExpr::Number(i))
-                }
-                None => Err("Cannot parse number")
-            }
-        }
-        _ => Err("Invalid atom")
+impl fmt::Debug for Node {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}@{}", self.expr, self.span)
     }
 }
 
-// Non-recursive parse using state machine
-fn read(code: &str) -> Result<Vec<SymbolicExpr>, &'static str> {
-    let mut accum = String::with_capacity(DEFAULT_ATOM_SIZE);
-    let mut exprs = Vec::new();
-    let mut stack = Vec::new();
-    let mut state = State::Start;
-
-    for c in code.chars() {
-        match c {
-            // Whitespace which can only terminate atoms
-            ' ' | '\n' | '\r' | '\t' => {
-                if state != State::Start {
-                    match to_atom(state, accum.clone()) {
-                        Ok(sexpr) => {
-                            exprs.push(sexpr);
-                            accum.clear();
-                            state = State::Start;
-                        }
-                        Err(s) => return Err(s)
-                    }
-                }
-            }
-
-            _ => {
-                match (state, c) {
-                    (_, '(') => {
-                        if state != State::Start {
-                            match to_atom(state, accum.clone()) {
-                                Ok(sexpr) => {
-                                    exprs.push(sexpr);
-                                    accum.clear();
-                                }
-                                Err(s) => return Err(s)
-                            }
-                        }
-                        state = State::Start;
-                        stack.push(exprs);
-                        exprs = Vec::new();
-                    }
-
-                    (_, ')') => {
-                        if state != State::Start {
-                            match to_atom(state, accum.clone()) {
-                                Ok(sexpr) => {
-                                    exprs.push(sexpr);
-                                    accum.clear();
-                                }
-                                Err(s) => return Err(s)
-                            }
-                        }
-                        let list = SymbolicExpr::ListExpr(exprs);
-                        state = State::Start;
-                        exprs = match stack.pop() {
-                            Some(mut parent) => {
-                                parent.push(list);
-                                parent
-                            }
-                            None => return Err("Missing '('")
-                        }
-                    }
-
-                    (State::Start, '0' ... '9') => {
-                        state = State::Integer;
-                        accum.push(c);
-                    }
-
-                    (State::Start, _) => {
-                        state = State::Symbol;
-                        accum.push(c);
-                    }
-
-                    (State::Integer, '.') => {
-                        state = State::IncompleteFloating;
-                        accum.push(c);
-                    }
-
-                    (State::IncompleteFloating, '0' ... '9') => {
-                        state = State::Floating;
-                        accum.push(c);
-                    }
-
-                    (State::Integer, '0' ... '9') | (State::Floating, '0' ... '9') => {
-                        accum.push(c);
-                    }
-
-                    (State::Integer, _) | (State::Floating, _) | (State::IncompleteFloating, _) => {
-                        return Err("Invalid number")
-                    }
-
-                    (State::Symbol, _) => {
-                        accum.push(c);
-                    }
-                }
+// Usage:
+//
+//   burton                       run the REPL, see repl.rs
+//   burton File... -e Expr...    load the files and evaluate the expressions
+//                                in the order given, printing their values
+//   burton profile File... -e Expr...
+//                                as above, then give the calls of each
+//                                function and the time in them on stderr
+//   burton serve Address         evaluate code sent to a socket, see server.rs
+//   burton --demo                run the examples in demo.rs
+//   --image File                 first restore the session File was saved
+//                                in by save-image
+//   --no-color                   don't colour the output
+//
+// Loading files exits with the status given to exit, or else 0, except that
+// a file starting with #!/usr/bin/env burton, run as a script, exits with
+// the value of its last form if that's an integer from 0 to 255.
+// Expressions exit with 0 unless they raise an error or exit.
+fn main() {
+    let mut args = std::os::args();
+    // Colour is left off when asked, as well as when output isn't to a terminal
+    let no_color = args.iter().any(|arg| &**arg == "--no-color");
+    args.retain(|arg| &**arg != "--no-color");
+    color::enable(!no_color && color::wanted());
+    if args.iter().skip(1).any(|arg| &**arg == "--demo") {
+        demo::run();
+        return
+    }
+    let mut interpreter = Interpreter::new();
+    match args.iter().position(|arg| &**arg == "--image") {
+        Some(i) if i + 1 < args.len() => {
+            let path = args.remove(i + 1);
+            args.remove(i);
+            match interpreter.load_image(&*path) {
+                Ok(()) => {}
+                Err(e) => std::process::exit(repl::report(e))
             }
         }
+        Some(_) => {
+            let _ = std::old_io::stderr().write_str("--image needs a file to restore\n");
+            std::process::exit(2)
+        }
+        None => {}
     }
-
-    if state != State::Start {
-        match to_atom(state, accum.clone()) {
-            Ok(sexpr) => {
-                exprs.push(sexpr);
+    if args.len() > 1 && &*args[1] == "profile" {
+        args.remove(1);
+        if args.len() == 1 {
+            let _ = std::old_io::stderr().write_str("profile needs files or expressions to run\n");
+            std::process::exit(2)
+        }
+        interpreter.start_profiling();
+    }
+    if args.len() > 1 && &*args[1] == "serve" {
+        if args.len() != 3 {
+            let _ = std::old_io::stderr().write_str("serve needs an address to listen on\n");
+            std::process::exit(2)
+        }
+        match server::serve(&mut interpreter, &*args[2]) {
+            Ok(()) => std::process::exit(0),
+            Err(e) => {
+                let _ = std::old_io::stderr().write_str(&*format!("serve: {}: {}\n", args[2], e));
+                std::process::exit(1)
             }
-            Err(s) => return Err(s)
         }
     }
-
-    if stack.len() == 0 {
-        return Ok(exprs)
-    } else {
-        return Err("Unmatched '('")
+    if args.len() > 1 {
+        let status = run_args(&mut interpreter, &args[1..]);
+        if let Some(profile) = interpreter.take_profile() {
+            let _ = std::old_io::stderr().write_str(&*profile.report());
+        }
+        std::process::exit(status)
     }
+    repl::run(&mut interpreter);
 }
 
-fn print_read(ast: Result<Vec<SymbolicExpr>, &str>) {
-    match ast {
-        Ok(sexprs) => {
-            for s in sexprs.iter() {
-                println!("{}", s);
-            }
+// Load the files and evaluate the expressions given with -e in turn, giving
+// the status to exit with
+fn run_args(interpreter: &mut Interpreter, args: &[String]) -> i32 {
+    let mut status = 0;
+    let mut rest = args.iter();
+    while let Some(arg) = rest.next() {
+        let result = if &**arg != "-e" {
+            interpreter.load_script(&**arg).and_then(|last| match last {
+                Some(Value::Num(Number::Int(n))) if n < 0 || n > 255 => {
+                    Err(EvalError::Simple(format!("{}: exit status {} isn't between 0 and 255", arg, n)))
+                }
+                Some(Value::Num(Number::Int(n))) => Ok(n as i32),
+                _ => Ok(0)
+            })
+        } else if let Some(code) = rest.next() {
+            evaluate(interpreter, &**code).map(|_| 0)
+        } else {
+            let _ = std::old_io::stderr().write_str("-e needs an expression to evaluate\n");
+            std::process::exit(2)
+        };
+        match result {
+            Ok(code) => status = code,
+            Err(e) => return repl::report(e)
         }
-        Err(s) => println!("{}", s)
     }
+    status
 }
 
-fn main() {
-    let code = "12.3";
-    print_read(read(code));
-
-    let sym = "+";
-    print_read(read(sym));
-
-    let list = "()";
-    print_read(read(list));
-
-    let add = "(+ 1 2)";
-    print_read(read(add));
-
-    let magsqr = "(* (+ 1 2) (+ 3 4))";
-    print_read(read(magsqr));
+// Evaluate the forms of an expression given with -e, printing the value of
+// each
+fn evaluate(interpreter: &mut Interpreter, code: &str) -> Result<(), EvalError> {
+    let forms = match reader::read(code) {
+        Ok(forms) => forms,
+        Err(e) => return Err(EvalError::Located(format!("-e:{}", e), e.span().excerpt(code)))
+    };
+    for form in forms.iter() {
+        repl::print_result(Ok(try!(interpreter.eval_toplevel(&Value::from_node(form)))));
+    }
+    Ok(())
 }
@@ -0,0 +1,355 @@
+use std::cmp::Ordering;
+use std::fmt;
+use std::num::Float;
+use std::num::Int;
+use num::bigint::BigInt;
+use num::rational::BigRational;
+use num::{self, FromPrimitive, Integer, ToPrimitive};
+
+use SymbolicExpr;
+
+// Numeric tower from most to least exact.  Binary operations coerce both
+// operands to the least exact of the two and results are demoted back to the
+// simplest exact representation, so the result of mixing two numbers is
+//
+//           Int      Big      Ratio    Float
+//   Int     Int*     Big*     Ratio*   Float
+//   Big     Big*     Big*     Ratio*   Float
+//   Ratio   Ratio*   Ratio*   Ratio*   Float
+//   Float   Float    Float    Float    Float
+//
+// where * marks results demoted when simpler, (+ 1/2 1/2) being the Int 1
+// and (- (* 2 Big) Big) an Int if it fits.  Any float makes the result a
+// float, even an integral one.  / is exact on exact numbers, (/ 1 3) being
+// 1/3 and (/ 4 2) the Int 2.  Comparisons are exact whatever the
+// representations, a float comparing as the rational it stands for.
+#[derive(Clone, PartialEq)]
+pub enum Number {
+    Int(i64),
+    Big(BigInt),
+    Ratio(BigRational),
+    Float(f64),
+}
+
+impl Number {
+    pub fn from_sexpr(sexpr: &SymbolicExpr) -> Option<Number> {
+        match *sexpr {
+            SymbolicExpr::Int(i) => Some(Number::Int(i)),
+            SymbolicExpr::BigInt(ref b) => Some(Number::Big(b.clone())),
+            SymbolicExpr::Rational(ref r) => Some(Number::Ratio(r.clone())),
+            SymbolicExpr::Float(x) => Some(Number::Float(x)),
+            _ => None
+        }
+    }
+
+    pub fn to_sexpr(self) -> SymbolicExpr {
+        match self {
+            Number::Int(i) => SymbolicExpr::Int(i),
+            Number::Big(b) => SymbolicExpr::BigInt(b),
+            Number::Ratio(r) => SymbolicExpr::Rational(r),
+            Number::Float(x) => SymbolicExpr::Float(x),
+        }
+    }
+
+    // Rationals with a unit denominator become integers and big integers
+    // which fit become machine integers
+    pub fn normalize(self) -> Number {
+        match self {
+            Number::Ratio(r) => {
+                if r.is_integer() {
+                    Number::Big(r.to_integer()).normalize()
+                } else {
+                    Number::Ratio(r)
+                }
+            }
+            Number::Big(b) => {
+                match b.to_i64() {
+                    Some(i) => Number::Int(i),
+                    None => Number::Big(b)
+                }
+            }
+            n => n
+        }
+    }
+
+    fn rank(&self) -> u8 {
+        match *self {
+            Number::Int(_) => 0,
+            Number::Big(_) => 1,
+            Number::Ratio(_) => 2,
+            Number::Float(_) => 3,
+        }
+    }
+
+    fn promote(self, rank: u8) -> Number {
+        match (self, rank) {
+            (Number::Int(i), 1) => Number::Big(big(i)),
+            (Number::Int(i), 2) => Number::Ratio(BigRational::from_integer(big(i))),
+            (Number::Big(b), 2) => Number::Ratio(BigRational::from_integer(b)),
+            (n, 3) => Number::Float(n.to_f64()),
+            (n, _) => n
+        }
+    }
+
+    pub fn to_f64(&self) -> f64 {
+        match *self {
+            Number::Int(i) => i as f64,
+            Number::Big(ref b) => b.to_f64().unwrap_or(std::f64::NAN),
+            Number::Ratio(ref r) => {
+                match (r.numer().to_f64(), r.denom().to_f64()) {
+                    (Some(n), Some(d)) => n / d,
+                    _ => std::f64::NAN
+                }
+            }
+            Number::Float(x) => x
+        }
+    }
+
+    // The number as a big integer if it's an exact integer
+    pub fn to_bigint(&self) -> Option<BigInt> {
+        match *self {
+            Number::Int(i) => Some(big(i)),
+            Number::Big(ref b) => Some(b.clone()),
+            _ => None
+        }
+    }
+
+    // Whether the number is a whole number, in whichever representation
+    pub fn is_integer(&self) -> bool {
+        match *self {
+            Number::Int(_) | Number::Big(_) => true,
+            Number::Ratio(_) => false,
+            Number::Float(x) => x.is_finite() && x.fract() == 0.0,
+        }
+    }
+
+    pub fn is_zero(&self) -> bool {
+        match *self {
+            Number::Int(i) => i == 0,
+            Number::Float(x) => x == 0.0,
+            // Big and Ratio are always normalized away from zero
+            _ => false
+        }
+    }
+}
+
+fn big(i: i64) -> BigInt {
+    FromPrimitive::from_i64(i).unwrap()
+}
+
+// The exact value of a number, None for infinities and NaN
+fn exact(n: &Number) -> Option<Number> {
+    match *n {
+        Number::Float(x) => BigRational::from_float(x).map(|r| Number::Ratio(r).normalize()),
+        ref n => Some(n.clone())
+    }
+}
+
+fn coerce(a: Number, b: Number) -> (Number, Number) {
+    let rank = if a.rank() > b.rank() { a.rank() } else { b.rank() };
+    (a.promote(rank), b.promote(rank))
+}
+
+pub fn add(a: Number, b: Number) -> Number {
+    match coerce(a, b) {
+        (Number::Int(x), Number::Int(y)) => {
+            match x.checked_add(y) {
+                Some(z) => Number::Int(z),
+                None => Number::Big(big(x) + big(y))
+            }
+        }
+        (Number::Big(x), Number::Big(y)) => Number::Big(x + y).normalize(),
+        (Number::Ratio(x), Number::Ratio(y)) => Number::Ratio(x + y).normalize(),
+        (Number::Float(x), Number::Float(y)) => Number::Float(x + y),
+        _ => unreachable!()
+    }
+}
+
+pub fn sub(a: Number, b: Number) -> Number {
+    match coerce(a, b) {
+        (Number::Int(x), Number::Int(y)) => {
+            match x.checked_sub(y) {
+                Some(z) => Number::Int(z),
+                None => Number::Big(big(x) - big(y))
+            }
+        }
+        (Number::Big(x), Number::Big(y)) => Number::Big(x - y).normalize(),
+        (Number::Ratio(x), Number::Ratio(y)) => Number::Ratio(x - y).normalize(),
+        (Number::Float(x), Number::Float(y)) => Number::Float(x - y),
+        _ => unreachable!()
+    }
+}
+
+pub fn mul(a: Number, b: Number) -> Number {
+    match coerce(a, b) {
+        (Number::Int(x), Number::Int(y)) => {
+            match x.checked_mul(y) {
+                Some(z) => Number::Int(z),
+                None => Number::Big(big(x) * big(y))
+            }
+        }
+        (Number::Big(x), Number::Big(y)) => Number::Big(x * y).normalize(),
+        (Number::Ratio(x), Number::Ratio(y)) => Number::Ratio(x * y).normalize(),
+        (Number::Float(x), Number::Float(y)) => Number::Float(x * y),
+        _ => unreachable!()
+    }
+}
+
+// Division of exact numbers stays exact, producing a rational when needed
+pub fn div(a: Number, b: Number) -> Result<Number, &'static str> {
+    if b.is_zero() {
+        return Err("Division by zero")
+    }
+    match coerce(a, b) {
+        (Number::Float(x), Number::Float(y)) => Ok(Number::Float(x / y)),
+        (x, y) => {
+            match (x.promote(2), y.promote(2)) {
+                (Number::Ratio(x), Number::Ratio(y)) => Ok(Number::Ratio(x / y).normalize()),
+                _ => unreachable!()
+            }
+        }
+    }
+}
+
+// Integer division rounding towards negative infinity, so that the modulus
+// takes the sign of the divisor and (+ (* (div A B) B) (mod A B)) is A
+pub fn div_floor(a: BigInt, b: BigInt) -> Result<Number, &'static str> {
+    if b == big(0) {
+        return Err("Division by zero")
+    }
+    Ok(Number::Big(a.div_floor(&b)).normalize())
+}
+
+pub fn mod_floor(a: BigInt, b: BigInt) -> Result<Number, &'static str> {
+    if b == big(0) {
+        return Err("Division by zero")
+    }
+    Ok(Number::Big(a.mod_floor(&b)).normalize())
+}
+
+// Bitwise operations treat negative integers as two's complement with
+// infinitely many leading ones
+pub fn bit_and(a: BigInt, b: BigInt) -> Number {
+    Number::Big(a & b).normalize()
+}
+
+pub fn bit_or(a: BigInt, b: BigInt) -> Number {
+    Number::Big(a | b).normalize()
+}
+
+pub fn bit_xor(a: BigInt, b: BigInt) -> Number {
+    Number::Big(a ^ b).normalize()
+}
+
+pub fn shift_left(a: BigInt, bits: usize) -> Number {
+    Number::Big(a * num::pow(big(2), bits)).normalize()
+}
+
+// Shifting right rounds towards negative infinity, as division by a power of
+// two with div does
+pub fn shift_right(a: BigInt, bits: usize) -> Number {
+    Number::Big(a.div_floor(&num::pow(big(2), bits))).normalize()
+}
+
+// Rounding to an integer, which is exact whatever the number rounded.
+// Rationals and floats are rounded alike, halves away from zero by round.
+pub fn floor(n: Number) -> Option<Number> {
+    round_with(n, |r| r.floor(), |x| x.floor())
+}
+
+pub fn ceiling(n: Number) -> Option<Number> {
+    round_with(n, |r| r.ceil(), |x| x.ceil())
+}
+
+pub fn round(n: Number) -> Option<Number> {
+    round_with(n, |r| r.round(), |x| x.round())
+}
+
+pub fn truncate(n: Number) -> Option<Number> {
+    round_with(n, |r| r.trunc(), |x| x.trunc())
+}
+
+// None for infinities and NaN, which have no integer to round to
+fn round_with<R, F>(n: Number, ratio: R, float: F) -> Option<Number>
+    where R: Fn(&BigRational) -> BigRational, F: Fn(f64) -> f64 {
+    match n {
+        Number::Ratio(r) => Some(Number::Ratio(ratio(&r)).normalize()),
+        Number::Float(x) => BigRational::from_float(float(x)).map(|r| Number::Ratio(r).normalize()),
+        n => Some(n)
+    }
+}
+
+// Square roots of exact squares, such as 4 or 9/16, are exact and others
+// floats.  None for negative numbers.
+pub fn sqrt(n: Number) -> Option<Number> {
+    if compare(n.clone(), Number::Int(0)) != Some(Ordering::Greater) {
+        return if n.is_zero() { Some(n) } else { None }
+    }
+    let root = |i: &BigInt| {
+        let root = isqrt(i);
+        if root.clone() * root.clone() == *i { Some(root) } else { None }
+    };
+    let exact = match n {
+        Number::Int(_) | Number::Big(_) => n.to_bigint().and_then(|i| root(&i)).map(Number::Big),
+        Number::Ratio(ref r) => {
+            match (root(r.numer()), root(r.denom())) {
+                (Some(numer), Some(denom)) => Some(ratio(numer, denom)),
+                _ => None
+            }
+        }
+        Number::Float(_) => None
+    };
+    match exact {
+        Some(root) => Some(root.normalize()),
+        None => Some(Number::Float(n.to_f64().sqrt()))
+    }
+}
+
+// The largest integer whose square is at most a positive integer, by Newton's
+// method, which falls towards it from above
+fn isqrt(n: &BigInt) -> BigInt {
+    let mut x = n.clone();
+    loop {
+        let next = (x.clone() + n.clone() / x.clone()) / big(2);
+        if next >= x {
+            return x
+        }
+        x = next;
+    }
+}
+
+// Compare numbers of any representation, None if either is NaN
+pub fn compare(a: Number, b: Number) -> Option<Ordering> {
+    // Rather than round an exact number to compare it with a float, compare
+    // it with the exact value of the float
+    if a.rank() != b.rank() && (a.rank() == 3 || b.rank() == 3) {
+        match (exact(&a), exact(&b)) {
+            (Some(x), Some(y)) => return compare(x, y),
+            _ => {}
+        }
+    }
+    match coerce(a, b) {
+        (Number::Int(x), Number::Int(y)) => Some(x.cmp(&y)),
+        (Number::Big(x), Number::Big(y)) => Some(x.cmp(&y)),
+        (Number::Ratio(x), Number::Ratio(y)) => Some(x.cmp(&y)),
+        (Number::Float(x), Number::Float(y)) => x.partial_cmp(&y),
+        _ => unreachable!()
+    }
+}
+
+impl fmt::Display for Number {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Number::Int(i) => write!(f, "{}", i),
+            Number::Big(ref b) => write!(f, "{}", b),
+            Number::Ratio(ref r) => write!(f, "{}/{}", r.numer(), r.denom()),
+            Number::Float(x) if x.is_finite() && x.fract() == 0.0 => write!(f, "{:.1}", x),
+            Number::Float(x) => write!(f, "{}", x),
+        }
+    }
+}
+
+// Exact rational from a numerator and a non-zero denominator
+pub fn ratio(numer: BigInt, denom: BigInt) -> Number {
+    Number::Ratio(BigRational::new(numer, denom)).normalize()
+}
@@ -0,0 +1,60 @@
+use eval::{EvalError, Interpreter};
+use symbol::{self, SymbolId};
+use value::{Env, Value};
+
+// Type names and the keywords of definitions, which keep their names inside
+// a package as the system's functions and special forms do
+static RESERVED: &'static [&'static str] = &[
+    "number", "string", "symbol", "boolean", "list", "vector", "lazy", "exception", "unit",
+    "where", "if", "receive", "return", "is", "when", "unify", "<e>", "<!>",
+];
+
+// Whether a symbol in a package is renamed, which all are bar those listed
+// as external, variables, and the names the system already uses
+fn qualified(interpreter: &Interpreter, external: &[SymbolId], name: SymbolId) -> bool {
+    !(external.contains(&name) || name.is_variable() || symbol::is_well_known(name) ||
+      interpreter.is_system(name) ||
+      name.with_name(|name| !name.chars().any(|c| c.is_alphanumeric()) || RESERVED.contains(&name)))
+}
+
+fn qualify(interpreter: &Interpreter, package: SymbolId, external: &[SymbolId], expr: &Value) -> Value {
+    match *expr {
+        Value::Symbol(name) if qualified(interpreter, external, name) => {
+            Value::Symbol(symbol::intern(&*format!("{}.{}", package, name)))
+        }
+        Value::List(ref items) => {
+            Value::list(items.iter().map(|item| qualify(interpreter, package, external, item)).collect())
+        }
+        _ => expr.clone()
+    }
+}
+
+impl Interpreter {
+    // The forms of (package Name External Forms...), with each symbol not
+    // in the external list prefixed by the package's name, as in
+    // Name.symbol.  The package null leaves the symbols as they are.
+    pub fn expand_package(&mut self, expr: &Value, items: &[Value]) -> Result<Vec<Value>, EvalError> {
+        let malformed = || EvalError::Malformed(symbol::PACKAGE, expr.clone());
+        if items.len() < 3 {
+            return Err(malformed())
+        }
+        let name = match items[1] {
+            Value::Symbol(name) => name,
+            _ => return Err(malformed())
+        };
+        let list = try!(self.eval(&items[2], &Env::new()));
+        let mut external = Vec::new();
+        for item in try!(list.to_vec().ok_or(malformed())).into_iter() {
+            match item {
+                Value::Symbol(symbol) => external.push(symbol),
+                _ => return Err(malformed())
+            }
+        }
+        self.put_property(name, symbol::intern("shen.external-symbols"), list);
+
+        if name == symbol::intern("null") {
+            return Ok(items[3..].to_vec())
+        }
+        Ok(items[3..].iter().map(|form| qualify(self, name, &*external, form)).collect())
+    }
+}
@@ -0,0 +1,115 @@
+use SymbolicExpr;
+
+pub static DEFAULT_WIDTH: usize = 80;
+
+// Layout independent description of text, after Wadler's "A prettier printer".
+// Each Line in a group becomes a space if the whole group fits on the current
+// line, otherwise a newline indented by the enclosing Nests.
+pub enum Doc {
+    Text(String),
+    Line,
+    Nest(usize, Box<Doc>),
+    Group(Box<Doc>),
+    Concat(Vec<Doc>),
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Mode {
+    Flat,
+    Break,
+}
+
+// Whether doc laid out flat, followed by what comes after it up to the next
+// line break, fits in the remaining width
+fn fits<'a>(remaining: isize, doc: &'a Doc, rest: &[(usize, Mode, &'a Doc)]) -> bool {
+    let mut remaining = remaining;
+    let mut pending = vec![(Mode::Flat, doc)];
+    let mut next_rest = rest.len();
+    loop {
+        if remaining < 0 {
+            return false
+        }
+        let (mode, doc) = match pending.pop() {
+            Some(item) => item,
+            None if next_rest == 0 => return true,
+            None => {
+                next_rest -= 1;
+                let (_, mode, doc) = rest[next_rest];
+                (mode, doc)
+            }
+        };
+        match *doc {
+            Doc::Text(ref s) => remaining -= s.chars().count() as isize,
+            Doc::Line if mode == Mode::Break => return true,
+            Doc::Line => remaining -= 1,
+            Doc::Nest(_, ref inner) | Doc::Group(ref inner) => pending.push((mode, &**inner)),
+            Doc::Concat(ref docs) => {
+                for inner in docs.iter().rev() {
+                    pending.push((mode, inner));
+                }
+            }
+        }
+    }
+}
+
+pub fn render(doc: &Doc, width: usize) -> String {
+    let mut out = String::new();
+    let mut column = 0;
+    let mut stack = vec![(0, Mode::Break, doc)];
+    while let Some((indent, mode, doc)) = stack.pop() {
+        match *doc {
+            Doc::Text(ref s) => {
+                out.push_str(&**s);
+                column += s.chars().count();
+            }
+            Doc::Line if mode == Mode::Flat => {
+                out.push(' ');
+                column += 1;
+            }
+            Doc::Line => {
+                out.push('\n');
+                for _ in 0..indent {
+                    out.push(' ');
+                }
+                column = indent;
+            }
+            Doc::Nest(n, ref inner) => stack.push((indent + n, mode, &**inner)),
+            Doc::Group(ref inner) => {
+                let flat = mode == Mode::Flat ||
+                    fits(width as isize - column as isize, &**inner, &*stack);
+                stack.push((indent, if flat { Mode::Flat } else { Mode::Break }, &**inner));
+            }
+            Doc::Concat(ref docs) => {
+                for inner in docs.iter().rev() {
+                    stack.push((indent, mode, inner));
+                }
+            }
+        }
+    }
+    out
+}
+
+// Lists keep their head on the opening line, with the remaining items either
+// following it or each on their own line indented beneath it
+pub fn to_doc(expr: &SymbolicExpr) -> Doc {
+    match *expr {
+        SymbolicExpr::ListExpr(ref items) if !items.is_empty() => {
+            let mut rest = Vec::new();
+            for item in items[1..].iter() {
+                rest.push(Doc::Line);
+                rest.push(to_doc(&item.expr));
+            }
+            Doc::Group(Box::new(Doc::Concat(vec![
+                Doc::Text("(".to_string()),
+                to_doc(&items[0].expr),
+                Doc::Nest(2, Box::new(Doc::Concat(rest))),
+                Doc::Text(")".to_string()),
+            ])))
+        }
+        _ => Doc::Text(expr.to_sexpr_string())
+    }
+}
+
+pub fn pretty(expr: &SymbolicExpr, width: usize) -> String {
+    render(&to_doc(expr), width)
+}
@@ -0,0 +1,1002 @@
+use std::cmp::Ordering;
+use std::rc::Rc;
+
+use dict::Dict;
+use eval::{EvalError, Interpreter};
+use std::cell::RefCell;
+use std::char;
+use time;
+use num::ToPrimitive;
+use num::bigint::BigInt;
+
+use number::{self, Number};
+use reader;
+use symbol::{self, SymbolId};
+use value::{Env, Partial, Value};
+
+pub fn install(interpreter: &mut Interpreter) {
+    interpreter.define_primitive("thaw", 1, thaw);
+    interpreter.define_primitive("simple-error", 1, simple_error);
+    interpreter.define_primitive("error-to-string", 1, error_to_string);
+    interpreter.define_primitive("set", 2, set);
+    interpreter.define_primitive("value", 1, value);
+    interpreter.define_primitive("cons", 2, cons);
+    interpreter.define_primitive("hd", 1, hd);
+    interpreter.define_primitive("tl", 1, tl);
+    interpreter.define_primitive("cons?", 1, is_cons);
+    interpreter.define_primitive("+", 2, add);
+    interpreter.define_primitive("-", 2, sub);
+    interpreter.define_primitive("*", 2, mul);
+    interpreter.define_primitive("/", 2, div);
+    interpreter.define_primitive("div", 2, div_floor);
+    interpreter.define_primitive("mod", 2, mod_floor);
+    interpreter.define_primitive("bitwise-and", 2, bit_and);
+    interpreter.define_primitive("bitwise-or", 2, bit_or);
+    interpreter.define_primitive("bitwise-xor", 2, bit_xor);
+    interpreter.define_primitive("shift-left", 2, shift_left);
+    interpreter.define_primitive("shift-right", 2, shift_right);
+    interpreter.define_primitive("sqrt", 1, sqrt);
+    interpreter.define_primitive("exp", 1, exp);
+    interpreter.define_primitive("log", 1, log);
+    interpreter.define_primitive("sin", 1, sin);
+    interpreter.define_primitive("cos", 1, cos);
+    interpreter.define_primitive("tan", 1, tan);
+    interpreter.define_primitive("asin", 1, asin);
+    interpreter.define_primitive("acos", 1, acos);
+    interpreter.define_primitive("atan", 1, atan);
+    interpreter.define_primitive("atan2", 2, atan2);
+    interpreter.define_primitive("floor", 1, floor);
+    interpreter.define_primitive("ceiling", 1, ceiling);
+    interpreter.define_primitive("round", 1, round);
+    interpreter.define_primitive("truncate", 1, truncate);
+    interpreter.define_primitive("random", 1, random);
+    interpreter.define_primitive("seed-random", 1, seed_random);
+    interpreter.define_primitive("get-time", 1, get_time);
+    interpreter.define_primitive("shen.dict", 1, dict);
+    interpreter.define_primitive("shen.dict?", 1, is_dict);
+    interpreter.define_primitive("shen.dict-count", 1, dict_count);
+    interpreter.define_primitive("shen.dict->", 3, dict_put);
+    interpreter.define_primitive("shen.<-dict", 2, dict_get);
+    interpreter.define_primitive("shen.dict-rm", 2, dict_remove);
+    interpreter.define_primitive("shen.dict-fold", 3, dict_fold);
+    interpreter.define_primitive("shen.dict-keys", 1, dict_keys);
+    interpreter.define_primitive("shen.dict-values", 1, dict_values);
+    interpreter.define_primitive("load", 1, load);
+    interpreter.define_primitive("exit", 1, exit);
+    interpreter.define_primitive("track", 1, track);
+    interpreter.define_primitive("untrack", 1, untrack);
+    interpreter.define_primitive("break", 1, set_breakpoint);
+    interpreter.define_primitive("unbreak", 1, clear_breakpoint);
+    interpreter.define_primitive("runtime-stats", 0, runtime_stats);
+    interpreter.define_primitive("save-image", 1, save_image);
+    interpreter.define_primitive("load-image", 1, load_image);
+    interpreter.define_primitive("declare", 2, declare);
+    interpreter.define_primitive("=", 2, equal);
+    interpreter.define_primitive(">", 2, greater);
+    interpreter.define_primitive("<", 2, less);
+    interpreter.define_primitive(">=", 2, greater_or_equal);
+    interpreter.define_primitive("<=", 2, less_or_equal);
+    interpreter.define_primitive("cn", 2, cn);
+    interpreter.define_primitive("pos", 2, pos);
+    interpreter.define_primitive("tlstr", 1, tlstr);
+    interpreter.define_primitive("str", 1, str);
+    interpreter.define_primitive("string?", 1, is_string);
+    interpreter.define_primitive("string->n", 1, string_to_n);
+    interpreter.define_primitive("n->string", 1, n_to_string);
+    interpreter.define_primitive("read-from-string", 1, read_from_string);
+    interpreter.define_primitive("intern", 1, intern);
+    interpreter.define_primitive("gensym", 1, gensym);
+    interpreter.define_primitive("symbol?", 1, is_symbol);
+    interpreter.define_primitive("number?", 1, is_number);
+    interpreter.define_primitive("integer?", 1, is_integer);
+    interpreter.define_primitive("absvector", 1, absvector);
+    interpreter.define_primitive("address->", 3, set_address);
+    interpreter.define_primitive("<-address", 2, get_address);
+    interpreter.define_primitive("absvector?", 1, is_absvector);
+    interpreter.define_primitive("put", 3, put);
+    interpreter.define_primitive("get", 2, get);
+    interpreter.define_primitive("unput", 2, unput);
+    interpreter.define_primitive("eval-kl", 1, eval_kl);
+    interpreter.define_primitive("macroexpand", 1, macroexpand);
+    interpreter.define_primitive("undefmacro", 1, undefmacro);
+    interpreter.define_primitive("@p", 2, tuple);
+    interpreter.define_primitive("fst", 1, fst);
+    interpreter.define_primitive("snd", 1, snd);
+    interpreter.define_primitive("tuple?", 1, is_tuple);
+    // Outside patterns @s is only concatenation
+    interpreter.define_primitive("@s", 2, concatenate);
+    interpreter.define_primitive("shen.+string?", 1, is_nonempty_string);
+    interpreter.define_primitive("@v", 2, add_vector);
+    interpreter.define_primitive("hdv", 1, hdv);
+    interpreter.define_primitive("tlv", 1, tlv);
+    interpreter.define_primitive("shen.+vector?", 1, is_nonempty_vector);
+    interpreter.define_primitive("tc", 1, tc);
+    interpreter.define_primitive("fail", 0, fail);
+    interpreter.define_primitive("compile", 2, compile);
+    interpreter.define_primitive("external", 1, external);
+    interpreter.define_primitive("function", 1, function);
+    interpreter.define_primitive("arity", 1, arity);
+}
+
+fn thaw(interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, EvalError> {
+    match args[0] {
+        Value::Frozen(ref frozen) => interpreter.eval(&frozen.body, &frozen.env),
+        ref value => Err(EvalError::WrongType(symbol::intern("thaw"), "a frozen expression", value.clone()))
+    }
+}
+
+// Evaluates a KLambda expression built as a list, such as [+ 1 2], in an
+// empty local environment
+fn eval_kl(interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, EvalError> {
+    interpreter.eval(&args[0].to_code(), &Env::new())
+}
+
+fn macroexpand(interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, EvalError> {
+    interpreter.macroexpand(args[0].clone())
+}
+
+// Uninstalls the named macro, leaving the function it was defined as
+fn undefmacro(interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, EvalError> {
+    match args[0] {
+        Value::Symbol(name) => Ok(Value::Bool(interpreter.remove_macro(name))),
+        ref value => Err(EvalError::WrongType(symbol::intern("undefmacro"), "a symbol", value.clone()))
+    }
+}
+
+fn simple_error(_: &mut Interpreter, args: Vec<Value>) -> Result<Value, EvalError> {
+    match args[0] {
+        Value::Str(ref message) => Err(EvalError::Simple((**message).clone())),
+        ref value => Err(EvalError::WrongType(symbol::intern("simple-error"), "a string", value.clone()))
+    }
+}
+
+fn error_to_string(_: &mut Interpreter, args: Vec<Value>) -> Result<Value, EvalError> {
+    match args[0] {
+        Value::Error(ref e) => Ok(Value::Str(Rc::new(e.to_string()))),
+        ref value => Err(EvalError::WrongType(symbol::intern("error-to-string"), "an error", value.clone()))
+    }
+}
+
+fn set(interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, EvalError> {
+    match args[0] {
+        Value::Symbol(name) => {
+            interpreter.set_global(name, args[1].clone());
+            Ok(args[1].clone())
+        }
+        ref value => Err(EvalError::WrongType(symbol::intern("set"), "a symbol", value.clone()))
+    }
+}
+
+fn value(interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, EvalError> {
+    match args[0] {
+        Value::Symbol(name) => interpreter.global(name),
+        ref value => Err(EvalError::WrongType(symbol::intern("value"), "a symbol", value.clone()))
+    }
+}
+
+fn cons(_: &mut Interpreter, args: Vec<Value>) -> Result<Value, EvalError> {
+    let mut args = args;
+    let tail = args.pop().unwrap();
+    let head = args.pop().unwrap();
+    Ok(Value::cons(head, tail))
+}
+
+fn hd(_: &mut Interpreter, args: Vec<Value>) -> Result<Value, EvalError> {
+    match args[0] {
+        Value::Cons(ref cons) => Ok(cons.head.clone()),
+        ref value => Err(EvalError::WrongType(symbol::intern("hd"), "a cons", value.clone()))
+    }
+}
+
+fn tl(_: &mut Interpreter, args: Vec<Value>) -> Result<Value, EvalError> {
+    match args[0] {
+        Value::Cons(ref cons) => Ok(cons.tail.clone()),
+        ref value => Err(EvalError::WrongType(symbol::intern("tl"), "a cons", value.clone()))
+    }
+}
+
+fn is_cons(_: &mut Interpreter, args: Vec<Value>) -> Result<Value, EvalError> {
+    match args[0] {
+        Value::Cons(_) => Ok(Value::Bool(true)),
+        _ => Ok(Value::Bool(false))
+    }
+}
+
+fn number(function: &str, value: &Value) -> Result<Number, EvalError> {
+    match *value {
+        Value::Num(ref n) => Ok(n.clone()),
+        _ => Err(EvalError::WrongType(symbol::intern(function), "a number", value.clone()))
+    }
+}
+
+fn add(_: &mut Interpreter, args: Vec<Value>) -> Result<Value, EvalError> {
+    let (a, b) = (try!(number("+", &args[0])), try!(number("+", &args[1])));
+    Ok(Value::Num(number::add(a, b)))
+}
+
+fn sub(_: &mut Interpreter, args: Vec<Value>) -> Result<Value, EvalError> {
+    let (a, b) = (try!(number("-", &args[0])), try!(number("-", &args[1])));
+    Ok(Value::Num(number::sub(a, b)))
+}
+
+fn mul(_: &mut Interpreter, args: Vec<Value>) -> Result<Value, EvalError> {
+    let (a, b) = (try!(number("*", &args[0])), try!(number("*", &args[1])));
+    Ok(Value::Num(number::mul(a, b)))
+}
+
+fn div(_: &mut Interpreter, args: Vec<Value>) -> Result<Value, EvalError> {
+    let (a, b) = (try!(number("/", &args[0])), try!(number("/", &args[1])));
+    match number::div(a, b) {
+        Ok(n) => Ok(Value::Num(n)),
+        Err(message) => Err(EvalError::Simple(message.to_string()))
+    }
+}
+
+fn integer(function: &str, value: &Value) -> Result<BigInt, EvalError> {
+    match *value {
+        Value::Num(ref n) => {
+            match n.to_bigint() {
+                Some(i) => return Ok(i),
+                None => {}
+            }
+        }
+        _ => {}
+    }
+    Err(EvalError::WrongType(symbol::intern(function), "an integer", value.clone()))
+}
+
+// Most bits a number can be shifted by.  Shifting goes by a power of two as
+// large as the shift, which beyond this would take too much memory.
+const MAX_SHIFT: usize = 1 << 22;
+
+// A number of bits to shift by, which can't be negative
+fn bits(function: &str, value: &Value) -> Result<usize, EvalError> {
+    match try!(integer(function, value)).to_usize() {
+        Some(bits) if bits > MAX_SHIFT => {
+            Err(EvalError::Simple(format!("{}: {} bits is more than the most, {}", function, bits, MAX_SHIFT)))
+        }
+        Some(bits) => Ok(bits),
+        None => Err(EvalError::WrongType(symbol::intern(function), "a number of bits", value.clone()))
+    }
+}
+
+fn div_floor(_: &mut Interpreter, args: Vec<Value>) -> Result<Value, EvalError> {
+    let (a, b) = (try!(integer("div", &args[0])), try!(integer("div", &args[1])));
+    match number::div_floor(a, b) {
+        Ok(n) => Ok(Value::Num(n)),
+        Err(message) => Err(EvalError::Simple(message.to_string()))
+    }
+}
+
+fn mod_floor(_: &mut Interpreter, args: Vec<Value>) -> Result<Value, EvalError> {
+    let (a, b) = (try!(integer("mod", &args[0])), try!(integer("mod", &args[1])));
+    match number::mod_floor(a, b) {
+        Ok(n) => Ok(Value::Num(n)),
+        Err(message) => Err(EvalError::Simple(message.to_string()))
+    }
+}
+
+fn bit_and(_: &mut Interpreter, args: Vec<Value>) -> Result<Value, EvalError> {
+    let (a, b) = (try!(integer("bitwise-and", &args[0])), try!(integer("bitwise-and", &args[1])));
+    Ok(Value::Num(number::bit_and(a, b)))
+}
+
+fn bit_or(_: &mut Interpreter, args: Vec<Value>) -> Result<Value, EvalError> {
+    let (a, b) = (try!(integer("bitwise-or", &args[0])), try!(integer("bitwise-or", &args[1])));
+    Ok(Value::Num(number::bit_or(a, b)))
+}
+
+fn bit_xor(_: &mut Interpreter, args: Vec<Value>) -> Result<Value, EvalError> {
+    let (a, b) = (try!(integer("bitwise-xor", &args[0])), try!(integer("bitwise-xor", &args[1])));
+    Ok(Value::Num(number::bit_xor(a, b)))
+}
+
+fn shift_left(_: &mut Interpreter, args: Vec<Value>) -> Result<Value, EvalError> {
+    let (a, bits) = (try!(integer("shift-left", &args[0])), try!(bits("shift-left", &args[1])));
+    Ok(Value::Num(number::shift_left(a, bits)))
+}
+
+fn shift_right(_: &mut Interpreter, args: Vec<Value>) -> Result<Value, EvalError> {
+    let (a, bits) = (try!(integer("shift-right", &args[0])), try!(bits("shift-right", &args[1])));
+    Ok(Value::Num(number::shift_right(a, bits)))
+}
+
+// A function of floats, giving a float for any number.  The arguments
+// outside the function's domain are those for which it gives NaN.
+fn float<F>(function: &str, args: Vec<Value>, f: F) -> Result<Value, EvalError> where F: Fn(f64) -> f64 {
+    let x = try!(number(function, &args[0])).to_f64();
+    let y = f(x);
+    if y.is_nan() && !x.is_nan() {
+        return Err(EvalError::WrongType(symbol::intern(function), "in its domain", args[0].clone()))
+    }
+    Ok(Value::Num(Number::Float(y)))
+}
+
+fn sqrt(_: &mut Interpreter, args: Vec<Value>) -> Result<Value, EvalError> {
+    match number::sqrt(try!(number("sqrt", &args[0]))) {
+        Some(root) => Ok(Value::Num(root)),
+        None => Err(EvalError::WrongType(symbol::intern("sqrt"), "a non-negative number", args[0].clone()))
+    }
+}
+
+fn exp(_: &mut Interpreter, args: Vec<Value>) -> Result<Value, EvalError> {
+    float("exp", args, |x| x.exp())
+}
+
+// The logarithm of 0 is negative infinity rather than an error
+fn log(_: &mut Interpreter, args: Vec<Value>) -> Result<Value, EvalError> {
+    float("log", args, |x| x.ln())
+}
+
+fn sin(_: &mut Interpreter, args: Vec<Value>) -> Result<Value, EvalError> {
+    float("sin", args, |x| x.sin())
+}
+
+fn cos(_: &mut Interpreter, args: Vec<Value>) -> Result<Value, EvalError> {
+    float("cos", args, |x| x.cos())
+}
+
+fn tan(_: &mut Interpreter, args: Vec<Value>) -> Result<Value, EvalError> {
+    float("tan", args, |x| x.tan())
+}
+
+fn asin(_: &mut Interpreter, args: Vec<Value>) -> Result<Value, EvalError> {
+    float("asin", args, |x| x.asin())
+}
+
+fn acos(_: &mut Interpreter, args: Vec<Value>) -> Result<Value, EvalError> {
+    float("acos", args, |x| x.acos())
+}
+
+fn atan(_: &mut Interpreter, args: Vec<Value>) -> Result<Value, EvalError> {
+    float("atan", args, |x| x.atan())
+}
+
+// The angle of the point (X, Y), taking the arguments as Y and X
+fn atan2(_: &mut Interpreter, args: Vec<Value>) -> Result<Value, EvalError> {
+    let (y, x) = (try!(number("atan2", &args[0])), try!(number("atan2", &args[1])));
+    Ok(Value::Num(Number::Float(y.to_f64().atan2(x.to_f64()))))
+}
+
+fn rounded<F>(function: &str, args: Vec<Value>, f: F) -> Result<Value, EvalError> where F: Fn(Number) -> Option<Number> {
+    match f(try!(number(function, &args[0]))) {
+        Some(n) => Ok(Value::Num(n)),
+        None => Err(EvalError::WrongType(symbol::intern(function), "a finite number", args[0].clone()))
+    }
+}
+
+fn floor(_: &mut Interpreter, args: Vec<Value>) -> Result<Value, EvalError> {
+    rounded("floor", args, number::floor)
+}
+
+fn ceiling(_: &mut Interpreter, args: Vec<Value>) -> Result<Value, EvalError> {
+    rounded("ceiling", args, number::ceiling)
+}
+
+fn round(_: &mut Interpreter, args: Vec<Value>) -> Result<Value, EvalError> {
+    rounded("round", args, number::round)
+}
+
+fn truncate(_: &mut Interpreter, args: Vec<Value>) -> Result<Value, EvalError> {
+    rounded("truncate", args, number::truncate)
+}
+
+// A random integer from 0 up to but not including a positive integer, or a
+// random float below a positive float
+fn random(interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, EvalError> {
+    match args[0] {
+        Value::Num(Number::Int(n)) if n > 0 => {
+            Ok(Value::Num(Number::Int(interpreter.random().below(n as u64) as i64)))
+        }
+        Value::Num(Number::Float(x)) if x > 0.0 => {
+            Ok(Value::Num(Number::Float(interpreter.random().float() * x)))
+        }
+        ref value => Err(EvalError::WrongType(symbol::intern("random"), "a positive machine integer or float", value.clone()))
+    }
+}
+
+fn seed_random(interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, EvalError> {
+    match args[0] {
+        Value::Num(Number::Int(seed)) => {
+            interpreter.seed_random(seed as u64);
+            Ok(args[0].clone())
+        }
+        ref value => Err(EvalError::WrongType(symbol::intern("seed-random"), "a machine integer", value.clone()))
+    }
+}
+
+// (get-time unix) is the whole seconds since the Unix epoch and (get-time
+// real) the same with the fraction of a second.  (get-time run) is the
+// seconds since the interpreter started, as a float for timing code.
+fn get_time(interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, EvalError> {
+    match args[0] {
+        Value::Symbol(clock) if clock == symbol::intern("unix") => {
+            Ok(Value::Num(Number::Int(time::get_time().sec)))
+        }
+        Value::Symbol(clock) if clock == symbol::intern("real") => {
+            let now = time::get_time();
+            Ok(Value::Num(Number::Float(now.sec as f64 + now.nsec as f64 / 1e9)))
+        }
+        Value::Symbol(clock) if clock == symbol::intern("run") => {
+            Ok(Value::Num(Number::Float(interpreter.run_time())))
+        }
+        ref value => Err(EvalError::WrongType(symbol::intern("get-time"), "unix, real or run", value.clone()))
+    }
+}
+
+fn dict_of(function: &str, value: &Value) -> Result<Rc<RefCell<Dict>>, EvalError> {
+    match *value {
+        Value::Dict(ref dict) => Ok(dict.clone()),
+        _ => Err(EvalError::WrongType(symbol::intern(function), "a dict", value.clone()))
+    }
+}
+
+// The size is only a hint, as dicts grow as needed
+fn dict(_: &mut Interpreter, args: Vec<Value>) -> Result<Value, EvalError> {
+    try!(index("shen.dict", &args[0]));
+    Ok(Value::Dict(Rc::new(RefCell::new(Dict::new()))))
+}
+
+fn is_dict(_: &mut Interpreter, args: Vec<Value>) -> Result<Value, EvalError> {
+    match args[0] {
+        Value::Dict(_) => Ok(Value::Bool(true)),
+        _ => Ok(Value::Bool(false))
+    }
+}
+
+fn dict_count(_: &mut Interpreter, args: Vec<Value>) -> Result<Value, EvalError> {
+    let dict = try!(dict_of("shen.dict-count", &args[0]));
+    let count = dict.borrow().len();
+    Ok(Value::Num(Number::Int(count as i64)))
+}
+
+// (shen.dict-> Dict Key Value) stores the value, giving it
+fn dict_put(_: &mut Interpreter, args: Vec<Value>) -> Result<Value, EvalError> {
+    let dict = try!(dict_of("shen.dict->", &args[0]));
+    dict.borrow_mut().insert(args[1].clone(), args[2].clone());
+    Ok(args[2].clone())
+}
+
+fn dict_get(_: &mut Interpreter, args: Vec<Value>) -> Result<Value, EvalError> {
+    let dict = try!(dict_of("shen.<-dict", &args[0]));
+    let value = dict.borrow().get(&args[1]).cloned();
+    match value {
+        Some(value) => Ok(value),
+        None => Err(EvalError::Simple(format!("value {} not found in dict", args[1])))
+    }
+}
+
+// Removing a key which isn't there does nothing, the key being given either
+// way
+fn dict_remove(_: &mut Interpreter, args: Vec<Value>) -> Result<Value, EvalError> {
+    let dict = try!(dict_of("shen.dict-rm", &args[0]));
+    dict.borrow_mut().remove(&args[1]);
+    Ok(args[1].clone())
+}
+
+// (shen.dict-fold F Dict Init) applies F to each key, its value and the
+// result so far.  The entries are taken first, so F may change the dict.
+fn dict_fold(interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, EvalError> {
+    let entries = try!(dict_of("shen.dict-fold", &args[1])).borrow().entries();
+    let mut result = args[2].clone();
+    for (key, value) in entries.into_iter() {
+        result = try!(interpreter.apply(args[0].clone(), vec![key, value, result]));
+    }
+    Ok(result)
+}
+
+fn dict_keys(_: &mut Interpreter, args: Vec<Value>) -> Result<Value, EvalError> {
+    let entries = try!(dict_of("shen.dict-keys", &args[0])).borrow().entries();
+    Ok(Value::from_vec(entries.into_iter().map(|(key, _)| key).collect()))
+}
+
+fn dict_values(_: &mut Interpreter, args: Vec<Value>) -> Result<Value, EvalError> {
+    let entries = try!(dict_of("shen.dict-values", &args[0])).borrow().entries();
+    Ok(Value::from_vec(entries.into_iter().map(|(_, value)| value).collect()))
+}
+
+fn load(interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, EvalError> {
+    match args[0] {
+        Value::Str(ref path) => {
+            try!(interpreter.load(&**path));
+            Ok(Value::Symbol(symbol::intern("loaded")))
+        }
+        ref value => Err(EvalError::WrongType(symbol::intern("load"), "a string", value.clone()))
+    }
+}
+
+// (exit Status) ends the program, after the cleanup forms of any
+// unwind-protects it's within
+fn exit(_: &mut Interpreter, args: Vec<Value>) -> Result<Value, EvalError> {
+    match args[0] {
+        Value::Num(Number::Int(status)) if status as i32 as i64 == status => Err(EvalError::Exit(status as i32)),
+        ref value => Err(EvalError::WrongType(symbol::intern("exit"), "an exit status", value.clone()))
+    }
+}
+
+// (track F) shows the arguments of each call of the defined function F,
+// indented by how many tracked calls it's within, and the value it returns,
+// until (untrack F)
+fn track(interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, EvalError> {
+    match args[0] {
+        Value::Symbol(name) if interpreter.track(name) => Ok(args[0].clone()),
+        ref value => Err(EvalError::WrongType(symbol::intern("track"), "the name of a defined function", value.clone()))
+    }
+}
+
+fn untrack(interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, EvalError> {
+    match args[0] {
+        Value::Symbol(name) => {
+            interpreter.untrack(name);
+            Ok(args[0].clone())
+        }
+        ref value => Err(EvalError::WrongType(symbol::intern("untrack"), "a symbol", value.clone()))
+    }
+}
+
+// (break F) pauses evaluation on entering the defined function F, to step
+// through it from the REPL, until (unbreak F)
+fn set_breakpoint(interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, EvalError> {
+    match args[0] {
+        Value::Symbol(name) if interpreter.set_breakpoint(name) => Ok(args[0].clone()),
+        ref value => Err(EvalError::WrongType(symbol::intern("break"), "the name of a defined function", value.clone()))
+    }
+}
+
+fn clear_breakpoint(interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, EvalError> {
+    match args[0] {
+        Value::Symbol(name) => {
+            interpreter.clear_breakpoint(name);
+            Ok(args[0].clone())
+        }
+        ref value => Err(EvalError::WrongType(symbol::intern("unbreak"), "a symbol", value.clone()))
+    }
+}
+
+// (runtime-stats) is what the session holds as a list of pairs of names and
+// numbers, see stats.rs
+fn runtime_stats(interpreter: &mut Interpreter, _: Vec<Value>) -> Result<Value, EvalError> {
+    let entries = interpreter.stats().entries().into_iter().map(|(name, n)| {
+        Value::tuple(Value::Symbol(symbol::intern(name)), Value::Num(Number::Int(n as i64)))
+    }).collect();
+    Ok(Value::from_vec(entries))
+}
+
+// (save-image File) writes the functions, globals and properties of the
+// session to File, for (load-image File) or burton --image File to restore,
+// see image.rs
+fn save_image(interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, EvalError> {
+    match args[0] {
+        Value::Str(ref path) => {
+            try!(interpreter.save_image(&**path));
+            Ok(args[0].clone())
+        }
+        ref value => Err(EvalError::WrongType(symbol::intern("save-image"), "a string", value.clone()))
+    }
+}
+
+fn load_image(interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, EvalError> {
+    match args[0] {
+        Value::Str(ref path) => {
+            try!(interpreter.load_image(&**path));
+            Ok(args[0].clone())
+        }
+        ref value => Err(EvalError::WrongType(symbol::intern("load-image"), "a string", value.clone()))
+    }
+}
+
+// (declare Name Type) gives a function a type signature, written as a list
+// of what goes between the braces of a define, as in
+// (declare double [number --> number])
+fn declare(interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, EvalError> {
+    match (&args[0], signature(&args[1])) {
+        (&Value::Symbol(name), Some(signature)) => {
+            interpreter.declare(name, signature);
+            Ok(args[0].clone())
+        }
+        (&Value::Symbol(_), None) => Err(EvalError::WrongType(symbol::intern("declare"), "a type", args[1].clone())),
+        (value, _) => Err(EvalError::WrongType(symbol::intern("declare"), "a symbol", value.clone()))
+    }
+}
+
+// A type written as a list, as a signature is kept: the items as read
+// between braces, with lists within becoming the forms they'd be read as
+fn signature(value: &Value) -> Option<Value> {
+    let items = match value.to_vec() {
+        Some(ref items) if !items.is_empty() => items.clone(),
+        _ => return None
+    };
+    let mut types = Vec::new();
+    for item in items.into_iter() {
+        types.push(match item {
+            Value::Cons(_) => match signature(&item) {
+                Some(type_) => type_,
+                None => return None
+            },
+            item => item
+        });
+    }
+    Some(Value::list(types))
+}
+
+fn equal(_: &mut Interpreter, args: Vec<Value>) -> Result<Value, EvalError> {
+    Ok(Value::Bool(args[0] == args[1]))
+}
+
+// Whether the ordering of two numbers is one of those accepted, NaN
+// comparing false with everything
+fn compare(function: &str, args: Vec<Value>, accept: &[Ordering]) -> Result<Value, EvalError> {
+    let (a, b) = (try!(number(function, &args[0])), try!(number(function, &args[1])));
+    Ok(Value::Bool(match number::compare(a, b) {
+        Some(ordering) => accept.contains(&ordering),
+        None => false
+    }))
+}
+
+fn greater(_: &mut Interpreter, args: Vec<Value>) -> Result<Value, EvalError> {
+    compare(">", args, &[Ordering::Greater])
+}
+
+fn less(_: &mut Interpreter, args: Vec<Value>) -> Result<Value, EvalError> {
+    compare("<", args, &[Ordering::Less])
+}
+
+fn greater_or_equal(_: &mut Interpreter, args: Vec<Value>) -> Result<Value, EvalError> {
+    compare(">=", args, &[Ordering::Greater, Ordering::Equal])
+}
+
+fn less_or_equal(_: &mut Interpreter, args: Vec<Value>) -> Result<Value, EvalError> {
+    compare("<=", args, &[Ordering::Less, Ordering::Equal])
+}
+
+fn string<'a>(function: &str, value: &'a Value) -> Result<&'a str, EvalError> {
+    match *value {
+        Value::Str(ref s) => Ok(&***s),
+        _ => Err(EvalError::WrongType(symbol::intern(function), "a string", value.clone()))
+    }
+}
+
+fn cn(_: &mut Interpreter, args: Vec<Value>) -> Result<Value, EvalError> {
+    join_strings("cn", args)
+}
+
+fn concatenate(_: &mut Interpreter, args: Vec<Value>) -> Result<Value, EvalError> {
+    join_strings("@s", args)
+}
+
+fn join_strings(function: &str, args: Vec<Value>) -> Result<Value, EvalError> {
+    let (a, b) = (try!(string(function, &args[0])), try!(string(function, &args[1])));
+    Ok(Value::string(format!("{}{}", a, b)))
+}
+
+// The character at a zero based index, as a unit string
+fn pos(_: &mut Interpreter, args: Vec<Value>) -> Result<Value, EvalError> {
+    let s = try!(string("pos", &args[0]));
+    let index = match try!(number("pos", &args[1])) {
+        Number::Int(i) if i >= 0 => i as usize,
+        _ => return Err(EvalError::WrongType(symbol::intern("pos"), "an index", args[1].clone()))
+    };
+    match s.chars().nth(index) {
+        Some(c) => Ok(Value::string(c.to_string())),
+        None => Err(EvalError::Simple(format!("pos: index {} is out of range in {}", index, args[0])))
+    }
+}
+
+fn tlstr(_: &mut Interpreter, args: Vec<Value>) -> Result<Value, EvalError> {
+    let s = try!(string("tlstr", &args[0]));
+    match s.chars().next() {
+        Some(c) => Ok(Value::string(s[c.len_utf8()..].to_string())),
+        None => Err(EvalError::Simple("tlstr: the string is empty".to_string()))
+    }
+}
+
+// Any value as the text it prints as, so strings gain their quotes
+fn str(_: &mut Interpreter, args: Vec<Value>) -> Result<Value, EvalError> {
+    Ok(Value::string(args[0].to_string()))
+}
+
+fn is_string(_: &mut Interpreter, args: Vec<Value>) -> Result<Value, EvalError> {
+    match args[0] {
+        Value::Str(_) => Ok(Value::Bool(true)),
+        _ => Ok(Value::Bool(false))
+    }
+}
+
+// The test generated for @s patterns, which only match strings with a first
+// character to split off
+fn is_nonempty_string(_: &mut Interpreter, args: Vec<Value>) -> Result<Value, EvalError> {
+    match args[0] {
+        Value::Str(ref s) => Ok(Value::Bool(!s.is_empty())),
+        _ => Ok(Value::Bool(false))
+    }
+}
+
+// The code point of a unit string
+fn string_to_n(_: &mut Interpreter, args: Vec<Value>) -> Result<Value, EvalError> {
+    let s = try!(string("string->n", &args[0]));
+    let mut chars = s.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) => Ok(Value::Num(Number::Int(c as i64))),
+        _ => Err(EvalError::WrongType(symbol::intern("string->n"), "a unit string", args[0].clone()))
+    }
+}
+
+fn n_to_string(_: &mut Interpreter, args: Vec<Value>) -> Result<Value, EvalError> {
+    let code = match try!(number("n->string", &args[0])) {
+        Number::Int(i) => i.to_u32().and_then(char::from_u32),
+        _ => None
+    };
+    match code {
+        Some(c) => Ok(Value::string(c.to_string())),
+        None => Err(EvalError::WrongType(symbol::intern("n->string"), "a character code", args[0].clone()))
+    }
+}
+
+// Every form in the string, as data
+fn read_from_string(_: &mut Interpreter, args: Vec<Value>) -> Result<Value, EvalError> {
+    let code = try!(string("read-from-string", &args[0]));
+    match reader::read(code) {
+        Ok(forms) => Ok(Value::from_vec(forms.iter().map(Value::data_from_node).collect())),
+        Err(e) => Err(EvalError::Simple(e.to_string()))
+    }
+}
+
+// Read booleans are symbols in Shen, so interning their names gives booleans
+fn intern(_: &mut Interpreter, args: Vec<Value>) -> Result<Value, EvalError> {
+    match try!(string("intern", &args[0])) {
+        "true" => Ok(Value::Bool(true)),
+        "false" => Ok(Value::Bool(false)),
+        name => Ok(Value::Symbol(symbol::intern(name)))
+    }
+}
+
+// (gensym X) gives a fresh symbol such as X1
+fn gensym(interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, EvalError> {
+    match args[0] {
+        Value::Symbol(prefix) => Ok(Value::Symbol(interpreter.gensym(&*prefix.name()))),
+        ref value => Err(EvalError::WrongType(symbol::intern("gensym"), "a symbol", value.clone()))
+    }
+}
+
+fn is_symbol(_: &mut Interpreter, args: Vec<Value>) -> Result<Value, EvalError> {
+    match args[0] {
+        Value::Symbol(_) => Ok(Value::Bool(true)),
+        _ => Ok(Value::Bool(false))
+    }
+}
+
+fn is_number(_: &mut Interpreter, args: Vec<Value>) -> Result<Value, EvalError> {
+    match args[0] {
+        Value::Num(_) => Ok(Value::Bool(true)),
+        _ => Ok(Value::Bool(false))
+    }
+}
+
+// Whole numbers are integers even as floats, as 2.0 is
+fn is_integer(_: &mut Interpreter, args: Vec<Value>) -> Result<Value, EvalError> {
+    match args[0] {
+        Value::Num(ref n) => Ok(Value::Bool(n.is_integer())),
+        _ => Ok(Value::Bool(false))
+    }
+}
+
+fn index(function: &str, value: &Value) -> Result<usize, EvalError> {
+    match *value {
+        Value::Num(Number::Int(i)) if i >= 0 => Ok(i as usize),
+        _ => Err(EvalError::WrongType(symbol::intern(function), "an index", value.clone()))
+    }
+}
+
+// Most elements a vector can be made with, so that a mistaken size fails
+// rather than exhausting memory
+const MAX_VECTOR: usize = 1 << 22;
+
+fn absvector(_: &mut Interpreter, args: Vec<Value>) -> Result<Value, EvalError> {
+    let size = try!(index("absvector", &args[0]));
+    if size > MAX_VECTOR {
+        return Err(EvalError::Simple(format!("absvector: {} elements is more than the most, {}", size, MAX_VECTOR)))
+    }
+    Ok(Value::vector(vec![Value::Nil; size]))
+}
+
+// (address-> Vector Index Value) stores the value, giving the vector
+fn set_address(_: &mut Interpreter, args: Vec<Value>) -> Result<Value, EvalError> {
+    let i = try!(index("address->", &args[1]));
+    match args[0] {
+        Value::Vector(ref items) => {
+            let mut items = items.borrow_mut();
+            if i >= items.len() {
+                return Err(EvalError::Simple(format!("address->: index {} is out of range for {} elements", i, items.len())))
+            }
+            items[i] = args[2].clone();
+        }
+        ref value => return Err(EvalError::WrongType(symbol::intern("address->"), "an absvector", value.clone()))
+    }
+    Ok(args[0].clone())
+}
+
+fn get_address(_: &mut Interpreter, args: Vec<Value>) -> Result<Value, EvalError> {
+    let i = try!(index("<-address", &args[1]));
+    match args[0] {
+        Value::Vector(ref items) => {
+            let items = items.borrow();
+            match items.get(i) {
+                Some(item) => Ok(item.clone()),
+                None => Err(EvalError::Simple(format!("<-address: index {} is out of range for {} elements", i, items.len())))
+            }
+        }
+        ref value => Err(EvalError::WrongType(symbol::intern("<-address"), "an absvector", value.clone()))
+    }
+}
+
+fn is_absvector(_: &mut Interpreter, args: Vec<Value>) -> Result<Value, EvalError> {
+    match args[0] {
+        Value::Vector(_) => Ok(Value::Bool(true)),
+        _ => Ok(Value::Bool(false))
+    }
+}
+
+fn symbols(function: &str, args: &[Value]) -> Result<(SymbolId, SymbolId), EvalError> {
+    match (&args[0], &args[1]) {
+        (&Value::Symbol(name), &Value::Symbol(pointer)) => Ok((name, pointer)),
+        (&Value::Symbol(_), value) | (value, _) =>
+            Err(EvalError::WrongType(symbol::intern(function), "a symbol", value.clone()))
+    }
+}
+
+// (put Name Pointer Value) records Value under Pointer in the property list
+// of Name, giving Value
+fn put(interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, EvalError> {
+    let (name, pointer) = try!(symbols("put", &args));
+    interpreter.put_property(name, pointer, args[2].clone());
+    Ok(args[2].clone())
+}
+
+fn get(interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, EvalError> {
+    let (name, pointer) = try!(symbols("get", &args));
+    match interpreter.property(name, pointer) {
+        Some(value) => Ok(value),
+        None => Err(EvalError::Simple(format!("get: {} has no {} property", name, pointer)))
+    }
+}
+
+// Removes the property if present, giving Name
+fn unput(interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, EvalError> {
+    let (name, pointer) = try!(symbols("unput", &args));
+    interpreter.remove_property(name, pointer);
+    Ok(args[0].clone())
+}
+
+fn tuple(_: &mut Interpreter, args: Vec<Value>) -> Result<Value, EvalError> {
+    let mut args = args;
+    let second = args.pop().unwrap();
+    let first = args.pop().unwrap();
+    Ok(Value::tuple(first, second))
+}
+
+fn fst(_: &mut Interpreter, args: Vec<Value>) -> Result<Value, EvalError> {
+    match args[0] {
+        Value::Tuple(ref tuple) => Ok(tuple.first.clone()),
+        ref value => Err(EvalError::WrongType(symbol::intern("fst"), "a tuple", value.clone()))
+    }
+}
+
+fn snd(_: &mut Interpreter, args: Vec<Value>) -> Result<Value, EvalError> {
+    match args[0] {
+        Value::Tuple(ref tuple) => Ok(tuple.second.clone()),
+        ref value => Err(EvalError::WrongType(symbol::intern("snd"), "a tuple", value.clone()))
+    }
+}
+
+fn is_tuple(_: &mut Interpreter, args: Vec<Value>) -> Result<Value, EvalError> {
+    match args[0] {
+        Value::Tuple(_) => Ok(Value::Bool(true)),
+        _ => Ok(Value::Bool(false))
+    }
+}
+
+fn vector<'v>(function: &str, value: &'v Value) -> Result<&'v RefCell<Vec<Value>>, EvalError> {
+    match *value {
+        Value::Vector(ref items) => Ok(&**items),
+        _ => Err(EvalError::WrongType(symbol::intern(function), "a vector", value.clone()))
+    }
+}
+
+// (@v X V) is a new vector of X followed by the elements of V
+fn add_vector(_: &mut Interpreter, args: Vec<Value>) -> Result<Value, EvalError> {
+    let rest = try!(vector("@v", &args[1]));
+    let mut items = vec![args[0].clone()];
+    items.extend(rest.borrow().iter().map(|item| item.clone()));
+    Ok(Value::vector(items))
+}
+
+fn hdv(_: &mut Interpreter, args: Vec<Value>) -> Result<Value, EvalError> {
+    let items = try!(vector("hdv", &args[0])).borrow();
+    match items.first() {
+        Some(item) => Ok(item.clone()),
+        None => Err(EvalError::Simple("hdv: the vector is empty".to_string()))
+    }
+}
+
+// A new vector of all but the first element
+fn tlv(_: &mut Interpreter, args: Vec<Value>) -> Result<Value, EvalError> {
+    let items = try!(vector("tlv", &args[0])).borrow();
+    if items.is_empty() {
+        return Err(EvalError::Simple("tlv: the vector is empty".to_string()))
+    }
+    Ok(Value::vector(items[1..].to_vec()))
+}
+
+// The test generated for @v patterns
+fn is_nonempty_vector(_: &mut Interpreter, args: Vec<Value>) -> Result<Value, EvalError> {
+    match args[0] {
+        Value::Vector(ref items) => Ok(Value::Bool(!items.borrow().is_empty())),
+        _ => Ok(Value::Bool(false))
+    }
+}
+
+// (tc +) turns type checking on and (tc -) turns it off
+fn tc(interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, EvalError> {
+    match args[0] {
+        Value::Symbol(mode) if mode == symbol::intern("+") || mode == symbol::intern("-") => {
+            let typecheck = mode == symbol::intern("+");
+            interpreter.set_typechecking(typecheck);
+            Ok(Value::Bool(typecheck))
+        }
+        ref value => Err(EvalError::WrongType(symbol::intern("tc"), "+ or -", value.clone()))
+    }
+}
+
+// The value a defcc parser gives when it can't parse its input
+fn fail(_: &mut Interpreter, _: Vec<Value>) -> Result<Value, EvalError> {
+    Ok(Value::Symbol(symbol::intern("shen.fail!")))
+}
+
+// (compile <parser> Tokens) parses all of the tokens, giving the result
+fn compile(interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, EvalError> {
+    let mut args = args;
+    let input = args.pop().unwrap();
+    let parser = args.pop().unwrap();
+    let parsed = match parser {
+        Value::Symbol(name) => try!(interpreter.call(name, vec![input])),
+        function => try!(interpreter.apply(function, vec![input]))
+    };
+    match parsed {
+        Value::Tuple(ref parsed) if parsed.first == Value::Nil => Ok(parsed.second.clone()),
+        Value::Tuple(ref parsed) => Err(EvalError::Simple(format!("syntax error here: {}", parsed.first))),
+        _ => Err(EvalError::Simple("parse failure".to_string()))
+    }
+}
+
+// The symbols a package declared external
+fn external(interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, EvalError> {
+    let package = match args[0] {
+        Value::Symbol(package) => package,
+        ref value => return Err(EvalError::WrongType(symbol::intern("external"), "a symbol", value.clone()))
+    };
+    match interpreter.property(package, symbol::intern("shen.external-symbols")) {
+        Some(external) => Ok(external),
+        None => Err(EvalError::Simple(format!("package {} has not been used", package)))
+    }
+}
+
+// The global function of the name as a value, to be passed or applied
+fn function(interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, EvalError> {
+    match args[0] {
+        Value::Symbol(name) if interpreter.function(name).is_some() => {
+            Ok(Value::Partial(Rc::new(Partial { function: name, args: Vec::new() })))
+        }
+        Value::Symbol(name) => Err(EvalError::UndefinedFunction(name)),
+        ref value => Err(EvalError::WrongType(symbol::intern("function"), "a symbol", value.clone()))
+    }
+}
+
+// The number of arguments the named function takes, or -1 if there's no
+// function of that name
+fn arity(interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, EvalError> {
+    let arity = match args[0] {
+        Value::Symbol(name) => interpreter.function(name).map(|function| function.arity() as i64),
+        _ => None
+    };
+    Ok(Value::Num(Number::Int(arity.unwrap_or(-1))))
+}
@@ -0,0 +1,113 @@
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use time;
+
+use symbol::SymbolId;
+
+// Calls of each function made while profiling, with the time spent in them.
+// Inclusive time takes in the functions a function calls while exclusive
+// time is the function's own.  Time in a recursive call is only counted
+// once towards the inclusive time of the outermost call.
+pub struct Profile {
+    functions: HashMap<SymbolId, Record>,
+    // Calls under way, innermost last
+    active: Vec<Activation>,
+    // Calls under way of each function, for telling when a recursion ends
+    depth: HashMap<SymbolId, usize>,
+    started: u64,
+}
+
+#[derive(Clone, Copy)]
+pub struct Record {
+    pub calls: u64,
+    pub inclusive: u64,
+    pub exclusive: u64,
+}
+
+struct Activation {
+    name: SymbolId,
+    started: u64,
+    // Time spent in the calls it's made
+    callees: u64,
+    // Where the call's frame is, by the evaluation it's in and its place on
+    // that evaluation's stack, to tell which calls an error unwinds
+    running: usize,
+    frame: usize,
+}
+
+impl Profile {
+    pub fn new() -> Profile {
+        Profile { functions: HashMap::new(), active: Vec::new(), depth: HashMap::new(), started: time::precise_time_ns() }
+    }
+
+    // A function called, with its frame's place
+    pub fn enter(&mut self, name: SymbolId, running: usize, frame: usize) {
+        self.functions.entry(name).or_insert(Record { calls: 0, inclusive: 0, exclusive: 0 }).calls += 1;
+        *self.depth.entry(name).or_insert(0) += 1;
+        self.active.push(Activation {
+            name: name,
+            started: time::precise_time_ns(),
+            callees: 0,
+            running: running,
+            frame: frame,
+        });
+    }
+
+    // The innermost call returning, or replaced by a call in tail position
+    pub fn leave(&mut self) {
+        let activation = match self.active.pop() {
+            Some(activation) => activation,
+            None => return
+        };
+        let elapsed = time::precise_time_ns() - activation.started;
+        let depth = {
+            let depth = self.depth.get_mut(&activation.name).unwrap();
+            *depth -= 1;
+            *depth
+        };
+        let record = self.functions.get_mut(&activation.name).unwrap();
+        record.exclusive += elapsed.saturating_sub(activation.callees);
+        if depth == 0 {
+            record.inclusive += elapsed;
+        }
+        match self.active.last_mut() {
+            Some(caller) => caller.callees += elapsed,
+            None => {}
+        }
+    }
+
+    // Leave the calls whose frames an error has unwound, those of inner
+    // evaluations or beyond the stack's remaining frames
+    pub fn unwind(&mut self, running: usize, frames: usize) {
+        while self.active.last().map_or(false, |activation| {
+            activation.running > running || (activation.running == running && activation.frame > frames)
+        }) {
+            self.leave();
+        }
+    }
+
+    // The functions called, those taking the most time of their own first
+    pub fn records(&self) -> Vec<(SymbolId, Record)> {
+        let mut records: Vec<(SymbolId, Record)> = self.functions.iter().map(|(name, record)| (*name, *record)).collect();
+        records.sort_by(|a, b| match b.1.exclusive.cmp(&a.1.exclusive) {
+            Ordering::Equal => b.1.calls.cmp(&a.1.calls),
+            ordering => ordering
+        });
+        records
+    }
+
+    // A table of the functions called, with the total time profiled
+    pub fn report(&self) -> String {
+        let mut report = format!("{:<32} {:>10} {:>14} {:>14}\n", "function", "calls", "inclusive ms", "exclusive ms");
+        for (name, record) in self.records().into_iter() {
+            report.push_str(&*format!("{:<32} {:>10} {:>14.3} {:>14.3}\n", name.to_string(), record.calls,
+                                      millis(record.inclusive), millis(record.exclusive)));
+        }
+        report.push_str(&*format!("total {:.3} ms\n", millis(time::precise_time_ns() - self.started)));
+        report
+    }
+}
+
+fn millis(nanos: u64) -> f64 {
+    nanos as f64 / 1e6
+}
@@ -0,0 +1,379 @@
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use eval::{EvalError, Interpreter};
+use symbol::{self, SymbolId};
+use types;
+use value::{Env, Value};
+
+// A Prolog term.  Lists and other compound terms are written as code, so
+// [X | Y] is the compound (cons X Y).
+#[derive(Clone, PartialEq)]
+pub enum Term {
+    Var(usize),
+    Atom(Value),
+    Compound(SymbolId, Vec<Term>),
+}
+
+// Head <-- Body;, with the variables numbered from 0 up to vars
+pub struct Clause {
+    head: Vec<Term>,
+    body: Vec<Term>,
+    vars: usize,
+}
+
+// The variables of a clause or query by name
+struct Names {
+    names: HashMap<SymbolId, usize>,
+    count: usize,
+}
+
+impl Names {
+    fn new() -> Names {
+        Names { names: HashMap::new(), count: 0 }
+    }
+
+    fn fresh(&mut self) -> usize {
+        self.count += 1;
+        self.count - 1
+    }
+
+    // The term written as code, where each _ is a distinct variable
+    fn term(&mut self, expr: &Value, form: &Value) -> Result<Term, EvalError> {
+        match *expr {
+            Value::Symbol(name) if name == symbol::intern("_") => Ok(Term::Var(self.fresh())),
+            Value::Symbol(name) if name.is_variable() => {
+                match self.names.get(&name) {
+                    Some(&var) => return Ok(Term::Var(var)),
+                    None => {}
+                }
+                let var = self.fresh();
+                self.names.insert(name, var);
+                Ok(Term::Var(var))
+            }
+            Value::List(ref items) => {
+                let name = match items[0] {
+                    Value::Symbol(name) => name,
+                    _ => return Err(EvalError::Malformed(symbol::DEFPROLOG, form.clone()))
+                };
+                let mut args = Vec::with_capacity(items.len() - 1);
+                for item in items[1..].iter() {
+                    args.push(try!(self.term(item, form)));
+                }
+                Ok(Term::Compound(name, args))
+            }
+            _ => Ok(Term::Atom(expr.clone()))
+        }
+    }
+}
+
+// (defprolog Name Clauses...) where each clause is its head's arguments,
+// <-- then the goals of its body, ended by a semicolon:
+//
+// (defprolog member
+//   X [X | _] <--;
+//   X [_ | Y] <-- (member X Y);)
+pub fn defprolog(expr: &Value, items: &[Value]) -> Result<(SymbolId, Vec<Clause>), EvalError> {
+    let malformed = || EvalError::Malformed(symbol::DEFPROLOG, expr.clone());
+    let name = match items.get(1) {
+        Some(&Value::Symbol(name)) => name,
+        _ => return Err(malformed())
+    };
+    let arrow = Value::Symbol(symbol::intern("<--"));
+    let semicolon = Value::Symbol(symbol::intern(";"));
+    let tokens = types::semicolons(&items[2..]);
+    let mut clauses = Vec::new();
+    let mut rest = &tokens[..];
+    while !rest.is_empty() {
+        let (arrow, end) = match (rest.iter().position(|item| *item == arrow),
+                                  rest.iter().position(|item| *item == semicolon)) {
+            (Some(arrow), Some(end)) if arrow < end => (arrow, end),
+            _ => return Err(malformed())
+        };
+        let mut names = Names::new();
+        let mut head = Vec::with_capacity(arrow);
+        for arg in rest[..arrow].iter() {
+            head.push(try!(names.term(arg, expr)));
+        }
+        let mut body = Vec::with_capacity(end - arrow - 1);
+        for goal in rest[arrow + 1..end].iter() {
+            body.push(try!(names.term(goal, expr)));
+        }
+        clauses.push(Clause { head: head, body: body, vars: names.count });
+        rest = &rest[end + 1..];
+    }
+    if clauses.is_empty() || clauses.iter().any(|clause| clause.head.len() != clauses[0].head.len()) {
+        return Err(malformed())
+    }
+    Ok((name, clauses))
+}
+
+fn renamed(term: &Term, offset: usize) -> Term {
+    match *term {
+        Term::Var(var) => Term::Var(var + offset),
+        Term::Atom(_) => term.clone(),
+        Term::Compound(name, ref args) => Term::Compound(name, args.iter().map(|arg| renamed(arg, offset)).collect())
+    }
+}
+
+// Lists and tuples become the compound terms they're written as
+fn from_value(value: &Value) -> Term {
+    match *value {
+        Value::Cons(ref cons) => {
+            Term::Compound(symbol::intern("cons"), vec![from_value(&cons.head), from_value(&cons.tail)])
+        }
+        Value::Tuple(ref tuple) => {
+            Term::Compound(symbol::intern("@p"), vec![from_value(&tuple.first), from_value(&tuple.second)])
+        }
+        _ => Term::Atom(value.clone())
+    }
+}
+
+// The goals left to prove, each with the height the choice stack is cut
+// back to by a ! among them
+struct Goal {
+    goal: Term,
+    cut: usize,
+    next: Goals,
+}
+
+type Goals = Option<Rc<Goal>>;
+
+fn push_goals(goals: &[Term], cut: usize, next: Goals) -> Goals {
+    goals.iter().rev().fold(next, |next, goal| Some(Rc::new(Goal { goal: goal.clone(), cut: cut, next: next })))
+}
+
+// A call with clauses left to try on backtracking
+struct Choice {
+    goal: Term,
+    next: Goals,
+    clauses: Rc<Vec<Clause>>,
+    clause: usize,
+    trail: usize,
+    cut: usize,
+}
+
+// Depth first search for a proof, recording each variable bound on a trail
+// so that backtracking can unbind it again
+struct Machine<'i> {
+    interpreter: &'i mut Interpreter,
+    bindings: Vec<Option<Term>>,
+    trail: Vec<usize>,
+    choices: Vec<Choice>,
+}
+
+impl<'i> Machine<'i> {
+    fn walk(&self, term: &Term) -> Term {
+        let mut term = term.clone();
+        loop {
+            term = match term {
+                Term::Var(var) => {
+                    match self.bindings[var] {
+                        Some(ref bound) => bound.clone(),
+                        None => return term
+                    }
+                }
+                _ => return term
+            }
+        }
+    }
+
+    fn bind(&mut self, var: usize, term: Term) {
+        self.bindings[var] = Some(term);
+        self.trail.push(var);
+    }
+
+    fn undo(&mut self, trail: usize) {
+        while self.trail.len() > trail {
+            let var = self.trail.pop().unwrap();
+            self.bindings[var] = None;
+        }
+    }
+
+    fn unify(&mut self, a: &Term, b: &Term) -> bool {
+        match (self.walk(a), self.walk(b)) {
+            (Term::Var(x), Term::Var(y)) if x == y => true,
+            (Term::Var(var), other) | (other, Term::Var(var)) => {
+                self.bind(var, other);
+                true
+            }
+            (Term::Atom(x), Term::Atom(y)) => x == y,
+            (Term::Compound(x, ref xs), Term::Compound(y, ref ys)) => {
+                x == y && xs.len() == ys.len() && xs.iter().zip(ys.iter()).all(|(x, y)| self.unify(x, y))
+            }
+            _ => false
+        }
+    }
+
+    // A term with its bound variables replaced, as a value.  A variable
+    // left unbound gives a symbol naming it.
+    fn value(&self, term: &Term) -> Value {
+        match self.walk(term) {
+            Term::Var(var) => Value::Symbol(symbol::intern(&*format!("_{}", var))),
+            Term::Atom(value) => value,
+            Term::Compound(name, ref args) if args.len() == 2 && name == symbol::intern("cons") => {
+                Value::cons(self.value(&args[0]), self.value(&args[1]))
+            }
+            Term::Compound(name, ref args) if args.len() == 2 && name == symbol::intern("@p") => {
+                Value::tuple(self.value(&args[0]), self.value(&args[1]))
+            }
+            Term::Compound(name, ref args) => {
+                let mut items = vec![Value::Symbol(name)];
+                items.extend(args.iter().map(|arg| self.value(arg)));
+                Value::from_vec(items)
+            }
+        }
+    }
+
+    // A term as code to evaluate, for the expressions given to is and when
+    fn code(&self, term: &Term) -> Value {
+        match self.walk(term) {
+            Term::Compound(name, ref args) => {
+                let mut items = vec![Value::Symbol(name)];
+                items.extend(args.iter().map(|arg| self.code(arg)));
+                Value::list(items)
+            }
+            term => self.value(&term)
+        }
+    }
+
+    fn eval(&mut self, term: &Term) -> Result<Value, EvalError> {
+        let code = self.code(term);
+        self.interpreter.eval(&code, &Env::new())
+    }
+
+    // Try the remaining clauses of the latest choice, backtracking further
+    // as choices run out.  None when there's nothing left to try.
+    fn backtrack(&mut self) -> Goals {
+        loop {
+            let (goal, clauses, clause, next, cut) = match self.choices.last_mut() {
+                None => return None,
+                Some(choice) => {
+                    choice.clause += 1;
+                    (choice.goal.clone(), choice.clauses.clone(), choice.clause - 1, choice.next.clone(), choice.cut)
+                }
+            };
+            let trail = self.choices.last().unwrap().trail;
+            self.undo(trail);
+            if clause >= clauses.len() {
+                self.choices.pop();
+                continue
+            }
+            let clause = &clauses[clause];
+            let offset = self.bindings.len();
+            self.bindings.extend((0..clause.vars).map(|_| None));
+            let args = match goal {
+                Term::Compound(_, args) => args,
+                _ => Vec::new()
+            };
+            let matched = args.len() == clause.head.len() &&
+                args.iter().zip(clause.head.iter()).all(|(arg, head)| self.unify(arg, &renamed(head, offset)));
+            if matched {
+                let body: Vec<Term> = clause.body.iter().map(|goal| renamed(goal, offset)).collect();
+                // An empty body succeeds, leaving the goals after the call.
+                // With none left, true stands in so the proof isn't taken
+                // for a failure.
+                return Some(push_goals(&*body, cut, next).unwrap_or_else(|| Rc::new(Goal {
+                    goal: Term::Atom(Value::Bool(true)),
+                    cut: cut,
+                    next: None
+                })))
+            }
+        }
+    }
+
+    // Prove the goals, giving the value of the first return reached, true
+    // if they're proved without one or false if they can't be
+    fn run(&mut self, goals: Goals) -> Result<Value, EvalError> {
+        let mut goals = goals;
+        loop {
+            let node = match goals {
+                Some(node) => node,
+                None => return Ok(Value::Bool(true))
+            };
+            let proved = match self.walk(&node.goal) {
+                Term::Atom(Value::Bool(true)) => true,
+                Term::Atom(Value::Symbol(name)) if name == symbol::intern("!") => {
+                    self.choices.truncate(node.cut);
+                    true
+                }
+                Term::Compound(name, ref args) if name == symbol::intern("return") && args.len() == 1 => {
+                    return Ok(self.value(&args[0]))
+                }
+                Term::Compound(name, ref args) if name == symbol::intern("is") && args.len() == 2 => {
+                    let value = try!(self.eval(&args[1]));
+                    self.unify(&args[0], &from_value(&value))
+                }
+                Term::Compound(name, ref args) if name == symbol::intern("when") && args.len() == 1 => {
+                    try!(self.eval(&args[0])) == Value::Bool(true)
+                }
+                Term::Compound(name, ref args) if name == symbol::intern("unify") && args.len() == 2 => {
+                    self.unify(&args[0], &args[1])
+                }
+                Term::Compound(name, _) if name == symbol::intern("fail") => false,
+                Term::Compound(name, _) => {
+                    let clauses = match self.interpreter.predicate(name) {
+                        Some(clauses) => clauses,
+                        None => return Err(EvalError::UndefinedFunction(name))
+                    };
+                    let choice = Choice {
+                        goal: node.goal.clone(),
+                        next: node.next.clone(),
+                        clauses: clauses,
+                        clause: 0,
+                        trail: self.trail.len(),
+                        cut: self.choices.len(),
+                    };
+                    self.choices.push(choice);
+                    goals = self.backtrack();
+                    if goals.is_none() {
+                        return Ok(Value::Bool(false))
+                    }
+                    continue
+                }
+                ref goal => return Err(EvalError::Malformed(symbol::PROLOG, self.value(goal)))
+            };
+            goals = if proved { node.next.clone() } else { self.backtrack() };
+            if !proved && goals.is_none() {
+                return Ok(Value::Bool(false))
+            }
+        }
+    }
+}
+
+impl Interpreter {
+    // (prolog? Goals...) proves the goals in turn.  (receive X) gives the
+    // query variable X the value of the local variable of that name.
+    pub fn prolog(&mut self, expr: &Value, items: &[Value], env: &Env) -> Result<Value, EvalError> {
+        let mut names = Names::new();
+        let mut goals = Vec::new();
+        let mut received = Vec::new();
+        for item in items[1..].iter() {
+            match *item {
+                Value::List(ref goal) if goal.len() == 2 && goal[0] == Value::Symbol(symbol::intern("receive")) => {
+                    let name = match goal[1] {
+                        Value::Symbol(name) => name,
+                        _ => return Err(EvalError::Malformed(symbol::PROLOG, expr.clone()))
+                    };
+                    let value = match env.lookup(name) {
+                        Some(value) => value.clone(),
+                        None => return Err(EvalError::Unbound(name))
+                    };
+                    received.push((try!(names.term(&goal[1], expr)), from_value(&value)));
+                }
+                _ => goals.push(try!(names.term(item, expr)))
+            }
+        }
+
+        let mut machine = Machine {
+            interpreter: self,
+            bindings: (0..names.count).map(|_| None).collect(),
+            trail: Vec::new(),
+            choices: Vec::new(),
+        };
+        for &(ref var, ref value) in received.iter() {
+            machine.unify(var, value);
+        }
+        machine.run(push_goals(&*goals, 0, None))
+    }
+}
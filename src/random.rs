@@ -0,0 +1,46 @@
+// A small pseudo-random number generator, xorshift64*, which is fast and
+// good enough for simulations though not for cryptography.  The same seed
+// always gives the same sequence, so runs can be reproduced.
+pub struct Random {
+    state: u64,
+}
+
+// Seed used until another is set
+pub const DEFAULT_SEED: u64 = 0x2545F4914F6CDD1D;
+
+impl Random {
+    pub fn new(seed: u64) -> Random {
+        // Scramble the seed with splitmix64, so that similar seeds start
+        // far apart and a seed of 0 doesn't leave the state stuck at 0
+        let mut z = seed.wrapping_add(0x9E3779B97F4A7C15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+        z = z ^ (z >> 31);
+        Random { state: if z == 0 { DEFAULT_SEED } else { z } }
+    }
+
+    pub fn next(&mut self) -> u64 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        self.state.wrapping_mul(0x2545F4914F6CDD1D)
+    }
+
+    // Uniformly distributed below the bound, which must be positive.  Draws
+    // from the top of the range which would favour small numbers are
+    // rejected.
+    pub fn below(&mut self, bound: u64) -> u64 {
+        let limit = !0u64 - (!0u64 % bound + 1) % bound;
+        loop {
+            let n = self.next();
+            if n <= limit {
+                return n % bound
+            }
+        }
+    }
+
+    // Uniformly distributed from 0 up to but not including 1
+    pub fn float(&mut self) -> f64 {
+        (self.next() >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
@@ -0,0 +1,1361 @@
+use std::ascii::AsciiExt;
+use std::char;
+use std::cmp;
+use std::collections::HashMap;
+use std::fmt;
+use std::mem;
+use std::old_io::{Buffer, IoError, IoErrorKind};
+use std::str::Chars;
+use num::bigint::BigInt;
+use num::Zero;
+
+use {Node, SymbolicExpr};
+use number::{self, Number};
+use symbol;
+
+static DEFAULT_ATOM_SIZE: usize = 32;
+
+// Deep enough for any hand written code while bounding memory use on
+// hostile input
+pub static DEFAULT_MAX_DEPTH: usize = 10000;
+
+// ASCII punctuation Shen allows in symbols alongside letters and digits.  The
+// '|' is included so it reads as a symbol for the [H | T] list syntax.
+static SYMBOL_PUNCTUATION: &'static str = "=-*/+_?$!@~.><&%'#`;:|";
+
+// Location of a character in the source, lines and columns counting from 1
+#[derive(Clone, Copy, PartialEq)]
+pub struct Position {
+    pub offset: usize,
+    pub line: usize,
+    pub column: usize,
+}
+
+impl Position {
+    pub fn new() -> Position {
+        Position { offset: 0, line: 1, column: 1 }
+    }
+
+    // Position of the character following c
+    fn advance(self, c: char) -> Position {
+        if c == '\n' {
+            Position { offset: self.offset + c.len_utf8(), line: self.line + 1, column: 1 }
+        } else {
+            Position { offset: self.offset + c.len_utf8(), line: self.line, column: self.column + 1 }
+        }
+    }
+}
+
+// Half open region of the source a node was read from
+#[derive(Clone, Copy, PartialEq)]
+pub struct Span {
+    pub start: Position,
+    pub end: Position,
+}
+
+impl Span {
+    pub fn new(start: Position, end: Position) -> Span {
+        Span { start: start, end: end }
+    }
+
+    // The line of the code the span starts on, numbered in a margin, with
+    // carets under the span beneath it, as far as the line's end:
+    //
+    //   3 | (define f X -> (g X)
+    //     | ^^^^^^^^^^^^^^^^^^^^
+    pub fn excerpt(&self, code: &str) -> String {
+        let start = cmp::min(self.start.offset, code.len());
+        let line_start = code[..start].rfind('\n').map(|i| i + 1).unwrap_or(0);
+        let line_end = code[start..].find('\n').map(|i| start + i).unwrap_or(code.len());
+        let line = code[line_start..line_end].trim_right_matches('\r');
+        let number = self.start.line.to_string();
+        let margin: String = number.chars().map(|_| ' ').collect();
+        // Tabs are kept so the carets line up however wide they're shown
+        let indent: String = line[..cmp::min(start - line_start, line.len())].chars()
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect();
+        let rest = line.chars().count() - indent.chars().count();
+        let width = if self.end.line == self.start.line && self.end.column > self.start.column {
+            cmp::min(self.end.column - self.start.column, rest)
+        } else {
+            rest
+        };
+        let carets: String = (0..cmp::max(width, 1)).map(|_| '^').collect();
+        format!("{} | {}\n{} | {}{}", number, line, margin, indent, carets)
+    }
+}
+
+impl fmt::Display for Span {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}", self.start.line, self.start.column)
+    }
+}
+
+pub enum ParseError {
+    // A ')' with no list open
+    UnexpectedClose(Span, char),
+    // End of input with a list still open, spanning from its '('
+    UnterminatedList(Span, char),
+    // A list closed with the wrong kind of bracket
+    MismatchedClose(Span, char),
+    // A '|' which isn't second to last in a [...] list
+    MisplacedBar(Span),
+    // A '.' which isn't second to last in a dotted (...) list
+    MisplacedDot(Span),
+    // End of input inside a block comment
+    UnterminatedComment(Span),
+    // The lexeme looked like a number but isn't one
+    MalformedNumber(Span, String),
+    // A rational literal with a zero denominator
+    ZeroDenominator(Span, String),
+    // Reader sugar such as ' with no form following it
+    DanglingPrefix(Span, String),
+    // A registered reader macro rejected the form it was given
+    ReaderMacro(Span, String),
+    // A character which can't appear outside of a comment
+    InvalidCharacter(Span, char),
+    // End of input inside a string literal, spanning from its opening quote
+    UnterminatedString(Span),
+    // An unknown or malformed \ escape in a string literal
+    InvalidEscape(Span),
+    // A #\ character literal with an unknown name
+    UnknownCharacter(Span, String),
+    // Lists nested deeper than the reader's limit
+    DepthExceeded(Span, usize),
+}
+
+impl ParseError {
+    pub fn span(&self) -> Span {
+        match *self {
+            ParseError::UnexpectedClose(span, _) |
+            ParseError::UnterminatedList(span, _) |
+            ParseError::MismatchedClose(span, _) |
+            ParseError::MisplacedBar(span) |
+            ParseError::MisplacedDot(span) |
+            ParseError::UnterminatedComment(span) |
+            ParseError::MalformedNumber(span, _) |
+            ParseError::ZeroDenominator(span, _) |
+            ParseError::DanglingPrefix(span, _) |
+            ParseError::ReaderMacro(span, _) |
+            ParseError::InvalidCharacter(span, _) |
+            ParseError::UnterminatedString(span) |
+            ParseError::InvalidEscape(span) |
+            ParseError::UnknownCharacter(span, _) |
+            ParseError::DepthExceeded(span, _) => span
+        }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ParseError::UnexpectedClose(span, ')') => write!(f, "{}: Missing '('", span),
+            ParseError::UnexpectedClose(span, _) => write!(f, "{}: Missing '['", span),
+            ParseError::UnterminatedList(span, open) => write!(f, "{}: Unmatched '{}'", span, open),
+            ParseError::MismatchedClose(span, close) => write!(f, "{}: Mismatched '{}'", span, close),
+            ParseError::MisplacedBar(span) => write!(f, "{}: Misplaced '|'", span),
+            ParseError::MisplacedDot(span) => write!(f, "{}: Misplaced '.'", span),
+            ParseError::UnterminatedComment(span) => write!(f, "{}: Unterminated comment", span),
+            ParseError::MalformedNumber(span, ref lexeme) => {
+                write!(f, "{}: Invalid number '{}'", span, lexeme)
+            }
+            ParseError::ZeroDenominator(span, ref lexeme) => {
+                write!(f, "{}: Zero denominator in '{}'", span, lexeme)
+            }
+            ParseError::DanglingPrefix(span, ref prefix) => {
+                write!(f, "{}: Nothing follows '{}'", span, prefix)
+            }
+            ParseError::ReaderMacro(span, ref message) => write!(f, "{}: {}", span, message),
+            ParseError::InvalidCharacter(span, c) => {
+                write!(f, "{}: Unexpected character '{}'", span, c.escape_default().collect::<String>())
+            }
+            ParseError::UnterminatedString(span) => write!(f, "{}: Unterminated string", span),
+            ParseError::InvalidEscape(span) => write!(f, "{}: Invalid escape sequence", span),
+            ParseError::UnknownCharacter(span, ref name) => {
+                write!(f, "{}: Unknown character '#\\{}'", span, name)
+            }
+            ParseError::DepthExceeded(span, limit) => {
+                write!(f, "{}: Nested more than {} levels deep", span, limit)
+            }
+        }
+    }
+}
+
+impl fmt::Debug for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
+
+#[derive(PartialEq, Copy)]
+enum State {
+    Start,
+    Symbol,
+    Integer,
+    IncompleteFloating,
+    Floating,
+    IncompleteExponent,
+    IncompleteSignedExponent,
+    Exponent,
+    IncompleteRational,
+    Rational,
+    IncompleteHex,
+    Hex,
+    IncompleteOctal,
+    Octal,
+    IncompleteBinary,
+    Binary,
+    Minus,
+    Comma,
+    Str,
+    StrEscape,
+    StrUnicodeOpen,
+    StrUnicode,
+    Hash,
+    CharStart,
+    Char,
+    Backslash,
+    LineComment,
+    BlockComment,
+    BlockCommentStar,
+}
+
+fn to_atom(state: State, accum: &str, span: Span) -> Result<SymbolicExpr, ParseError> {
+    match state {
+        State::Symbol => {
+            if accum == "true" {
+                Ok(SymbolicExpr::Bool(true))
+            } else if accum == "false" {
+                Ok(SymbolicExpr::Bool(false))
+            } else {
+                Ok(SymbolicExpr::Symbol(symbol::intern(accum)))
+            }
+        }
+        State::Minus | State::Hash | State::Backslash => {Ok(SymbolicExpr::Symbol(symbol::intern(accum)))}
+        State::Char => {
+            match char_named(accum) {
+                Some(c) => Ok(SymbolicExpr::Char(c)),
+                None => Err(ParseError::UnknownCharacter(span, accum.to_string()))
+            }
+        }
+        State::Integer => {
+            match accum.parse::<i64>() {
+                Some(i) => {
+                    Ok(SymbolicExpr::Int(i))
+                }
+                // Promote literals which don't fit in an i64
+                None => match accum.parse::<BigInt>() {
+                    Some(big) => Ok(SymbolicExpr::BigInt(big)),
+                    None => Err(ParseError::MalformedNumber(span, accum.to_string()))
+                }
+            }
+        }
+        State::Floating | State::Exponent => {
+            match accum.parse::<f64>() {
+                Some(x) => {
+                    Ok(SymbolicExpr::Float(x))
+                }
+                None => Err(ParseError::MalformedNumber(span, accum.to_string()))
+            }
+        }
+        State::Rational => {
+            let (numer, denom) = {
+                let mut parts = accum.split('/');
+                (parts.next().and_then(|n| n.parse::<BigInt>()),
+                 parts.next().and_then(|d| d.parse::<BigInt>()))
+            };
+            match (numer, denom) {
+                (Some(_), Some(ref d)) if d.is_zero() => Err(ParseError::ZeroDenominator(span, accum.to_string())),
+                (Some(n), Some(d)) => Ok(number::ratio(n, d).to_sexpr()),
+                _ => Err(ParseError::MalformedNumber(span, accum.to_string()))
+            }
+        }
+        State::Hex | State::Octal | State::Binary => {
+            let radix = match state {
+                State::Hex => 16,
+                State::Octal => 8,
+                _ => 2
+            };
+            // Skip the sign and the two character radix prefix
+            let negative = accum.starts_with("-");
+            let digits = if negative { &accum[3..] } else { &accum[2..] };
+            let mut value = 0i64;
+            for d in digits.chars() {
+                match d.to_digit(radix) {
+                    Some(n) => {
+                        if value > (std::i64::MAX - n as i64) / (radix as i64) {
+                            // Normalized as decimal literals are, so the most
+                            // negative i64 is still an Int
+                            return match BigInt::parse_bytes(digits.as_bytes(), radix) {
+                                Some(big) => Ok(Number::Big(if negative { -big } else { big }).normalize().to_sexpr()),
+                                None => Err(ParseError::MalformedNumber(span, accum.to_string()))
+                            }
+                        }
+                        value = value * (radix as i64) + (n as i64);
+                    }
+                    None => return Err(ParseError::MalformedNumber(span, accum.to_string()))
+                }
+            }
+            Ok(SymbolicExpr::Int(if negative { -value } else { value }))
+        }
+        // Only the incomplete number states remain
+        _ => Err(ParseError::MalformedNumber(span, accum.to_string()))
+    }
+}
+
+fn is_zero(accum: &str) -> bool {
+    accum == "0" || accum == "-0"
+}
+
+// Characters which are written by name in #\ literals
+static CHARACTER_NAMES: [(&'static str, char); 5] = [
+    ("space", ' '),
+    ("newline", '\n'),
+    ("tab", '\t'),
+    ("return", '\r'),
+    ("nul", '\0'),
+];
+
+fn char_named(name: &str) -> Option<char> {
+    let mut chars = name.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) => return Some(c),
+        _ => {}
+    }
+    CHARACTER_NAMES.iter().find(|&&(n, _)| n == name).map(|&(_, c)| c)
+}
+
+// Text of a character literal which reads back as the given character
+pub fn escape_char(c: char) -> String {
+    match CHARACTER_NAMES.iter().find(|&&(_, named)| named == c) {
+        Some(&(name, _)) => format!("#\\{}", name),
+        None => format!("#\\{}", c)
+    }
+}
+
+// Text of a string literal which reads back as the given string
+pub fn escape_string(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len() + 2);
+    escaped.push('"');
+    for c in text.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\t' => escaped.push_str("\\t"),
+            '\r' => escaped.push_str("\\r"),
+            _ if c.is_control() => escaped.push_str(&*format!("\\u{{{:x}}}", c as u32)),
+            _ => escaped.push(c)
+        }
+    }
+    escaped.push('"');
+    escaped
+}
+
+// Any letter or digit in any script may appear in a symbol, as may non-ASCII
+// punctuation such as mathematical operators, so long as it isn't a control
+// or whitespace character
+fn is_symbol_char(c: char) -> bool {
+    if (c as u32) < 0x80 {
+        c.is_alphanumeric() || SYMBOL_PUNCTUATION.chars().any(|p| p == c)
+    } else {
+        !c.is_whitespace() && !c.is_control()
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct ReaderOptions {
+    // Most lists and prefixes which may be open at once
+    pub max_depth: usize,
+    // Otherwise ASCII letters in symbols are read as lower case
+    pub case_sensitive: bool,
+    // Whether [...] lists are accepted
+    pub brackets: bool,
+    pub line_comments: bool,
+    pub block_comments: bool,
+    // Otherwise atoms which aren't valid numbers, such as 2x, are symbols
+    pub strict: bool,
+}
+
+impl ReaderOptions {
+    pub fn new() -> ReaderOptions {
+        ReaderOptions {
+            max_depth: DEFAULT_MAX_DEPTH,
+            case_sensitive: true,
+            brackets: true,
+            line_comments: true,
+            block_comments: true,
+            strict: true,
+        }
+    }
+}
+
+// Rewrites the form following a '#' dispatch sequence
+pub type ReaderMacro = Box<Fn(Node) -> Result<SymbolicExpr, String>>;
+
+// Reader macros keyed by the character after the '#', so #s(1 2) hands the
+// list (1 2) to the handler registered for 's'.  A '#' followed by any other
+// character is read as part of a symbol as usual.
+pub struct ReadTable {
+    dispatch: HashMap<char, ReaderMacro>,
+}
+
+impl ReadTable {
+    pub fn new() -> ReadTable {
+        ReadTable { dispatch: HashMap::new() }
+    }
+
+    pub fn register(&mut self, c: char, handler: ReaderMacro) {
+        self.dispatch.insert(c, handler);
+    }
+
+    pub fn unregister(&mut self, c: char) {
+        self.dispatch.remove(&c);
+    }
+
+    fn handler(&self, c: char) -> Option<&ReaderMacro> {
+        self.dispatch.get(&c)
+    }
+}
+
+// Delimiters and atoms recorded for the lossless mode, anything between
+// them in the source being whitespace or comments
+#[derive(Clone, Copy, PartialEq)]
+pub enum TokenKind {
+    Open,
+    Close,
+    Prefix,
+    Atom,
+}
+
+#[derive(Clone, Copy)]
+pub struct Token {
+    pub kind: TokenKind,
+    pub span: Span,
+}
+
+// What an entry on the parser's stack is waiting to complete
+#[derive(Clone, Copy, PartialEq)]
+enum Frame {
+    List,
+    // A [...] list, read as nested conses
+    Bracket,
+    // Reader sugar which wraps the next complete form, 'x reads as (quote x)
+    Prefix(&'static str, &'static str),
+    // A reader macro to apply to the next complete form
+    Dispatch(char),
+    // A #; datum comment, discarding the next complete form
+    Skip,
+}
+
+impl Frame {
+    fn is_list(&self) -> bool {
+        *self == Frame::List || *self == Frame::Bracket
+    }
+
+    // The source text which opened the frame
+    fn prefix(&self) -> String {
+        match *self {
+            Frame::List => "(".to_string(),
+            Frame::Bracket => "[".to_string(),
+            Frame::Prefix(prefix, _) => prefix.to_string(),
+            Frame::Dispatch(c) => format!("#{}", c),
+            Frame::Skip => "#;".to_string(),
+        }
+    }
+}
+
+// Non-recursive parser state machine, fed one character at a time
+struct Parser<'t> {
+    accum: String,
+    exprs: Vec<Node>,
+    stack: Vec<(Vec<Node>, Position, Frame)>,
+    state: State,
+    pos: Position,
+    start: Position,
+    table: Option<&'t ReadTable>,
+    options: ReaderOptions,
+    // Only recorded when reading losslessly
+    tokens: Option<Vec<Token>>,
+    // Leave symbols and unescaped strings empty for the caller to slice from
+    // the source rather than copying them
+    borrowed: bool,
+    // Value of a \u{...} escape being read and how many digits it has
+    codepoint: u32,
+    codepoint_digits: usize,
+}
+
+impl<'t> Parser<'t> {
+    fn new() -> Parser<'t> {
+        Parser::at(Position::new())
+    }
+
+    // A parser for input beginning part way through a source
+    fn at(pos: Position) -> Parser<'t> {
+        Parser {
+            accum: String::with_capacity(DEFAULT_ATOM_SIZE),
+            exprs: Vec::new(),
+            stack: Vec::new(),
+            state: State::Start,
+            pos: pos,
+            start: pos,
+            table: None,
+            options: ReaderOptions::new(),
+            tokens: None,
+            borrowed: false,
+            codepoint: 0,
+            codepoint_digits: 0,
+        }
+    }
+
+    fn dispatches(&self, c: char) -> bool {
+        match self.table {
+            Some(table) => table.handler(c).is_some(),
+            None => false
+        }
+    }
+
+    // Number of lists currently open
+    fn depth(&self) -> usize {
+        self.stack.len()
+    }
+
+    // Top level forms completed so far
+    fn take_forms(&mut self) -> Vec<Node> {
+        if self.stack.len() == 0 {
+            mem::replace(&mut self.exprs, Vec::new())
+        } else {
+            mem::replace(&mut self.stack[0].0, Vec::new())
+        }
+    }
+
+    // Terminate any atom in progress, adding it to the innermost list
+    fn complete_atom(&mut self) -> Result<(), ParseError> {
+        if self.state != State::Start {
+            let span = Span::new(self.start, self.pos);
+            if self.state == State::Symbol && !self.options.case_sensitive {
+                self.accum = self.accum.to_ascii_lowercase();
+            }
+            let sexpr = match self.state {
+                // Borrowed reads take the text of symbols from the source,
+                // except for the separators of cons lists, which are looked for
+                // by name once the list closes
+                State::Symbol | State::Minus | State::Hash | State::Backslash
+                    if self.borrowed && !["true", "false", "|", "."].contains(&&*self.accum) => {
+                    SymbolicExpr::Symbol(symbol::EMPTY)
+                }
+                state => {
+                    match to_atom(state, &*self.accum, span) {
+                        Err(ParseError::MalformedNumber(_, text)) if !self.options.strict => {
+                            SymbolicExpr::Symbol(symbol::intern(&*text))
+                        }
+                        result => try!(result)
+                    }
+                }
+            };
+            let (start, end) = (self.start, self.pos);
+            self.token(TokenKind::Atom, start, end);
+            try!(self.add_form(Node::new(sexpr, start, end)));
+            self.accum.clear();
+            self.state = State::Start;
+        }
+        Ok(())
+    }
+
+    // Add a complete form to the innermost list, first applying any prefix
+    // sugar or reader macros waiting on it
+    fn add_form(&mut self, node: Node) -> Result<(), ParseError> {
+        let mut node = node;
+        loop {
+            let frame = match self.stack.last() {
+                Some(&(_, _, frame)) if !frame.is_list() => frame,
+                _ => break
+            };
+            let (parent, open, _) = self.stack.pop().unwrap();
+            self.exprs = parent;
+            let end = node.span.end;
+            let expr = match frame {
+                Frame::Prefix(_, name) => {
+                    let head = Node::new(SymbolicExpr::Symbol(symbol::intern(name)), open, end);
+                    SymbolicExpr::ListExpr(vec![head, node])
+                }
+                Frame::Dispatch(c) => {
+                    let handler = self.table.and_then(|table| table.handler(c)).unwrap();
+                    match (**handler)(node) {
+                        Ok(expr) => expr,
+                        Err(message) => return Err(ParseError::ReaderMacro(Span::new(open, end), message))
+                    }
+                }
+                Frame::Skip => return Ok(()),
+                Frame::List | Frame::Bracket => unreachable!()
+            };
+            node = Node::new(expr, open, end);
+        }
+        self.exprs.push(node);
+        Ok(())
+    }
+
+    // Start waiting for the form to which prefix sugar applies
+    fn push_prefix(&mut self, prefix: &'static str, name: &'static str,
+                   end: Position) -> Result<(), ParseError> {
+        let open = self.start;
+        self.push_frame(Frame::Prefix(prefix, name), open, end)
+    }
+
+    // Open a list or prefix whose delimiter spans open to end
+    fn push_frame(&mut self, frame: Frame, open: Position, end: Position) -> Result<(), ParseError> {
+        if self.stack.len() >= self.options.max_depth {
+            return Err(ParseError::DepthExceeded(Span::new(open, end), self.options.max_depth))
+        }
+        self.token(if frame.is_list() { TokenKind::Open } else { TokenKind::Prefix }, open, end);
+        let exprs = mem::replace(&mut self.exprs, Vec::new());
+        self.stack.push((exprs, open, frame));
+        Ok(())
+    }
+
+    fn in_string(&self) -> bool {
+        match self.state {
+            State::Str | State::StrEscape | State::StrUnicodeOpen | State::StrUnicode => true,
+            _ => false
+        }
+    }
+
+    fn token(&mut self, kind: TokenKind, start: Position, end: Position) {
+        match self.tokens {
+            Some(ref mut tokens) => tokens.push(Token { kind: kind, span: Span::new(start, end) }),
+            None => {}
+        }
+    }
+
+    // Characters inside a string literal bypass the usual dispatch, with the
+    // unescaped text collected in accum
+    fn push_string(&mut self, c: char) -> Result<(), ParseError> {
+        let next = self.pos.advance(c);
+        let escape_error = ParseError::InvalidEscape(Span::new(self.pos, next));
+        match (self.state, c) {
+            (State::Str, '"') => {
+                let start = self.start;
+                // Without escapes the text is the source between the quotes,
+                // which borrowed reads take directly
+                let escaped = self.accum.len() != next.offset - start.offset - 2;
+                let text = if self.borrowed && !escaped { String::new() } else { self.accum.clone() };
+                self.accum.clear();
+                self.state = State::Start;
+                self.token(TokenKind::Atom, start, next);
+                try!(self.add_form(Node::new(SymbolicExpr::Str(text), start, next)));
+            }
+            (State::Str, '\\') => self.state = State::StrEscape,
+            (State::Str, _) => self.accum.push(c),
+
+            (State::StrEscape, 'u') => self.state = State::StrUnicodeOpen,
+            (State::StrEscape, _) => {
+                let unescaped = match c {
+                    'n' => '\n',
+                    't' => '\t',
+                    'r' => '\r',
+                    '0' => '\0',
+                    '"' | '\\' => c,
+                    _ => return Err(escape_error)
+                };
+                self.accum.push(unescaped);
+                self.state = State::Str;
+            }
+
+            (State::StrUnicodeOpen, '{') => {
+                self.codepoint = 0;
+                self.codepoint_digits = 0;
+                self.state = State::StrUnicode;
+            }
+            (State::StrUnicode, '}') if self.codepoint_digits > 0 => {
+                match char::from_u32(self.codepoint) {
+                    Some(unescaped) => self.accum.push(unescaped),
+                    None => return Err(escape_error)
+                }
+                self.state = State::Str;
+            }
+            (State::StrUnicode, _) if self.codepoint_digits < 6 && c.is_digit(16) => {
+                self.codepoint = self.codepoint * 16 + c.to_digit(16).unwrap() as u32;
+                self.codepoint_digits += 1;
+            }
+            _ => return Err(escape_error)
+        }
+        self.pos = next;
+        Ok(())
+    }
+
+    fn push(&mut self, c: char) -> Result<(), ParseError> {
+        if self.in_string() {
+            return self.push_string(c)
+        }
+
+        // The first character after #\ is taken literally, even a delimiter
+        if self.state == State::CharStart {
+            self.accum.push(c);
+            self.state = State::Char;
+            self.pos = self.pos.advance(c);
+            return Ok(())
+        }
+
+        // A ',' is only known to be unquote once it can't be the start of ',@'
+        if self.state == State::Comma {
+            self.state = State::Start;
+            let pos = self.pos;
+            if c == '@' {
+                try!(self.push_prefix(",@", "unquote-splicing", pos.advance(c)));
+                self.pos = pos.advance(c);
+                return Ok(())
+            }
+            try!(self.push_prefix(",", "unquote", pos));
+        }
+
+        // Any token begins at the first character read in the Start state
+        if self.state == State::Start {
+            self.start = self.pos;
+        }
+        let next = self.pos.advance(c);
+
+        match c {
+            // Whitespace which can only terminate atoms and line comments
+            _ if c.is_whitespace() => {
+                if self.state == State::LineComment {
+                    if c == '\n' {
+                        self.state = State::Start;
+                    }
+                } else if self.state == State::BlockComment || self.state == State::BlockCommentStar {
+                    self.state = State::BlockComment;
+                } else {
+                    try!(self.complete_atom());
+                }
+            }
+
+            _ => {
+                match (self.state, c) {
+                    // Everything up to the end of the line is ignored
+                    (State::LineComment, _) => {}
+
+                    (State::Backslash, '\\') if self.options.line_comments => {
+                        self.state = State::LineComment;
+                        self.accum.clear();
+                    }
+
+                    // Block comments may span lines and contain parens, so they
+                    // are consumed here without touching the stack
+                    (State::Backslash, '*') if self.options.block_comments => {
+                        self.state = State::BlockComment;
+                        self.accum.clear();
+                    }
+
+                    (State::BlockComment, '*') | (State::BlockCommentStar, '*') => {
+                        self.state = State::BlockCommentStar;
+                    }
+
+                    (State::BlockCommentStar, '\\') => {
+                        self.state = State::Start;
+                    }
+
+                    (State::BlockComment, _) | (State::BlockCommentStar, _) => {
+                        self.state = State::BlockComment;
+                    }
+
+                    (State::Hash, '\\') => {
+                        self.accum.clear();
+                        self.state = State::CharStart;
+                    }
+
+                    (State::Hash, ';') => {
+                        let open = self.start;
+                        try!(self.push_frame(Frame::Skip, open, next));
+                        self.accum.clear();
+                        self.state = State::Start;
+                    }
+
+                    // Checked before parens so handlers may be registered for them
+                    (State::Hash, _) if self.dispatches(c) => {
+                        let open = self.start;
+                        try!(self.push_frame(Frame::Dispatch(c), open, next));
+                        self.accum.clear();
+                        self.state = State::Start;
+                    }
+
+                    // Strings delimit atoms like parens do
+                    (_, '"') => {
+                        try!(self.complete_atom());
+                        self.start = self.pos;
+                        self.state = State::Str;
+                    }
+
+                    // Braces enclosing type signatures read as symbols of their own
+                    (_, '{') | (_, '}') => {
+                        try!(self.complete_atom());
+                        let (start, end) = (self.pos, next);
+                        self.token(TokenKind::Atom, start, end);
+                        let name = if self.borrowed { symbol::EMPTY } else { symbol::intern(&*c.to_string()) };
+                        try!(self.add_form(Node::new(SymbolicExpr::Symbol(name), start, end)));
+                    }
+
+                    (_, '(') | (_, '[') if c == '(' || self.options.brackets => {
+                        try!(self.complete_atom());
+                        let (frame, open) = (if c == '(' { Frame::List } else { Frame::Bracket }, self.pos);
+                        try!(self.push_frame(frame, open, next));
+                    }
+
+                    (_, ')') | (_, ']') if c == ')' || self.options.brackets => {
+                        try!(self.complete_atom());
+                        let (open, frame) = match self.stack.last() {
+                            Some(&(_, open, frame)) => (open, frame),
+                            None => return Err(ParseError::UnexpectedClose(Span::new(self.pos, next), c))
+                        };
+                        match (frame, c) {
+                            (Frame::List, ')') | (Frame::Bracket, ']') => {}
+                            (Frame::List, _) | (Frame::Bracket, _) => {
+                                return Err(ParseError::MismatchedClose(Span::new(self.pos, next), c))
+                            }
+                            _ => return Err(ParseError::DanglingPrefix(Span::new(open, next), frame.prefix()))
+                        }
+
+                        let close = self.pos;
+                        self.token(TokenKind::Close, close, next);
+                        let (parent, _, _) = self.stack.pop().unwrap();
+                        let items = mem::replace(&mut self.exprs, Vec::new());
+                        self.exprs = parent;
+                        let list = if frame == Frame::Bracket {
+                            try!(cons_list(items, "|", open, next))
+                        } else if items.iter().any(|item| is_separator(item, ".")) {
+                            try!(cons_list(items, ".", open, next))
+                        } else {
+                            Node::new(SymbolicExpr::ListExpr(items), open, next)
+                        };
+                        try!(self.add_form(list));
+                    }
+
+                    (State::Start, '\'') => {
+                        try!(self.push_prefix("'", "quote", next));
+                    }
+
+                    (State::Start, '`') => {
+                        try!(self.push_prefix("`", "quasiquote", next));
+                    }
+
+                    (State::Start, ',') => {
+                        self.state = State::Comma;
+                    }
+
+                    (State::Start, '#') => {
+                        self.state = State::Hash;
+                        self.accum.push(c);
+                    }
+
+                    (State::Start, '\\') => {
+                        self.state = State::Backslash;
+                        self.accum.push(c);
+                    }
+
+                    (State::Start, '0' ... '9') | (State::Minus, '0' ... '9') => {
+                        self.state = State::Integer;
+                        self.accum.push(c);
+                    }
+
+                    // A lone '-' is the subtraction symbol, '-' followed by digits a number
+                    (State::Start, '-') => {
+                        self.state = State::Minus;
+                        self.accum.push(c);
+                    }
+
+                    (State::Start, _) if is_symbol_char(c) => {
+                        self.state = State::Symbol;
+                        self.accum.push(c);
+                    }
+
+                    (State::Start, _) => {
+                        return Err(ParseError::InvalidCharacter(Span::new(self.pos, next), c))
+                    }
+
+                    (State::Integer, '.') => {
+                        self.state = State::IncompleteFloating;
+                        self.accum.push(c);
+                    }
+
+                    (State::IncompleteFloating, '0' ... '9') => {
+                        self.state = State::Floating;
+                        self.accum.push(c);
+                    }
+
+                    (State::Integer, '0' ... '9') | (State::Floating, '0' ... '9') => {
+                        self.accum.push(c);
+                    }
+
+                    (State::Integer, 'e') | (State::Integer, 'E') |
+                    (State::Floating, 'e') | (State::Floating, 'E') => {
+                        self.state = State::IncompleteExponent;
+                        self.accum.push(c);
+                    }
+
+                    (State::IncompleteExponent, '+') | (State::IncompleteExponent, '-') => {
+                        self.state = State::IncompleteSignedExponent;
+                        self.accum.push(c);
+                    }
+
+                    // Radix prefixes are only valid directly after a leading zero
+                    (State::Integer, 'x') if is_zero(&*self.accum) => {
+                        self.state = State::IncompleteHex;
+                        self.accum.push(c);
+                    }
+
+                    (State::Integer, 'o') if is_zero(&*self.accum) => {
+                        self.state = State::IncompleteOctal;
+                        self.accum.push(c);
+                    }
+
+                    (State::Integer, 'b') if is_zero(&*self.accum) => {
+                        self.state = State::IncompleteBinary;
+                        self.accum.push(c);
+                    }
+
+                    (State::IncompleteHex, _) | (State::Hex, _) if c.is_digit(16) => {
+                        self.state = State::Hex;
+                        self.accum.push(c);
+                    }
+
+                    (State::IncompleteOctal, _) | (State::Octal, _) if c.is_digit(8) => {
+                        self.state = State::Octal;
+                        self.accum.push(c);
+                    }
+
+                    (State::IncompleteBinary, _) | (State::Binary, _) if c.is_digit(2) => {
+                        self.state = State::Binary;
+                        self.accum.push(c);
+                    }
+
+                    (State::Integer, '/') => {
+                        self.state = State::IncompleteRational;
+                        self.accum.push(c);
+                    }
+
+                    (State::IncompleteRational, '0' ... '9') | (State::Rational, '0' ... '9') => {
+                        self.state = State::Rational;
+                        self.accum.push(c);
+                    }
+
+                    (State::IncompleteExponent, '0' ... '9') |
+                    (State::IncompleteSignedExponent, '0' ... '9') |
+                    (State::Exponent, '0' ... '9') => {
+                        self.state = State::Exponent;
+                        self.accum.push(c);
+                    }
+
+                    (State::Integer, _) | (State::Floating, _) | (State::IncompleteFloating, _) |
+                    (State::IncompleteExponent, _) | (State::IncompleteSignedExponent, _) |
+                    (State::Exponent, _) | (State::IncompleteRational, _) | (State::Rational, _) |
+                    (State::IncompleteHex, _) | (State::Hex, _) |
+                    (State::IncompleteOctal, _) | (State::Octal, _) |
+                    (State::IncompleteBinary, _) | (State::Binary, _) => {
+                        self.accum.push(c);
+                        if !self.options.strict && is_symbol_char(c) {
+                            self.state = State::Symbol;
+                            self.pos = next;
+                            return Ok(())
+                        }
+                        return Err(ParseError::MalformedNumber(Span::new(self.start, next), self.accum.clone()))
+                    }
+
+                    (State::Symbol, _) | (State::Minus, _) |
+                    (State::Hash, _) | (State::Backslash, _) if is_symbol_char(c) => {
+                        self.state = State::Symbol;
+                        self.accum.push(c);
+                    }
+
+                    (State::Char, _) if is_symbol_char(c) => {
+                        self.accum.push(c);
+                    }
+
+                    (State::Symbol, _) | (State::Minus, _) | (State::Hash, _) |
+                    (State::Backslash, _) | (State::Char, _) => {
+                        return Err(ParseError::InvalidCharacter(Span::new(self.pos, next), c))
+                    }
+
+                    // Resolved before dispatching on the character
+                    (State::Comma, _) | (State::CharStart, _) | (State::Str, _) | (State::StrEscape, _) |
+                    (State::StrUnicodeOpen, _) | (State::StrUnicode, _) => unreachable!()
+                }
+            }
+        }
+
+
+        self.pos = next;
+        Ok(())
+    }
+
+    // Check the input didn't end part way through a comment, atom or list
+    fn finish(&mut self) -> Result<(), ParseError> {
+        if self.state == State::BlockComment || self.state == State::BlockCommentStar {
+            return Err(ParseError::UnterminatedComment(Span::new(self.start, self.pos)))
+        }
+
+        if self.in_string() {
+            return Err(ParseError::UnterminatedString(Span::new(self.start, self.pos)))
+        }
+
+        if self.state == State::CharStart {
+            return Err(ParseError::UnknownCharacter(Span::new(self.start, self.pos), String::new()))
+        }
+
+        if self.state == State::Comma {
+            return Err(ParseError::DanglingPrefix(Span::new(self.start, self.pos), ",".to_string()))
+        }
+
+        if self.state == State::LineComment {
+            self.state = State::Start;
+        }
+        try!(self.complete_atom());
+
+        match self.stack.last() {
+            None => Ok(()),
+            Some(&(_, open, Frame::List)) => {
+                Err(ParseError::UnterminatedList(Span::new(open, self.pos), '('))
+            }
+            Some(&(_, open, Frame::Bracket)) => {
+                Err(ParseError::UnterminatedList(Span::new(open, self.pos), '['))
+            }
+            Some(&(_, open, frame)) => {
+                Err(ParseError::DanglingPrefix(Span::new(open, self.pos), frame.prefix()))
+            }
+        }
+    }
+}
+
+fn is_separator(node: &Node, separator: &str) -> bool {
+    match node.expr {
+        SymbolicExpr::Symbol(s) => s == symbol::intern(separator),
+        _ => false
+    }
+}
+
+// [a b] reads as (cons a (cons b ())) and [a b | t] as (cons a (cons b t)),
+// as does the dotted list (a b . t) with a '.' separator
+fn cons_list(items: Vec<Node>, separator: &str, open: Position, end: Position) -> Result<Node, ParseError> {
+    let mut items = items;
+    let mut tail = match items.iter().position(|item| is_separator(item, separator)) {
+        None => Node::new(SymbolicExpr::ListExpr(Vec::new()), open, end),
+        Some(i) if i > 0 && i + 2 == items.len() => {
+            let tail = items.pop().unwrap();
+            items.pop();
+            tail
+        }
+        Some(i) if separator == "|" => return Err(ParseError::MisplacedBar(items[i].span)),
+        Some(i) => return Err(ParseError::MisplacedDot(items[i].span))
+    };
+
+    while let Some(item) = items.pop() {
+        let start = item.span.start;
+        let cons = Node::new(SymbolicExpr::Symbol(symbol::intern("cons")), start, start);
+        tail = Node::new(SymbolicExpr::ListExpr(vec![cons, item, tail]), start, end);
+    }
+    tail.span.start = open;
+    Ok(tail)
+}
+
+fn feed(parser: &mut Parser, code: &str) -> Result<(), ParseError> {
+    for c in code.chars() {
+        try!(parser.push(c));
+    }
+    parser.finish()
+}
+
+// Reads code according to its options and read table, e.g.
+// Reader::new().max_depth(100).brackets(false).read(code)
+pub struct Reader<'t> {
+    options: ReaderOptions,
+    table: Option<&'t ReadTable>,
+}
+
+impl<'t> Reader<'t> {
+    pub fn new() -> Reader<'t> {
+        Reader { options: ReaderOptions::new(), table: None }
+    }
+
+    pub fn options(mut self, options: ReaderOptions) -> Reader<'t> {
+        self.options = options;
+        self
+    }
+
+    pub fn max_depth(mut self, max_depth: usize) -> Reader<'t> {
+        self.options.max_depth = max_depth;
+        self
+    }
+
+    pub fn case_sensitive(mut self, case_sensitive: bool) -> Reader<'t> {
+        self.options.case_sensitive = case_sensitive;
+        self
+    }
+
+    pub fn brackets(mut self, brackets: bool) -> Reader<'t> {
+        self.options.brackets = brackets;
+        self
+    }
+
+    pub fn line_comments(mut self, line_comments: bool) -> Reader<'t> {
+        self.options.line_comments = line_comments;
+        self
+    }
+
+    pub fn block_comments(mut self, block_comments: bool) -> Reader<'t> {
+        self.options.block_comments = block_comments;
+        self
+    }
+
+    pub fn strict(mut self, strict: bool) -> Reader<'t> {
+        self.options.strict = strict;
+        self
+    }
+
+    // Dispatch '#' to the reader macros in the table
+    pub fn table(mut self, table: &'t ReadTable) -> Reader<'t> {
+        self.table = Some(table);
+        self
+    }
+
+    fn parser(&self) -> Parser<'t> {
+        let mut parser = Parser::new();
+        parser.options = self.options;
+        parser.table = self.table;
+        parser
+    }
+
+    pub fn read(&self, code: &str) -> Result<Vec<Node>, ParseError> {
+        let mut parser = self.parser();
+        try!(feed(&mut parser, code));
+        Ok(parser.take_forms())
+    }
+
+    // Read the first form in the code, returning it along with the input
+    // following it, or None if the code holds no forms.  Anything after the
+    // form isn't read, so needn't be valid.
+    pub fn parse_one<'c>(&self, code: &'c str) -> Result<Option<(Node, &'c str)>, ParseError> {
+        let mut parser = self.parser();
+        let mut chars = code.chars();
+        loop {
+            let (result, done) = match chars.next() {
+                Some(c) => (parser.push(c), false),
+                None => (parser.finish(), true)
+            };
+            // A form may be completed by the character which then fails
+            if parser.depth() == 0 && parser.exprs.len() > 0 {
+                let form = parser.exprs.remove(0);
+                let rest = &code[form.span.end.offset..];
+                return Ok(Some((form, rest)))
+            }
+            try!(result);
+            if done {
+                return Ok(None)
+            }
+        }
+    }
+
+    // Read input which may be continued by further lines, such as at a
+    // prompt, distinguishing incomplete input from input which can never be valid
+    pub fn read_partial(&self, code: &str) -> Result<ParseOutcome, ParseError> {
+        let mut parser = self.parser();
+        for c in code.chars() {
+            try!(parser.push(c));
+        }
+        if parser.depth() > 0 || parser.state == State::Comma || parser.in_string() ||
+           parser.state == State::BlockComment || parser.state == State::BlockCommentStar {
+            return Ok(ParseOutcome::NeedMore)
+        }
+        try!(parser.finish());
+        Ok(ParseOutcome::Complete(parser.take_forms()))
+    }
+}
+
+pub fn read(code: &str) -> Result<Vec<Node>, ParseError> {
+    Reader::new().read(code)
+}
+
+// Read with limits suitable for the source of the code
+pub fn read_with_options(code: &str, options: ReaderOptions) -> Result<Vec<Node>, ParseError> {
+    Reader::new().options(options).read(code)
+}
+
+// Read recording the position of every delimiter and atom as well as the forms
+pub fn read_tokens(code: &str) -> Result<(Vec<Node>, Vec<Token>), ParseError> {
+    let mut parser = Parser::new();
+    parser.tokens = Some(Vec::new());
+    try!(feed(&mut parser, code));
+    let forms = parser.take_forms();
+    Ok((forms, parser.tokens.unwrap_or(Vec::new())))
+}
+
+// Read leaving the text of symbols and unescaped strings empty, see borrowed
+pub fn read_unsliced(code: &str) -> Result<Vec<Node>, ParseError> {
+    let mut parser = Parser::new();
+    parser.borrowed = true;
+    try!(feed(&mut parser, code));
+    Ok(parser.take_forms())
+}
+
+// Read with '#' dispatch to the reader macros in the table
+pub fn read_with_table(code: &str, table: &ReadTable) -> Result<Vec<Node>, ParseError> {
+    Reader::new().table(table).read(code)
+}
+
+pub enum ParseOutcome {
+    Complete(Vec<Node>),
+    // The input is valid so far but ends inside a list or comment
+    NeedMore,
+}
+
+pub fn parse_one(code: &str) -> Result<Option<(Node, &str)>, ParseError> {
+    Reader::new().parse_one(code)
+}
+
+pub fn read_partial(code: &str) -> Result<ParseOutcome, ParseError> {
+    Reader::new().read_partial(code)
+}
+
+pub enum LineReadError {
+    // The input ended before any form was read
+    EndOfInput,
+    Io(IoError),
+    Parse(ParseError),
+}
+
+impl fmt::Display for LineReadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            LineReadError::EndOfInput => f.write_str("End of input"),
+            LineReadError::Io(ref e) => write!(f, "{}", e),
+            LineReadError::Parse(ref e) => write!(f, "{}", e),
+        }
+    }
+}
+
+// Shen's lineread, reading whole lines of input until they hold at least one
+// complete form and returning every form they hold.  Lines holding only
+// whitespace or comments are skipped.
+pub fn lineread<B: Buffer>(input: &mut B) -> Result<Vec<Node>, LineReadError> {
+    let mut code = String::new();
+    loop {
+        match input.read_line() {
+            Ok(line) => code.push_str(&*line),
+            Err(ref e) if e.kind == IoErrorKind::EndOfFile => {
+                if code.chars().all(|c| c.is_whitespace()) {
+                    return Err(LineReadError::EndOfInput)
+                }
+                // Report whatever left the last form incomplete
+                return read(&*code).map_err(LineReadError::Parse)
+            }
+            Err(e) => return Err(LineReadError::Io(e))
+        }
+
+        match read_partial(&*code) {
+            Ok(ParseOutcome::Complete(forms)) => {
+                if forms.len() > 0 {
+                    return Ok(forms)
+                }
+                code.clear();
+            }
+            Ok(ParseOutcome::NeedMore) => {}
+            Err(e) => return Err(LineReadError::Parse(e))
+        }
+    }
+}
+
+// Lazily reads top level forms, yielding each as soon as it is complete
+pub struct ReadIter<'a> {
+    chars: Chars<'a>,
+    parser: Parser<'a>,
+    error: Option<ParseError>,
+    done: bool,
+}
+
+impl<'a> Iterator for ReadIter<'a> {
+    type Item = Result<Node, ParseError>;
+
+    fn next(&mut self) -> Option<Result<Node, ParseError>> {
+        loop {
+            // Forms completed before an error are still yielded first
+            if self.parser.depth() == 0 && self.parser.exprs.len() > 0 {
+                return Some(Ok(self.parser.exprs.remove(0)))
+            }
+            if let Some(e) = self.error.take() {
+                return Some(Err(e))
+            }
+            if self.done {
+                return None
+            }
+
+            let result = match self.chars.next() {
+                Some(c) => self.parser.push(c),
+                None => {
+                    self.done = true;
+                    self.parser.finish()
+                }
+            };
+            if let Err(e) = result {
+                self.error = Some(e);
+                self.done = true;
+            }
+        }
+    }
+}
+
+pub fn read_iter(code: &str) -> ReadIter {
+    ReadIter { chars: code.chars(), parser: Parser::new(), error: None, done: false }
+}
+
+// Where to pick up reading after an error.  A bad atom at the top level is
+// skipped up to the next delimiter, otherwise reading resumes at the next
+// '(' in the first column, which conventionally starts a new definition.
+fn resume_point(code: &str, error: &ParseError, depth: usize) -> Option<Position> {
+    let span = error.span();
+    let mut pos = span.start;
+    let mut skipping_atom = false;
+    match *error {
+        ParseError::UnterminatedList(_, _) | ParseError::UnterminatedComment(_) => {}
+        _ if depth == 0 => {
+            pos = span.end;
+            skipping_atom = true;
+        }
+        _ => {}
+    }
+
+    let mut prev = ' ';
+    for c in code[pos.offset..].chars() {
+        if skipping_atom {
+            match c {
+                '(' | ')' | '[' | ']' => return Some(pos),
+                _ if c.is_whitespace() => return Some(pos),
+                _ => {}
+            }
+        } else if c == '(' && prev == '\n' {
+            return Some(pos)
+        }
+        prev = c;
+        pos = pos.advance(c);
+    }
+    None
+}
+
+// Read as much of the code as possible, collecting every error rather than
+// stopping at the first
+pub fn read_with_recovery(code: &str) -> (Vec<Node>, Vec<ParseError>) {
+    let mut forms = Vec::new();
+    let mut errors = Vec::new();
+    let mut resume = Some(Position::new());
+
+    while let Some(from) = resume {
+        let mut parser = Parser::at(from);
+        let result = feed(&mut parser, &code[from.offset..]);
+        forms.extend(parser.take_forms().into_iter());
+        resume = match result {
+            Ok(()) => None,
+            Err(e) => {
+                let next = resume_point(code, &e, parser.depth());
+                errors.push(e);
+                next
+            }
+        };
+    }
+
+    (forms, errors)
+}
@@ -0,0 +1,475 @@
+use std::collections::HashMap;
+use std::old_io::{self, Writer};
+use std::old_io::fs::PathExtensions;
+use std::os;
+use std::process;
+use std::old_path::Path;
+use time;
+
+use color;
+use debugger::Debugger;
+use editor::Editor;
+use interrupt;
+use eval::{EvalError, Interpreter};
+use number::Number;
+use reader::{self, ParseError, ParseOutcome};
+use symbol::{self, SymbolId};
+use transcript;
+use types::Type;
+use value::Value;
+use {Node, SymbolicExpr};
+
+// File in the home directory the REPL's history is kept in
+const HISTORY_FILE: &'static str = ".burton_history";
+
+// Files of code loaded when the REPL starts, in the home directory, or else
+// in a shen directory of the XDG config directory
+const RC_FILE: &'static str = ".shenrc";
+const XDG_RC_FILE: &'static str = "shenrc";
+
+// Read forms from standard input and evaluate them, printing each value, and
+// its type when type checking, until the input ends or (quit) is entered.
+// Input is read a line at a time until the lines hold a complete form, so a
+// form may span lines.  A line starting with a colon is a command to the
+// REPL, given by the name after the colon, see COMMANDS.
+//
+// At a terminal C-c interrupts the evaluation under way, returning to the
+// prompt, and at the prompt discards the line being entered.  Otherwise it
+// ends the program as usual.
+//
+// When standard input isn't a terminal, as when code is piped in, there are
+// no prompts and errors go to standard error, so that only the values are
+// output.  The exit status is then 1 if there were any errors.
+//
+// Results can be used in later entries: it is the last value printed, and
+// %N the value of the entry numbered N at its prompt, the last of its forms
+// if it had several.  They're bound as variables around each entry, so they
+// aren't seen inside functions, and aren't bound until there's a value.
+//
+// The REPL can be set up with globals, set in the rc file or at the prompt:
+//
+//   *prompt*                        the prompt, with ~A standing for the
+//                                   number of the entry, as in "shen ~A> "
+//   *result-prefix*                 a string printed before each value
+//   *hush-banner*                   true to start without the banner
+//   *maximum-print-sequence-size*   the items of lists and vectors printed
+//                                   before the rest is left as ...
+pub fn run(interpreter: &mut Interpreter) {
+    let history = os::getenv("HOME").map(|home| Path::new(&*home).join(HISTORY_FILE));
+    let editor = Editor::new(history);
+    let mut session = Session {
+        interactive: editor.is_terminal(),
+        editor: editor,
+        failed: false,
+        count: 0,
+        results: HashMap::new(),
+        last: None,
+    };
+    interpreter.set_stepper(Box::new(Debugger::new()));
+    if session.interactive {
+        interrupt::install();
+        load_rc(interpreter);
+        if !Settings::of(interpreter).hush_banner {
+            outln!("burton {}, a Shen interpreter; :help lists the REPL's commands", env!("CARGO_PKG_VERSION"));
+        }
+    }
+    loop {
+        let prompt = match Settings::of(interpreter).prompt {
+            _ if !session.interactive => String::new(),
+            Some(prompt) => prompt.replace("~A", &*session.count.to_string()),
+            None => format!("({}{}) ", session.count, if interpreter.typechecking() { "+" } else { "-" })
+        };
+        session.editor.set_words(words(interpreter));
+        match read_entry(&mut session.editor, &*prompt) {
+            None if session.interactive => {
+                outln!("");
+                return
+            }
+            None => process::exit(if session.failed { 1 } else { 0 }),
+            Some(Ok(Entry::Forms(ref forms))) if forms.is_empty() => continue,
+            Some(Ok(Entry::Forms(forms))) => {
+                for form in forms.iter() {
+                    let code = Value::from_node(form);
+                    if is_quit(&code) {
+                        return
+                    }
+                    let result = session.eval(interpreter, &code);
+                    session.show(interpreter, result);
+                }
+            }
+            Some(Ok(Entry::Command(name, forms))) => {
+                match command(interpreter, &mut session, &*name, &*forms) {
+                    Ok(()) => {}
+                    Err(e) => session.show(interpreter, Err(e))
+                }
+            }
+            Some(Err(e)) => session.show(interpreter, Err(e))
+        }
+        session.count += 1;
+    }
+}
+
+// The state of a REPL session kept between entries
+struct Session {
+    editor: Editor,
+    // Whether input is from a terminal rather than a pipe
+    interactive: bool,
+    // Whether there's been an error, which ends a piped session with a
+    // status of 1
+    failed: bool,
+    // Number of the entry being read
+    count: usize,
+    // The value of each entry that had one, with its type if checked, and
+    // the last value
+    results: HashMap<usize, (Value, Option<Type>)>,
+    last: Option<(Value, Option<Type>)>,
+}
+
+impl Session {
+    // Evaluate code with it and %N bound to the results they name
+    fn eval(&self, interpreter: &mut Interpreter, code: &Value) -> Result<(Value, Option<Type>), EvalError> {
+        let mut names = Vec::new();
+        symbols(code, &mut names);
+        let variables: Vec<(SymbolId, Value, Option<Type>)> = names.into_iter().filter_map(|name| {
+            let text = name.to_string();
+            let result = if text == "it" {
+                self.last.as_ref()
+            } else if text.starts_with("%") {
+                text[1..].parse::<usize>().and_then(|n| self.results.get(&n))
+            } else {
+                None
+            };
+            result.map(|&(ref value, ref type_)| (name, value.clone(), type_.clone()))
+        }).collect();
+        interrupt::clear();
+        interpreter.eval_toplevel_with(code, &*variables)
+    }
+
+    // Print the result of evaluating, or in a pipe give errors on standard
+    // error.  An exit ends the program.
+    fn show(&mut self, interpreter: &Interpreter, result: Result<(Value, Option<Type>), EvalError>) {
+        match result {
+            Err(ref e) if is_exit(e) => process::exit(report(e.clone())),
+            Err(ref e) if !self.interactive => {
+                self.failed = true;
+                print_error(&*describe(e));
+            }
+            result => {
+                if let Ok(ref result) = result {
+                    self.results.insert(self.count, result.clone());
+                    self.last = Some(result.clone());
+                }
+                print_with(result, &Settings::of(interpreter))
+            }
+        }
+    }
+}
+
+// Add the symbols in code to those found, each once
+fn symbols(code: &Value, found: &mut Vec<SymbolId>) {
+    match *code {
+        Value::Symbol(name) if !found.contains(&name) => found.push(name),
+        Value::List(ref items) => {
+            for item in items.iter() {
+                symbols(item, found);
+            }
+        }
+        _ => {}
+    }
+}
+
+// How the REPL is set up by the globals described above, those not set
+// leaving it as it would be without them
+struct Settings {
+    prompt: Option<String>,
+    result_prefix: String,
+    hush_banner: bool,
+    print_limit: Option<usize>,
+}
+
+impl Settings {
+    fn new() -> Settings {
+        Settings { prompt: None, result_prefix: String::new(), hush_banner: false, print_limit: None }
+    }
+
+    fn of(interpreter: &Interpreter) -> Settings {
+        let global = |name| interpreter.global(symbol::intern(name)).ok();
+        let mut settings = Settings::new();
+        if let Some(Value::Str(prompt)) = global("*prompt*") {
+            settings.prompt = Some((*prompt).clone());
+        }
+        if let Some(Value::Str(prefix)) = global("*result-prefix*") {
+            settings.result_prefix = (*prefix).clone();
+        }
+        if let Some(Value::Bool(hush)) = global("*hush-banner*") {
+            settings.hush_banner = hush;
+        }
+        if let Some(Value::Num(Number::Int(limit))) = global("*maximum-print-sequence-size*") {
+            settings.print_limit = if limit >= 0 { Some(limit as usize) } else { None };
+        }
+        settings
+    }
+
+    fn print(&self, value: &Value) -> String {
+        let text = match self.print_limit {
+            Some(limit) => value.abbreviated(limit),
+            None => value.to_string()
+        };
+        format!("{}{}", self.result_prefix, color::value(&*text))
+    }
+}
+
+// Names to complete at the prompt: the functions and globals as they are
+// now, and the REPL's commands
+fn words(interpreter: &Interpreter) -> Vec<String> {
+    let mut words: Vec<String> = interpreter.function_names().iter().map(|name| name.to_string()).collect();
+    words.extend(interpreter.globals().iter().map(|&(name, _)| name.to_string()));
+    words.extend(COMMANDS.iter().map(|&(usage, _)| format!(":{}", usage.split(' ').next().unwrap())));
+    words
+}
+
+// What's entered at the prompt: forms to evaluate, or a command with the
+// forms following its name
+enum Entry {
+    Forms(Vec<Node>),
+    Command(String, Vec<Node>),
+}
+
+// Names of the REPL's commands, with what they take and do, for :help
+static COMMANDS: &'static [(&'static str, &'static str)] = &[
+    ("help", "list these commands"),
+    ("time Expr", "evaluate Expr, then give the time and the steps it took"),
+    ("step Expr", "evaluate Expr pausing at each application, in tracked functions if any"),
+    ("profile Expr", "evaluate Expr, then give the calls of each function and the time in them"),
+    ("stats", "give the symbols, functions and values the session holds"),
+    ("transcript on File", "write what's entered and shown from now on to File"),
+    ("transcript off", "stop writing the transcript"),
+];
+
+fn command(interpreter: &mut Interpreter, session: &mut Session, name: &str, forms: &[Node])
+           -> Result<(), EvalError> {
+    match name {
+        "help" => {
+            for &(usage, description) in COMMANDS.iter() {
+                outln!("  :{:<16} {}", usage, description);
+            }
+            Ok(())
+        }
+        "time" if forms.is_empty() => Err(EvalError::Simple(format!(":time needs an expression"))),
+        "time" => {
+            let start = time::precise_time_s();
+            let steps = interpreter.steps_taken();
+            for form in forms.iter() {
+                let result = session.eval(interpreter, &Value::from_node(form));
+                session.show(interpreter, result);
+            }
+            outln!("run time: {} secs, {} steps", time::precise_time_s() - start,
+                   interpreter.steps_taken() - steps);
+            Ok(())
+        }
+        "step" if forms.is_empty() => Err(EvalError::Simple(format!(":step needs an expression"))),
+        "step" => {
+            interpreter.set_stepping(true);
+            for form in forms.iter() {
+                let result = session.eval(interpreter, &Value::from_node(form));
+                session.show(interpreter, result);
+            }
+            interpreter.set_stepping(false);
+            Ok(())
+        }
+        "profile" if forms.is_empty() => Err(EvalError::Simple(format!(":profile needs an expression"))),
+        "profile" => {
+            interpreter.start_profiling();
+            for form in forms.iter() {
+                let result = session.eval(interpreter, &Value::from_node(form));
+                session.show(interpreter, result);
+            }
+            match interpreter.take_profile() {
+                Some(profile) => out!("{}", profile.report()),
+                None => {}
+            }
+            Ok(())
+        }
+        "stats" => {
+            for (name, n) in interpreter.stats().entries().into_iter() {
+                outln!("  {:<12} {:>12}", name, n);
+            }
+            Ok(())
+        }
+        "transcript" => {
+            // The file may be given as a string or a symbol
+            let words: Vec<Option<String>> = forms.iter().map(|form| match form.expr {
+                SymbolicExpr::Str(ref text) => Some(text.clone()),
+                SymbolicExpr::Symbol(name) => Some(name.to_string()),
+                _ => None
+            }).collect();
+            match (words.len(), words.get(0).and_then(|word| word.clone())) {
+                (2, Some(ref on)) if *on == "on" && words[1].is_some() => {
+                    let path = words[1].clone().unwrap();
+                    transcript::start(&*path).map_err(|e| EvalError::Simple(format!("{}: {}", path, e)))
+                }
+                (1, Some(ref off)) if *off == "off" => {
+                    if !transcript::stop() {
+                        outln!("no transcript is being written");
+                    }
+                    Ok(())
+                }
+                _ => Err(EvalError::Simple(format!("use :transcript on File or :transcript off")))
+            }
+        }
+        _ => Err(EvalError::Simple(format!("unknown command :{}, see :help", name)))
+    }
+}
+
+// Quietly load the first rc file there is, so users can define functions and
+// settings for every session.  An error is reported and the REPL goes on.
+fn load_rc(interpreter: &mut Interpreter) {
+    let home = os::getenv("HOME").map(|home| Path::new(&*home));
+    let config = os::getenv("XDG_CONFIG_HOME").map(|config| Path::new(&*config))
+        .or_else(|| home.as_ref().map(|home| home.join(".config")));
+    let candidates = vec![home.map(|home| home.join(RC_FILE)),
+                          config.map(|config| config.join("shen").join(XDG_RC_FILE))];
+    let rc = candidates.into_iter().filter_map(|path| path).find(|path| path.exists());
+    if let Some(path) = rc {
+        match interpreter.load_quietly(&*path.display().to_string()) {
+            Ok(_) => {}
+            Err(ref e) if is_exit(e) => process::exit(report(e.clone())),
+            Err(e) => { report(e); }
+        }
+    }
+}
+
+// Read lines until they hold complete forms, or None once the input ends.
+// Lines holding only whitespace or comments give no forms.  While a list,
+// string or comment is left open the lines after the first are prompted for
+// with dots in place of the prompt, as in
+//
+//   (0-) (define double
+//   ...    X -> (* 2 X))
+//
+// Forms which can't be read are given as an error with an excerpt of the
+// lines entered.
+fn read_entry(editor: &mut Editor, prompt: &str) -> Option<Result<Entry, EvalError>> {
+    // Without a prompt, as when reading from a pipe, lines aren't prompted for
+    let continuation = if prompt.is_empty() {
+        String::new()
+    } else {
+        color::prompt(&*format!("{:<1$}", "...", prompt.chars().count()))
+    };
+    let prompt = color::prompt(prompt);
+    let mut code = String::new();
+    loop {
+        let line = match editor.read_line(if code.is_empty() { &*prompt } else { &*continuation }) {
+            Ok(Some(line)) => line,
+            Ok(None) if code.trim().is_empty() => return None,
+            // Report whatever left the last form incomplete
+            Ok(None) => {
+                let (name, code) = split_command(&*code);
+                return Some(reader::read(code).map(|forms| entry(name, forms)).map_err(|e| unreadable(e, code)))
+            }
+            Err(e) => {
+                print_error(&*format!("{}\n", e));
+                return None
+            }
+        };
+        editor.add_history(&*line);
+        transcript::record(&*format!("{}{}\n", if code.is_empty() { &*prompt } else { &*continuation }, line));
+        code.push_str(&*line);
+        code.push('\n');
+        let (name, code) = split_command(&*code);
+        match reader::read_partial(code) {
+            Ok(ParseOutcome::Complete(forms)) => return Some(Ok(entry(name, forms))),
+            Ok(ParseOutcome::NeedMore) => {}
+            Err(e) => return Some(Err(unreadable(e, code)))
+        }
+    }
+}
+
+// The name of the command the code starts with, if it does, and the code
+// following it
+fn split_command(code: &str) -> (Option<&str>, &str) {
+    let code = code.trim_left();
+    if !code.starts_with(":") {
+        return (None, code)
+    }
+    let end = code.find(|c: char| c.is_whitespace()).unwrap_or(code.len());
+    (Some(&code[1..end]), &code[end..])
+}
+
+fn entry(name: Option<&str>, forms: Vec<Node>) -> Entry {
+    match name {
+        Some(name) => Entry::Command(name.to_string(), forms),
+        None => Entry::Forms(forms)
+    }
+}
+
+fn unreadable(e: ParseError, code: &str) -> EvalError {
+    EvalError::Located(e.to_string(), e.span().excerpt(code))
+}
+
+// Whether the error is exit ending the program
+pub fn is_exit(e: &EvalError) -> bool {
+    match *e.cause() {
+        EvalError::Exit(_) => true,
+        _ => false
+    }
+}
+
+fn is_quit(code: &Value) -> bool {
+    match *code {
+        Value::List(ref items) => items.len() == 1 && items[0] == Value::Symbol(symbol::intern("quit")),
+        _ => false
+    }
+}
+
+// Print a value with its type, if it has one, or an error with the functions
+// it was raised through
+pub fn print_result(result: Result<(Value, Option<Type>), EvalError>) {
+    print_with(result, &Settings::new())
+}
+
+// Print a result as the settings have values printed
+fn print_with(result: Result<(Value, Option<Type>), EvalError>, settings: &Settings) {
+    match result {
+        Ok((value, Some(type_))) => outln!("{} {}", settings.print(&value), color::type_(&*format!(": {}", type_))),
+        Ok((value, None)) => outln!("{}", settings.print(&value)),
+        Err(e) => out!("{}", describe(&e))
+    }
+}
+
+// The error's message and the functions it was raised through, a line each
+pub fn describe(e: &EvalError) -> String {
+    let mut text = format!("{}\n", color::error(&*e.to_string()));
+    if let Some(excerpt) = e.excerpt() {
+        text.push_str(&*format!("{}\n", excerpt));
+    }
+    let trace = e.trace();
+    let mut i = 0;
+    // A run of recursive calls is described on one line
+    while i < trace.len() {
+        let run = trace[i..].iter().take_while(|&&name| name == trace[i]).count();
+        if run == 1 {
+            text.push_str(&*format!("    in {}\n", trace[i]));
+        } else {
+            text.push_str(&*format!("    in {} ({} calls)\n", trace[i], run));
+        }
+        i += run;
+    }
+    text
+}
+
+fn print_error(text: &str) {
+    let _ = old_io::stderr().write_str(text);
+    transcript::record(text);
+}
+
+// Report an error ending the program on standard error, giving the status
+// to exit with, which is 1 unless the error is exiting with another
+pub fn report(e: EvalError) -> i32 {
+    match *e.cause() {
+        EvalError::Exit(status) => return status,
+        _ => {}
+    }
+    print_error(&*describe(&e));
+    1
+}
@@ -0,0 +1,88 @@
+use std::old_io::{Acceptor, Buffer, BufferedReader, IoResult, Listener, Reader, Writer};
+use std::old_io::net::pipe::UnixListener;
+use std::old_io::net::tcp::TcpListener;
+use std::old_path::Path;
+
+use eval::Interpreter;
+use reader::{self, ParseOutcome};
+use repl;
+use value::Value;
+
+// A server evaluating code sent over a socket, so that editors and other
+// tools can drive a session.  The address is a host and port to listen on
+// with TCP, such as localhost:7888, or else the path of a Unix socket.
+// Clients are served one at a time, all evaluating in the same session.
+//
+// A client sends lines of code, the lines of a form spanning several being
+// read until it's complete.  For each form the server replies with a line
+//
+//   ok Value       the form's value, as the REPL prints it
+//   type Type      following ok when type checking, the value's type
+//   error Message  the error the form raised
+//
+// and after the last form a line holding done.  Code which can't be read is
+// replied to with an error and done.  Newlines and backslashes in values
+// and messages are escaped as \n and \\, so each reply is a line.  Output
+// printed by the forms goes to the server's standard output.  exit closes
+// the connection, leaving the server listening.
+pub fn serve(interpreter: &mut Interpreter, address: &str) -> IoResult<()> {
+    if address.contains("/") {
+        let mut acceptor = try!(UnixListener::bind(&Path::new(address)).listen());
+        loop {
+            let stream = try!(acceptor.accept());
+            converse(interpreter, stream);
+        }
+    } else {
+        let mut acceptor = try!(TcpListener::bind(address).listen());
+        loop {
+            let stream = try!(acceptor.accept());
+            converse(interpreter, stream);
+        }
+    }
+}
+
+// Serve a client until it closes the connection.  A failure to read or write
+// only ends the connection.
+fn converse<S: Reader + Writer + Clone>(interpreter: &mut Interpreter, mut stream: S) {
+    let mut lines = BufferedReader::new(stream.clone());
+    let mut code = String::new();
+    loop {
+        match lines.read_line() {
+            Ok(line) => code.push_str(&*line),
+            // The client has gone, whether closing the connection or not
+            Err(_) => return
+        }
+        let forms = match reader::read_partial(&*code) {
+            Ok(ParseOutcome::Complete(forms)) => forms,
+            Ok(ParseOutcome::NeedMore) => continue,
+            Err(e) => {
+                if reply(&mut stream, "error", &*e.to_string()).is_err() {
+                    return
+                }
+                Vec::new()
+            }
+        };
+        code.clear();
+        for form in forms.iter() {
+            let sent = match interpreter.eval_toplevel(&Value::from_node(form)) {
+                Ok((value, None)) => reply(&mut stream, "ok", &*value.to_string()),
+                Ok((value, Some(type_))) => {
+                    reply(&mut stream, "ok", &*value.to_string())
+                        .and_then(|_| reply(&mut stream, "type", &*type_.to_string()))
+                }
+                Err(ref e) if repl::is_exit(e) => return,
+                Err(e) => reply(&mut stream, "error", &*e.to_string())
+            };
+            if sent.is_err() {
+                return
+            }
+        }
+        if stream.write_str("done\n").and_then(|_| stream.flush()).is_err() {
+            return
+        }
+    }
+}
+
+fn reply<S: Writer>(stream: &mut S, kind: &str, text: &str) -> IoResult<()> {
+    stream.write_str(&*format!("{} {}\n", kind, text.replace("\\", "\\\\").replace("\n", "\\n")))
+}
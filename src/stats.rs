@@ -0,0 +1,107 @@
+use std::collections::HashSet;
+use std::mem;
+
+use value::Value;
+
+// What a session is holding on to, for finding where memory goes in a long
+// session.  Values are reference counted, so they're freed as soon as
+// they're no longer used and there's no collector with collections to
+// count; what's left is what's reachable from the interpreter's functions,
+// globals and property lists.
+pub struct Stats {
+    // Symbols interned, which are never freed
+    pub symbols: usize,
+    pub functions: usize,
+    pub globals: usize,
+    pub properties: usize,
+    // Distinct values reachable from the above, shared values counted once
+    pub values: usize,
+    // Estimate of the bytes those values take
+    pub bytes: usize,
+}
+
+impl Stats {
+    // The statistics as name and number pairs, in the order listed
+    pub fn entries(&self) -> Vec<(&'static str, usize)> {
+        vec![("symbols", self.symbols),
+             ("functions", self.functions),
+             ("globals", self.globals),
+             ("properties", self.properties),
+             ("live-values", self.values),
+             ("bytes", self.bytes)]
+    }
+}
+
+// Count the values reachable from the roots and the bytes they take,
+// following each shared value only once, so that cycles through vectors
+// are counted without looping
+pub fn measure(roots: Vec<Value>) -> (usize, usize) {
+    let mut seen: HashSet<usize> = HashSet::new();
+    let mut pending = roots;
+    let (mut values, mut bytes) = (0, 0);
+    while let Some(value) = pending.pop() {
+        // Values held in one place are counted with what holds them, those
+        // shared by pointer the first time they're met
+        let shared = match value {
+            Value::Str(ref s) => Some((&**s as *const String as usize, mem::size_of::<String>() + s.len())),
+            Value::Cons(ref cons) => Some((&**cons as *const _ as usize, mem::size_of_val(&**cons))),
+            Value::List(ref items) => {
+                Some((&**items as *const _ as usize, mem::size_of::<Vec<Value>>() + items.len() * mem::size_of::<Value>()))
+            }
+            Value::Tuple(ref tuple) => Some((&**tuple as *const _ as usize, mem::size_of_val(&**tuple))),
+            Value::Vector(ref vector) => {
+                let size = mem::size_of::<Vec<Value>>() + vector.borrow().len() * mem::size_of::<Value>();
+                Some((&**vector as *const _ as usize, size))
+            }
+            Value::Dict(ref dict) => {
+                let size = dict.borrow().len() * 2 * mem::size_of::<Value>();
+                Some((&**dict as *const _ as usize, size))
+            }
+            Value::Closure(ref closure) => Some((&**closure as *const _ as usize, mem::size_of_val(&**closure))),
+            Value::Partial(ref partial) => Some((&**partial as *const _ as usize, mem::size_of_val(&**partial))),
+            Value::Frozen(ref frozen) => Some((&**frozen as *const _ as usize, mem::size_of_val(&**frozen))),
+            Value::Continuation(ref k) => Some((&**k as *const _ as usize, mem::size_of_val(&**k))),
+            Value::Error(ref e) => Some((&**e as *const _ as usize, mem::size_of_val(&**e))),
+            _ => None
+        };
+        match shared {
+            Some((pointer, size)) => {
+                if !seen.insert(pointer) {
+                    continue
+                }
+                bytes += size;
+            }
+            None => bytes += mem::size_of::<Value>()
+        }
+        values += 1;
+        match value {
+            Value::Cons(ref cons) => {
+                pending.push(cons.head.clone());
+                pending.push(cons.tail.clone());
+            }
+            Value::List(ref items) => pending.extend(items.iter().cloned()),
+            Value::Tuple(ref tuple) => {
+                pending.push(tuple.first.clone());
+                pending.push(tuple.second.clone());
+            }
+            Value::Vector(ref vector) => pending.extend(vector.borrow().iter().cloned()),
+            Value::Dict(ref dict) => {
+                for (key, value) in dict.borrow().entries().into_iter() {
+                    pending.push(key);
+                    pending.push(value);
+                }
+            }
+            Value::Closure(ref closure) => {
+                pending.push(closure.body.clone());
+                pending.extend(closure.env.bindings().into_iter().map(|(_, value)| value));
+            }
+            Value::Partial(ref partial) => pending.extend(partial.args.iter().cloned()),
+            Value::Frozen(ref frozen) => {
+                pending.push(frozen.body.clone());
+                pending.extend(frozen.env.bindings().into_iter().map(|(_, value)| value));
+            }
+            _ => {}
+        }
+    }
+    (values, bytes)
+}
@@ -0,0 +1,23 @@
+use eval::Interpreter;
+use reader;
+use value::Value;
+
+static SOURCE: &'static str = include_str!("stdlib.shen");
+
+// Evaluate the standard library with type checking on, so its functions'
+// signatures are declared whether or not tc is on later
+pub fn load(interpreter: &mut Interpreter) {
+    let forms = match reader::read(SOURCE) {
+        Ok(forms) => forms,
+        Err(e) => panic!("the standard library failed to read: {}", e)
+    };
+    let typechecking = interpreter.typechecking();
+    interpreter.set_typechecking(true);
+    for form in forms.iter() {
+        match interpreter.eval_toplevel(&Value::from_node(form)) {
+            Ok(_) => {}
+            Err(e) => panic!("the standard library failed to load: {}", e)
+        }
+    }
+    interpreter.set_typechecking(typechecking);
+}
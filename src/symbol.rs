@@ -0,0 +1,167 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+
+// A symbol name interned in the symbol table, so comparing symbols compares
+// integers rather than strings
+#[derive(Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct SymbolId(u32);
+
+// Interned first by every table, in this order, so the constants below can
+// be compared against without looking their names up
+static WELL_KNOWN: &'static [&'static str] = &[
+    "",
+    "lambda",
+    "/.",
+    "defun",
+    "if",
+    "cond",
+    "and",
+    "or",
+    "let",
+    "freeze",
+    "trap-error",
+    "defmacro",
+    "*macros*",
+    "define",
+    "<>",
+    "datatype",
+    "defprolog",
+    "prolog?",
+    "defcc",
+    "package",
+    "thaw",
+    "reset",
+    "shift",
+    "unwind-protect",
+    "do",
+    "cases",
+];
+
+// The empty name, which no symbol read from source has
+pub const EMPTY: SymbolId = SymbolId(0);
+pub const LAMBDA: SymbolId = SymbolId(1);
+pub const SLASH_DOT: SymbolId = SymbolId(2);
+pub const DEFUN: SymbolId = SymbolId(3);
+pub const IF: SymbolId = SymbolId(4);
+pub const COND: SymbolId = SymbolId(5);
+pub const AND: SymbolId = SymbolId(6);
+pub const OR: SymbolId = SymbolId(7);
+pub const LET: SymbolId = SymbolId(8);
+pub const FREEZE: SymbolId = SymbolId(9);
+pub const TRAP_ERROR: SymbolId = SymbolId(10);
+pub const DEFMACRO: SymbolId = SymbolId(11);
+// The global listing installed macros
+pub const MACROS: SymbolId = SymbolId(12);
+pub const DEFINE: SymbolId = SymbolId(13);
+// The empty vector literal
+pub const EMPTY_VECTOR: SymbolId = SymbolId(14);
+pub const DATATYPE: SymbolId = SymbolId(15);
+pub const DEFPROLOG: SymbolId = SymbolId(16);
+pub const PROLOG: SymbolId = SymbolId(17);
+pub const DEFCC: SymbolId = SymbolId(18);
+pub const PACKAGE: SymbolId = SymbolId(19);
+pub const THAW: SymbolId = SymbolId(20);
+pub const RESET: SymbolId = SymbolId(21);
+pub const SHIFT: SymbolId = SymbolId(22);
+pub const UNWIND_PROTECT: SymbolId = SymbolId(23);
+pub const DO: SymbolId = SymbolId(24);
+pub const CASES: SymbolId = SymbolId(25);
+
+pub struct SymbolTable {
+    names: Vec<String>,
+    ids: HashMap<String, SymbolId>,
+}
+
+impl SymbolTable {
+    pub fn new() -> SymbolTable {
+        let mut table = SymbolTable { names: Vec::new(), ids: HashMap::new() };
+        for name in WELL_KNOWN.iter() {
+            table.intern(*name);
+        }
+        table
+    }
+
+    pub fn intern(&mut self, name: &str) -> SymbolId {
+        match self.ids.get(name) {
+            Some(&id) => return id,
+            None => {}
+        }
+        let id = SymbolId(self.names.len() as u32);
+        self.names.push(name.to_string());
+        self.ids.insert(name.to_string(), id);
+        id
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.ids.contains_key(name)
+    }
+
+    pub fn name(&self, id: SymbolId) -> &str {
+        &*self.names[id.0 as usize]
+    }
+
+    pub fn len(&self) -> usize {
+        self.names.len()
+    }
+}
+
+// Shared by everything on the thread, so ids from different reads agree
+thread_local!(static SYMBOLS: RefCell<SymbolTable> = RefCell::new(SymbolTable::new()));
+
+pub fn intern(name: &str) -> SymbolId {
+    SYMBOLS.with(|table| table.borrow_mut().intern(name))
+}
+
+// A symbol which has never been interned, named prefix followed by a number
+// from counter, which is advanced past the numbers already taken
+pub fn fresh(prefix: &str, counter: &mut u64) -> SymbolId {
+    SYMBOLS.with(|table| {
+        let mut table = table.borrow_mut();
+        loop {
+            *counter += 1;
+            let name = format!("{}{}", prefix, counter);
+            if !table.contains(&*name) {
+                return table.intern(&*name)
+            }
+        }
+    })
+}
+
+// Whether the symbol is one of the special forms and others interned first
+pub fn is_well_known(id: SymbolId) -> bool {
+    (id.0 as usize) < WELL_KNOWN.len()
+}
+
+// Number of distinct symbols interned so far
+pub fn interned() -> usize {
+    SYMBOLS.with(|table| table.borrow().len())
+}
+
+impl SymbolId {
+    // Call f with the symbol's name without copying it out of the table
+    pub fn with_name<T, F: FnOnce(&str) -> T>(self, f: F) -> T {
+        SYMBOLS.with(|table| f(table.borrow().name(self)))
+    }
+
+    pub fn name(self) -> String {
+        self.with_name(|name| name.to_string())
+    }
+
+    // Variables in patterns are the symbols starting with a capital letter
+    pub fn is_variable(self) -> bool {
+        self.with_name(|name| name.chars().next().map_or(false, |c| c.is_uppercase()))
+    }
+}
+
+impl fmt::Display for SymbolId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.with_name(|name| f.write_str(name))
+    }
+}
+
+impl fmt::Debug for SymbolId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
@@ -0,0 +1,60 @@
+use std::cell::RefCell;
+use std::old_io::{self, File, IoResult, Writer};
+use std::old_path::Path;
+
+// A transcript of a REPL session, everything typed at the prompt and shown
+// in reply written to a file as it happens, without colour.  Output goes
+// through outln! and out!, which show it and add it to any transcript.
+
+thread_local!(static TRANSCRIPT: RefCell<Option<File>> = RefCell::new(None));
+
+// Keep a transcript in the file, replacing any kept before
+pub fn start(path: &str) -> IoResult<()> {
+    let file = try!(File::create(&Path::new(path)));
+    TRANSCRIPT.with(|transcript| *transcript.borrow_mut() = Some(file));
+    Ok(())
+}
+
+// Stop keeping a transcript, false if none was kept
+pub fn stop() -> bool {
+    TRANSCRIPT.with(|transcript| transcript.borrow_mut().take().is_some())
+}
+
+// Add text to the transcript, if there is one
+pub fn record(text: &str) {
+    TRANSCRIPT.with(|transcript| {
+        match *transcript.borrow_mut() {
+            // A transcript is a record, so failing to write it shouldn't
+            // stop the session
+            Some(ref mut file) => { let _ = file.write_str(&*plain(text)); }
+            None => {}
+        }
+    })
+}
+
+// Show text on standard output, adding it to the transcript
+pub fn print(text: &str) {
+    let mut out = old_io::stdout();
+    let _ = out.write_str(text);
+    let _ = out.flush();
+    record(text);
+}
+
+// The text without the escape sequences colouring it
+fn plain(text: &str) -> String {
+    let mut plain = String::new();
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c == '\x1b' {
+            // ESC [ parameters, ended by a letter
+            while let Some(c) = chars.next() {
+                if c.is_alphabetic() {
+                    break
+                }
+            }
+        } else {
+            plain.push(c);
+        }
+    }
+    plain
+}
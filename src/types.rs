@@ -0,0 +1,927 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use define;
+use eval::{self, EvalError, Interpreter};
+use reader;
+use symbol::{self, SymbolId};
+use value::{Env, Value};
+
+// Signatures of the primitives, written as between the braces of a define
+static PRIMITIVES: &'static [(&'static str, &'static str)] = &[
+    ("+", "number --> number --> number"),
+    ("-", "number --> number --> number"),
+    ("*", "number --> number --> number"),
+    ("/", "number --> number --> number"),
+    ("div", "number --> number --> number"),
+    ("mod", "number --> number --> number"),
+    ("bitwise-and", "number --> number --> number"),
+    ("bitwise-or", "number --> number --> number"),
+    ("bitwise-xor", "number --> number --> number"),
+    ("shift-left", "number --> number --> number"),
+    ("shift-right", "number --> number --> number"),
+    ("sqrt", "number --> number"),
+    ("exp", "number --> number"),
+    ("log", "number --> number"),
+    ("sin", "number --> number"),
+    ("cos", "number --> number"),
+    ("tan", "number --> number"),
+    ("asin", "number --> number"),
+    ("acos", "number --> number"),
+    ("atan", "number --> number"),
+    ("atan2", "number --> number --> number"),
+    ("floor", "number --> number"),
+    ("ceiling", "number --> number"),
+    ("round", "number --> number"),
+    ("truncate", "number --> number"),
+    ("random", "number --> number"),
+    ("seed-random", "number --> number"),
+    ("get-time", "symbol --> number"),
+    ("shen.dict", "number --> (dict K V)"),
+    ("shen.dict?", "A --> boolean"),
+    ("shen.dict-count", "(dict K V) --> number"),
+    ("shen.dict->", "(dict K V) --> K --> V --> V"),
+    ("shen.<-dict", "(dict K V) --> K --> V"),
+    ("shen.dict-rm", "(dict K V) --> K --> K"),
+    ("shen.dict-fold", "(K --> V --> A --> A) --> (dict K V) --> A --> A"),
+    ("shen.dict-keys", "(dict K V) --> (list K)"),
+    ("shen.dict-values", "(dict K V) --> (list V)"),
+    ("load", "string --> symbol"),
+    ("exit", "number --> A"),
+    ("track", "symbol --> symbol"),
+    ("untrack", "symbol --> symbol"),
+    ("break", "symbol --> symbol"),
+    ("unbreak", "symbol --> symbol"),
+    ("runtime-stats", "--> (list (symbol * number))"),
+    ("save-image", "string --> string"),
+    ("load-image", "string --> string"),
+    ("declare", "symbol --> A --> symbol"),
+    (">", "number --> number --> boolean"),
+    ("<", "number --> number --> boolean"),
+    (">=", "number --> number --> boolean"),
+    ("<=", "number --> number --> boolean"),
+    ("=", "A --> A --> boolean"),
+    ("cons", "A --> (list A) --> (list A)"),
+    ("hd", "(list A) --> A"),
+    ("tl", "(list A) --> (list A)"),
+    ("cons?", "A --> boolean"),
+    ("string?", "A --> boolean"),
+    ("symbol?", "A --> boolean"),
+    ("number?", "A --> boolean"),
+    ("integer?", "A --> boolean"),
+    ("tuple?", "A --> boolean"),
+    ("absvector?", "A --> boolean"),
+    ("cn", "string --> string --> string"),
+    ("@s", "string --> string --> string"),
+    ("pos", "string --> number --> string"),
+    ("tlstr", "string --> string"),
+    ("str", "A --> string"),
+    ("string->n", "string --> number"),
+    ("n->string", "number --> string"),
+    ("intern", "string --> symbol"),
+    ("gensym", "symbol --> symbol"),
+    ("@p", "A --> B --> (A * B)"),
+    ("fst", "(A * B) --> A"),
+    ("snd", "(A * B) --> B"),
+    ("@v", "A --> (vector A) --> (vector A)"),
+    ("hdv", "(vector A) --> A"),
+    ("tlv", "(vector A) --> (vector A)"),
+    ("thaw", "(lazy A) --> A"),
+    ("simple-error", "string --> A"),
+    ("error-to-string", "exception --> string"),
+    ("set", "symbol --> A --> A"),
+    ("value", "symbol --> A"),
+    ("tc", "symbol --> boolean"),
+    ("arity", "symbol --> number"),
+];
+
+pub fn install(interpreter: &mut Interpreter) {
+    for &(name, signature) in PRIMITIVES.iter() {
+        let items = reader::read(signature).unwrap().iter().map(Value::from_node).collect();
+        interpreter.declare(symbol::intern(name), Value::list(items));
+    }
+}
+
+// A type such as number, (list A) or (A --> B), where Var stands for a type
+// not yet known
+#[derive(Clone, PartialEq)]
+pub enum Type {
+    Var(usize),
+    Con(SymbolId, Vec<Type>),
+}
+
+fn con(name: &str, args: Vec<Type>) -> Type {
+    Type::Con(symbol::intern(name), args)
+}
+
+fn arrow(from: Type, to: Type) -> Type {
+    con("-->", vec![from, to])
+}
+
+impl Type {
+    // Name the variables A, B, C... in the order they appear
+    fn rename(&self, names: &mut Vec<usize>) -> Type {
+        match *self {
+            Type::Var(var) => {
+                match names.iter().position(|&name| name == var) {
+                    Some(i) => Type::Var(i),
+                    None => {
+                        names.push(var);
+                        Type::Var(names.len() - 1)
+                    }
+                }
+            }
+            Type::Con(name, ref args) => Type::Con(name, args.iter().map(|arg| arg.rename(names)).collect())
+        }
+    }
+}
+
+// Arrows and products are infix, with arrows to the right shown flattened,
+// as in (number --> number --> number)
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Type::Var(i) if i < 26 => write!(f, "{}", (b'A' + i as u8) as char),
+            Type::Var(i) => write!(f, "T{}", i),
+            Type::Con(name, ref args) if args.is_empty() => write!(f, "{}", name),
+            Type::Con(name, ref args) if args.len() == 2 && name == symbol::intern("-->") => {
+                try!(write!(f, "({}", args[0]));
+                let mut to = &args[1];
+                loop {
+                    match *to {
+                        Type::Con(name, ref args) if args.len() == 2 && name == symbol::intern("-->") => {
+                            try!(write!(f, " --> {}", args[0]));
+                            to = &args[1];
+                        }
+                        _ => return write!(f, " --> {})", to)
+                    }
+                }
+            }
+            Type::Con(name, ref args) if args.len() == 2 && name == symbol::intern("*") => {
+                write!(f, "({} * {})", args[0], args[1])
+            }
+            Type::Con(name, ref args) => {
+                try!(write!(f, "({}", name));
+                for arg in args.iter() {
+                    try!(write!(f, " {}", arg));
+                }
+                f.write_str(")")
+            }
+        }
+    }
+}
+
+impl fmt::Debug for Type {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
+
+// A premise of a datatype rule
+pub enum Premise {
+    // Term : Type, the type as the items following the colon
+    Judgement(Value, Vec<Value>),
+    // if Expr, a side condition evaluated with the rule's variables bound to
+    // the terms they match
+    Condition(Value),
+}
+
+// A rule of a datatype, concluding Term : Type when its premises hold.
+// Rules written with a double line also work backwards, so a pattern of the
+// conclusion's form binds its variables to the types in the premises.
+pub struct Rule {
+    pub premises: Vec<Premise>,
+    pub term: Value,
+    pub type_: Vec<Value>,
+    pub invertible: bool,
+}
+
+// Lines of underscores or equals signs separate the premises of a rule from
+// its conclusion
+fn line(item: &Value) -> Option<bool> {
+    match *item {
+        Value::Symbol(name) => {
+            name.with_name(|name| {
+                if name.len() < 3 {
+                    None
+                } else if name.chars().all(|c| c == '_') {
+                    Some(false)
+                } else if name.chars().all(|c| c == '=') {
+                    Some(true)
+                } else {
+                    None
+                }
+            })
+        }
+        _ => None
+    }
+}
+
+// A semicolon ending a symbol, as in X : number;, reads as part of it, so
+// split it off as a symbol of its own
+pub fn semicolons(items: &[Value]) -> Vec<Value> {
+    let mut tokens = Vec::new();
+    for item in items.iter() {
+        match *item {
+            Value::Symbol(symbol) if symbol.with_name(|name| name.len() > 1 && name.ends_with(";")) => {
+                let name = symbol.name();
+                tokens.push(Value::Symbol(symbol::intern(&name[..name.len() - 1])));
+                tokens.push(Value::Symbol(symbol::intern(";")));
+            }
+            _ => tokens.push(item.clone())
+        }
+    }
+    tokens
+}
+
+// Term : Type; from the start of the items, giving the rest
+fn judgement<'v>(expr: &Value, items: &'v [Value]) -> Result<((Value, Vec<Value>), &'v [Value]), EvalError> {
+    let colon = Value::Symbol(symbol::intern(":"));
+    let semicolon = Value::Symbol(symbol::intern(";"));
+    match items.iter().position(|item| *item == semicolon) {
+        Some(end) if end >= 3 && items[1] == colon => {
+            Ok(((items[0].clone(), items[2..end].to_vec()), &items[end + 1..]))
+        }
+        _ => Err(EvalError::Malformed(symbol::DATATYPE, expr.clone()))
+    }
+}
+
+// (datatype Name Rules...) where each rule is its premises, a line, then its
+// conclusion:
+//
+// (datatype point
+//   X : number; Y : number;
+//   =======================
+//   [X Y] : point;)
+pub fn datatype(expr: &Value, items: &[Value]) -> Result<(SymbolId, Vec<Rule>), EvalError> {
+    let malformed = || EvalError::Malformed(symbol::DATATYPE, expr.clone());
+    let name = match items.get(1) {
+        Some(&Value::Symbol(name)) => name,
+        _ => return Err(malformed())
+    };
+    let tokens = semicolons(&items[2..]);
+
+    let condition = Value::Symbol(symbol::intern("if"));
+    let mut rules = Vec::new();
+    let mut premises = Vec::new();
+    let mut rest = &tokens[..];
+    while !rest.is_empty() {
+        match line(&rest[0]) {
+            Some(invertible) => {
+                let ((term, type_), after) = try!(judgement(expr, &rest[1..]));
+                let premises = ::std::mem::replace(&mut premises, Vec::new());
+                rules.push(Rule { premises: premises, term: term, type_: type_, invertible: invertible });
+                rest = after;
+            }
+            None if rest[0] == condition && rest.len() > 1 => {
+                premises.push(Premise::Condition(rest[1].clone()));
+                rest = &rest[2..];
+            }
+            None => {
+                let ((term, type_), after) = try!(judgement(expr, rest));
+                premises.push(Premise::Judgement(term, type_));
+                rest = after;
+            }
+        }
+    }
+    if rules.is_empty() || !premises.is_empty() {
+        return Err(malformed())
+    }
+    Ok((name, rules))
+}
+
+// Match a rule's term against code, binding the rule's variables to the
+// parts of the code they match
+fn match_term(pattern: &Value, term: &Value, bindings: &mut Vec<(SymbolId, Value)>) -> bool {
+    match (pattern, term) {
+        (&Value::Symbol(name), _) if name == symbol::intern("_") => true,
+        (&Value::Symbol(name), _) if name.is_variable() => {
+            match bindings.iter().find(|&&(bound, _)| bound == name).map(|&(_, ref value)| value.clone()) {
+                Some(bound) => bound == *term,
+                None => {
+                    bindings.push((name, term.clone()));
+                    true
+                }
+            }
+        }
+        (&Value::List(ref patterns), &Value::List(ref terms)) => {
+            patterns.len() == terms.len() &&
+                patterns.iter().zip(terms.iter()).all(|(pattern, term)| match_term(pattern, term, bindings))
+        }
+        _ => pattern == term
+    }
+}
+
+fn substitute(term: &Value, bindings: &[(SymbolId, Value)]) -> Value {
+    match *term {
+        Value::Symbol(name) => {
+            match bindings.iter().find(|&&(bound, _)| bound == name) {
+                Some(&(_, ref value)) => value.clone(),
+                None => term.clone()
+            }
+        }
+        Value::List(ref items) => Value::list(items.iter().map(|item| substitute(item, bindings)).collect()),
+        _ => term.clone()
+    }
+}
+
+fn lookup(env: &[(SymbolId, Type)], name: SymbolId) -> Option<Type> {
+    env.iter().rev().find(|&&(bound, _)| bound == name).map(|&(_, ref type_)| type_.clone())
+}
+
+fn type_error(expr: &Value) -> EvalError {
+    EvalError::TypeError(format!("type error in {}", expr))
+}
+
+// Infers types by unification, each function's signature being instantiated
+// afresh wherever it's used
+struct Checker<'i> {
+    interpreter: &'i mut Interpreter,
+    // What each variable has been unified with, if anything
+    bindings: Vec<Option<Type>>,
+    // Signatures of functions being defined, which are only declared once
+    // their definitions check
+    pending: HashMap<SymbolId, Value>,
+}
+
+impl<'i> Checker<'i> {
+    fn new(interpreter: &'i mut Interpreter) -> Checker<'i> {
+        Checker { interpreter: interpreter, bindings: Vec::new(), pending: HashMap::new() }
+    }
+
+    fn fresh(&mut self) -> Type {
+        self.bindings.push(None);
+        Type::Var(self.bindings.len() - 1)
+    }
+
+    // The type with its variables replaced by what they're bound to
+    fn resolve(&self, type_: &Type) -> Type {
+        match *type_ {
+            Type::Var(var) => {
+                match self.bindings[var] {
+                    Some(ref bound) => self.resolve(bound),
+                    None => type_.clone()
+                }
+            }
+            Type::Con(name, ref args) => Type::Con(name, args.iter().map(|arg| self.resolve(arg)).collect())
+        }
+    }
+
+    fn occurs(&self, var: usize, type_: &Type) -> bool {
+        match *type_ {
+            Type::Var(other) => {
+                match self.bindings[other] {
+                    Some(ref bound) => self.occurs(var, bound),
+                    None => var == other
+                }
+            }
+            Type::Con(_, ref args) => args.iter().any(|arg| self.occurs(var, arg))
+        }
+    }
+
+    fn unify(&mut self, a: &Type, b: &Type) -> bool {
+        match (a, b) {
+            (&Type::Var(var), _) if self.bindings[var].is_some() => {
+                let bound = self.bindings[var].clone().unwrap();
+                self.unify(&bound, b)
+            }
+            (_, &Type::Var(var)) if self.bindings[var].is_some() => {
+                let bound = self.bindings[var].clone().unwrap();
+                self.unify(a, &bound)
+            }
+            (&Type::Var(x), &Type::Var(y)) if x == y => true,
+            (&Type::Var(var), other) | (other, &Type::Var(var)) => {
+                if self.occurs(var, other) {
+                    return false
+                }
+                self.bindings[var] = Some(other.clone());
+                true
+            }
+            (&Type::Con(x, ref xs), &Type::Con(y, ref ys)) => {
+                x == y && xs.len() == ys.len() && xs.iter().zip(ys.iter()).all(|(x, y)| self.unify(x, y))
+            }
+        }
+    }
+
+    // The type written as the items of a signature.  Arrows bind less
+    // tightly than products and both associate to the right.  Variables are
+    // fresh, unless rigid when they only match themselves, as when checking
+    // a definition against a polymorphic signature.
+    fn parse(&mut self, items: &[Value], vars: &mut HashMap<SymbolId, Type>, rigid: bool) -> Result<Type, EvalError> {
+        let malformed = || EvalError::TypeError(format!("malformed type {}", Value::list(items.to_vec())));
+        for infix in ["-->", "*"].iter() {
+            let operator = Value::Symbol(symbol::intern(*infix));
+            match items.iter().position(|item| *item == operator) {
+                // {--> number} is the type of a function of no arguments
+                Some(0) if *infix == "-->" => return self.parse(&items[1..], vars, rigid),
+                Some(i) => {
+                    let left = try!(self.parse(&items[..i], vars, rigid));
+                    let right = try!(self.parse(&items[i + 1..], vars, rigid));
+                    return Ok(con(*infix, vec![left, right]))
+                }
+                None => {}
+            }
+        }
+        match items.len() {
+            0 => Err(malformed()),
+            1 => {
+                match items[0] {
+                    Value::Symbol(name) if name.is_variable() => {
+                        if rigid {
+                            return Ok(Type::Con(name, Vec::new()))
+                        }
+                        match vars.get(&name) {
+                            Some(var) => return Ok(var.clone()),
+                            None => {}
+                        }
+                        let var = self.fresh();
+                        vars.insert(name, var.clone());
+                        Ok(var)
+                    }
+                    Value::Symbol(name) => Ok(Type::Con(name, Vec::new())),
+                    Value::List(ref inner) => self.parse(&**inner, vars, rigid),
+                    _ => Err(malformed())
+                }
+            }
+            _ => {
+                let name = match items[0] {
+                    Value::Symbol(name) => name,
+                    _ => return Err(malformed())
+                };
+                let mut args = Vec::new();
+                for item in items[1..].iter() {
+                    args.push(try!(self.parse(&[item.clone()], vars, rigid)));
+                }
+                Ok(Type::Con(name, args))
+            }
+        }
+    }
+
+    // A fresh copy of a type found earlier, its variables replaced by new
+    // ones
+    fn instantiate(&mut self, type_: &Type, vars: &mut HashMap<usize, Type>) -> Type {
+        match *type_ {
+            Type::Var(var) => {
+                if let Some(type_) = vars.get(&var) {
+                    return type_.clone()
+                }
+                let fresh = self.fresh();
+                vars.insert(var, fresh.clone());
+                fresh
+            }
+            Type::Con(name, ref args) => Type::Con(name, args.iter().map(|arg| self.instantiate(arg, vars)).collect())
+        }
+    }
+
+    fn signature(&mut self, name: SymbolId) -> Result<Type, EvalError> {
+        let signature = match self.pending.get(&name) {
+            Some(signature) => Some(signature.clone()),
+            None => self.interpreter.signature(name)
+        };
+        match signature {
+            Some(Value::List(ref items)) => self.parse(&**items, &mut HashMap::new(), false),
+            _ => Err(EvalError::TypeError(format!("{} has no type signature", name)))
+        }
+    }
+
+    // The result of applying a function of the given type to arguments of
+    // the given types, which are taken one at a time
+    fn apply(&mut self, expr: &Value, function: Type, args: Vec<Type>) -> Result<Type, EvalError> {
+        let mut result = function;
+        for arg in args.into_iter() {
+            let next = self.fresh();
+            if !self.unify(&result, &arrow(arg, next.clone())) {
+                return Err(type_error(expr))
+            }
+            result = next;
+        }
+        Ok(result)
+    }
+
+    // Check the code has the expected type, by its own type or failing that
+    // by the rules of the datatypes
+    fn expect(&mut self, expr: &Value, env: &[(SymbolId, Type)], expected: Type) -> Result<(), EvalError> {
+        let saved = self.bindings.clone();
+        let error = match self.infer(expr, env) {
+            Ok(ref type_) if self.unify(type_, &expected) => return Ok(()),
+            Ok(_) => type_error(expr),
+            Err(e) => e
+        };
+        self.bindings = saved;
+        if try!(self.datatypes(expr, env, &expected)) {
+            return Ok(())
+        }
+        Err(error)
+    }
+
+    // Whether a datatype rule concludes the code has the expected type.
+    // Rules are tried in turn, undoing what a failed attempt unified.
+    fn datatypes(&mut self, expr: &Value, env: &[(SymbolId, Type)], expected: &Type) -> Result<bool, EvalError> {
+        for rules in self.interpreter.datatypes().iter() {
+            for rule in rules.iter() {
+                let mut terms = Vec::new();
+                if !match_term(&rule.term, expr, &mut terms) {
+                    continue
+                }
+                let saved = self.bindings.clone();
+                if try!(self.premises(rule, &terms, env, expected)) {
+                    return Ok(true)
+                }
+                self.bindings = saved;
+            }
+        }
+        Ok(false)
+    }
+
+    fn premises(&mut self, rule: &Rule, terms: &[(SymbolId, Value)], env: &[(SymbolId, Type)],
+                expected: &Type) -> Result<bool, EvalError> {
+        let mut vars = HashMap::new();
+        let conclusion = try!(self.parse(&*rule.type_, &mut vars, false));
+        if !self.unify(&conclusion, expected) {
+            return Ok(false)
+        }
+        for premise in rule.premises.iter() {
+            match *premise {
+                Premise::Judgement(ref term, ref type_) => {
+                    let type_ = try!(self.parse(&**type_, &mut vars, false));
+                    if self.expect(&substitute(term, terms), env, type_).is_err() {
+                        return Ok(false)
+                    }
+                }
+                Premise::Condition(ref condition) => {
+                    let scope = terms.iter().fold(Env::new(), |scope, &(name, ref term)| scope.bind(name, term.to_data()));
+                    match try!(self.interpreter.eval(condition, &scope)) {
+                        Value::Bool(true) => {}
+                        _ => return Ok(false)
+                    }
+                }
+            }
+        }
+        Ok(true)
+    }
+
+    fn infer(&mut self, expr: &Value, env: &[(SymbolId, Type)]) -> Result<Type, EvalError> {
+        let items = match *expr {
+            Value::Num(_) => return Ok(con("number", Vec::new())),
+            Value::Str(_) => return Ok(con("string", Vec::new())),
+            Value::Bool(_) => return Ok(con("boolean", Vec::new())),
+            Value::Nil => return Ok(con("list", vec![self.fresh()])),
+            Value::Symbol(name) => {
+                return Ok(match lookup(env, name) {
+                    Some(type_) => type_,
+                    None if name == symbol::EMPTY_VECTOR => con("vector", vec![self.fresh()]),
+                    None => con("symbol", Vec::new())
+                })
+            }
+            Value::List(ref items) => items,
+            _ => return Err(type_error(expr))
+        };
+        let malformed = |form| EvalError::Malformed(form, expr.clone());
+
+        match items[0] {
+            Value::Symbol(form) if form == symbol::LAMBDA || form == symbol::SLASH_DOT => {
+                if items.len() < 3 || (form == symbol::LAMBDA && items.len() != 3) {
+                    return Err(malformed(form))
+                }
+                let mut scope = env.to_vec();
+                let mut params = Vec::new();
+                for param in items[1..items.len() - 1].iter() {
+                    match *param {
+                        Value::Symbol(param) => {
+                            let type_ = self.fresh();
+                            scope.push((param, type_.clone()));
+                            params.push(type_);
+                        }
+                        _ => return Err(malformed(form))
+                    }
+                }
+                let body = try!(self.infer(&items[items.len() - 1], &scope));
+                Ok(params.into_iter().rev().fold(body, |to, from| arrow(from, to)))
+            }
+            Value::Symbol(form) if form == symbol::LET => {
+                if items.len() < 4 || items.len() % 2 != 0 {
+                    return Err(malformed(form))
+                }
+                let mut scope = env.to_vec();
+                for binding in items[1..items.len() - 1].chunks(2) {
+                    let type_ = try!(self.infer(&binding[1], &scope));
+                    match binding[0] {
+                        Value::Symbol(name) => scope.push((name, type_)),
+                        _ => return Err(malformed(form))
+                    }
+                }
+                self.infer(&items[items.len() - 1], &scope)
+            }
+            Value::Symbol(form) if form == symbol::IF => {
+                if items.len() != 4 {
+                    return Err(malformed(form))
+                }
+                try!(self.expect(&items[1], env, con("boolean", Vec::new())));
+                let then = try!(self.infer(&items[2], env));
+                try!(self.expect(&items[3], env, then.clone()));
+                Ok(then)
+            }
+            Value::Symbol(form) if form == symbol::COND => {
+                let result = self.fresh();
+                for clause in items[1..].iter() {
+                    match *clause {
+                        Value::List(ref clause) if clause.len() == 2 => {
+                            try!(self.expect(&clause[0], env, con("boolean", Vec::new())));
+                            try!(self.expect(&clause[1], env, result.clone()));
+                        }
+                        _ => return Err(malformed(form))
+                    }
+                }
+                Ok(result)
+            }
+            Value::Symbol(form) if form == symbol::AND || form == symbol::OR => {
+                for condition in items[1..].iter() {
+                    try!(self.expect(condition, env, con("boolean", Vec::new())));
+                }
+                Ok(con("boolean", Vec::new()))
+            }
+            Value::Symbol(form) if form == symbol::FREEZE => {
+                if items.len() != 2 {
+                    return Err(malformed(form))
+                }
+                Ok(con("lazy", vec![try!(self.infer(&items[1], env))]))
+            }
+            // The handler is given the error and must give the same type
+            // as the expression it guards
+            Value::Symbol(form) if form == symbol::TRAP_ERROR => {
+                if items.len() != 3 {
+                    return Err(malformed(form))
+                }
+                let result = try!(self.infer(&items[1], env));
+                try!(self.expect(&items[2], env, arrow(con("exception", Vec::new()), result.clone())));
+                Ok(result)
+            }
+            Value::Symbol(form) if form == symbol::CASES => {
+                let ifs = try!(eval::cases(expr, &**items));
+                self.infer(&ifs, env)
+            }
+            // Only the last expression's type matters, the others being
+            // evaluated for their effects
+            Value::Symbol(form) if form == symbol::DO => {
+                if items.len() < 2 {
+                    return Err(malformed(form))
+                }
+                for form in items[1..items.len() - 1].iter() {
+                    try!(self.infer(form, env));
+                }
+                self.infer(&items[items.len() - 1], env)
+            }
+            // The cleanup forms may be of any type, their values discarded
+            Value::Symbol(form) if form == symbol::UNWIND_PROTECT => {
+                if items.len() < 3 {
+                    return Err(malformed(form))
+                }
+                let result = try!(self.infer(&items[1], env));
+                for form in items[2..].iter() {
+                    try!(self.infer(form, env));
+                }
+                Ok(result)
+            }
+            Value::Symbol(form) if form == symbol::RESET => {
+                if items.len() != 2 {
+                    return Err(malformed(form))
+                }
+                self.infer(&items[1], env)
+            }
+            // The continuation takes the value shift stands for, and gives
+            // that of the reset, which the body must give too
+            Value::Symbol(form) if form == symbol::SHIFT => {
+                let name = match (items.len(), &items[1]) {
+                    (3, &Value::Symbol(name)) => name,
+                    _ => return Err(malformed(form))
+                };
+                let (hole, answer) = (self.fresh(), self.fresh());
+                let mut scope = env.to_vec();
+                scope.push((name, arrow(hole.clone(), answer.clone())));
+                try!(self.expect(&items[2], &scope, answer));
+                Ok(hole)
+            }
+            // (function f) has the type of f's signature
+            Value::Symbol(form) if form == symbol::intern("function") && items.len() == 2 => {
+                match items[1] {
+                    Value::Symbol(name) if lookup(env, name).is_none() => self.signature(name),
+                    _ => Err(type_error(expr))
+                }
+            }
+            _ => {
+                let mut result = match items[0] {
+                    Value::Symbol(name) if lookup(env, name).is_none() => try!(self.signature(name)),
+                    ref head => try!(self.infer(head, env))
+                };
+                for arg in items[1..].iter() {
+                    let (param, next) = (self.fresh(), self.fresh());
+                    if !self.unify(&result, &arrow(param.clone(), next.clone())) {
+                        return Err(type_error(expr))
+                    }
+                    try!(self.expect(arg, env, param));
+                    result = next;
+                }
+                Ok(result)
+            }
+        }
+    }
+
+    // The type of a pattern, binding its variables in the environment.
+    // Patterns built with a constructor such as cons or @p have the type of
+    // the constructor's result.
+    fn pattern(&mut self, pattern: &Value, env: &mut Vec<(SymbolId, Type)>) -> Result<Type, EvalError> {
+        match *pattern {
+            Value::Symbol(name) if name == symbol::intern("_") => Ok(self.fresh()),
+            Value::Symbol(name) if name.is_variable() => {
+                match lookup(&**env, name) {
+                    Some(type_) => Ok(type_),
+                    None => {
+                        let type_ = self.fresh();
+                        env.push((name, type_.clone()));
+                        Ok(type_)
+                    }
+                }
+            }
+            Value::List(ref items) if items.len() >= 3 => {
+                let constructor = match items[0] {
+                    Value::Symbol(name) => name,
+                    _ => return Err(type_error(pattern))
+                };
+                let first = try!(self.pattern(&items[1], env));
+                // (@p A B C) is (@p A (@p B C))
+                let rest = if items.len() == 3 {
+                    try!(self.pattern(&items[2], env))
+                } else {
+                    let mut rest = vec![items[0].clone()];
+                    rest.extend(items[2..].iter().map(|item| item.clone()));
+                    try!(self.pattern(&Value::list(rest), env))
+                };
+                let function = try!(self.signature(constructor));
+                self.apply(pattern, function, vec![first, rest])
+            }
+            _ => self.infer(pattern, &**env)
+        }
+    }
+
+    // Check a pattern has the expected type, by its own type or failing that
+    // by the invertible rules of the datatypes, whose premises give the
+    // types of the variables in the pattern
+    fn expect_pattern(&mut self, pattern: &Value, env: &mut Vec<(SymbolId, Type)>, expected: Type) -> Result<(), EvalError> {
+        let (saved, bound) = (self.bindings.clone(), env.len());
+        let error = match self.pattern(pattern, env) {
+            Ok(ref type_) if self.unify(type_, &expected) => return Ok(()),
+            Ok(_) => type_error(pattern),
+            Err(e) => e
+        };
+        self.bindings = saved;
+        env.truncate(bound);
+        // A constant such as red may be of a type by any rule
+        let scope = env.clone();
+        if try!(self.datatypes(pattern, &*scope, &expected)) {
+            return Ok(())
+        }
+
+        for rules in self.interpreter.datatypes().iter() {
+            for rule in rules.iter().filter(|rule| rule.invertible) {
+                let mut terms = Vec::new();
+                if !match_term(&rule.term, pattern, &mut terms) {
+                    continue
+                }
+                let saved = self.bindings.clone();
+                let mut vars = HashMap::new();
+                let conclusion = try!(self.parse(&*rule.type_, &mut vars, false));
+                let mut matched = self.unify(&conclusion, &expected);
+                for premise in rule.premises.iter() {
+                    match *premise {
+                        Premise::Judgement(ref term, ref type_) if matched => {
+                            let type_ = try!(self.parse(&**type_, &mut vars, false));
+                            matched = self.expect_pattern(&substitute(term, &terms), env, type_).is_ok();
+                        }
+                        _ => {}
+                    }
+                }
+                if matched {
+                    return Ok(())
+                }
+                self.bindings = saved;
+                env.truncate(bound);
+            }
+        }
+        Err(error)
+    }
+
+    // Check each clause of a define against its signature, which the
+    // clauses may use to call the function recursively
+    fn define(&mut self, expr: &Value, items: &[Value]) -> Result<Type, EvalError> {
+        let name = match items.get(1) {
+            Some(&Value::Symbol(name)) => name,
+            _ => return Err(EvalError::Malformed(symbol::DEFINE, expr.clone()))
+        };
+        let (signature, rest) = try!(define::signature(expr, &items[2..]));
+        let signature = match signature {
+            Some(signature) => signature,
+            None => return Err(EvalError::TypeError(format!("{} has no type signature", name)))
+        };
+        let declared = try!(self.parse(signature, &mut HashMap::new(), true));
+        self.pending.insert(name, Value::list(signature.to_vec()));
+
+        for (i, clause) in try!(define::clauses(expr, rest)).iter().enumerate() {
+            let failed = || EvalError::TypeError(format!("type error in rule {} of {}", i + 1, name));
+            let mut env = Vec::new();
+            let mut result = declared.clone();
+            for pattern in clause.patterns.iter() {
+                let (param, next) = (self.fresh(), self.fresh());
+                if !self.unify(&result, &arrow(param.clone(), next.clone())) {
+                    return Err(failed())
+                }
+                if self.expect_pattern(pattern, &mut env, param).is_err() {
+                    return Err(failed())
+                }
+                result = next;
+            }
+            match clause.guard {
+                Some(ref guard) => try!(self.expect(guard, &*env, con("boolean", Vec::new()))),
+                None => {}
+            }
+            match self.expect(&clause.body, &*env, result) {
+                Err(EvalError::TypeError(_)) => return Err(failed()),
+                result => try!(result)
+            }
+        }
+        Ok(declared)
+    }
+
+    fn toplevel(&mut self, code: &Value, env: &[(SymbolId, Type)]) -> Result<Type, EvalError> {
+        let type_ = match *code {
+            Value::List(ref items) if items[0] == Value::Symbol(symbol::DEFINE) => try!(self.define(code, &**items)),
+            Value::List(ref items) if items[0] == Value::Symbol(symbol::DATATYPE) => con("symbol", Vec::new()),
+            Value::List(ref items) if items[0] == Value::Symbol(symbol::DEFPROLOG) => con("symbol", Vec::new()),
+            // The result of a query is untyped
+            Value::List(ref items) if items[0] == Value::Symbol(symbol::PROLOG) => self.fresh(),
+            _ => try!(self.infer(code, env))
+        };
+        Ok(self.resolve(&type_).rename(&mut Vec::new()))
+    }
+}
+
+impl Interpreter {
+    // The type of code read at the top level.  A define is checked against
+    // its signature, which is declared for later code once it passes.
+    pub fn typecheck(&mut self, code: &Value) -> Result<Type, EvalError> {
+        self.typecheck_with(code, &[])
+    }
+
+    // The type of code with variables bound around it, of the types given
+    // or of any type when they have none
+    fn typecheck_with(&mut self, code: &Value, variables: &[(SymbolId, Value, Option<Type>)])
+                      -> Result<Type, EvalError> {
+        let (result, pending) = {
+            let mut checker = Checker::new(self);
+            let env: Vec<(SymbolId, Type)> = variables.iter().map(|&(name, _, ref type_)| {
+                (name, match *type_ {
+                    Some(ref type_) => checker.instantiate(type_, &mut HashMap::new()),
+                    None => checker.fresh()
+                })
+            }).collect();
+            let result = checker.toplevel(code, &*env);
+            (result, checker.pending)
+        };
+        if result.is_ok() {
+            for (name, signature) in pending.into_iter() {
+                self.declare(name, signature);
+            }
+        }
+        result
+    }
+
+    // Evaluate code read at the top level, expanding any macros and, when tc
+    // is on, type checking it first.  The type is given along with the value
+    // if it was checked.
+    pub fn eval_toplevel(&mut self, code: &Value) -> Result<(Value, Option<Type>), EvalError> {
+        self.eval_toplevel_with(code, &[])
+    }
+
+    // Evaluate code read at the top level with variables bound to values,
+    // and when type checking to the types given with them
+    pub fn eval_toplevel_with(&mut self, code: &Value, variables: &[(SymbolId, Value, Option<Type>)])
+                              -> Result<(Value, Option<Type>), EvalError> {
+        // Each form of a package is handled as if at the top level itself
+        match *code {
+            Value::List(ref items) if items[0] == Value::Symbol(symbol::PACKAGE) => {
+                // Expanding checks the package is well formed, so its name is there
+                let forms = try!(self.expand_package(code, &**items));
+                let mut result = (items[1].clone(), None);
+                for form in forms.iter() {
+                    result = try!(self.eval_toplevel_with(form, variables));
+                }
+                return Ok(result)
+            }
+            _ => {}
+        }
+        let code = try!(self.expand_toplevel(code));
+        let type_ = if self.typechecking() { Some(try!(self.typecheck_with(&code, variables))) } else { None };
+        let env = variables.iter().fold(Env::new(), |env, &(name, ref value, _)| env.bind(name, value.clone()));
+        let value = try!(self.eval(&code, &env));
+        Ok((value, type_))
+    }
+}
@@ -0,0 +1,409 @@
+use std::cell::RefCell;
+use std::cmp::Ordering;
+use std::collections::HashSet;
+use std::fmt;
+use std::rc::Rc;
+
+use dict::Dict;
+use eval::{Continuation, EvalError};
+use number::{self, Number};
+use reader;
+use symbol::SymbolId;
+use {Node, SymbolicExpr};
+
+// Runtime values.  Code is data, so expressions are evaluated in this form
+// too, having been converted from the reader's nodes.
+#[derive(Clone)]
+pub enum Value {
+    Num(Number),
+    Bool(bool),
+    Str(Rc<String>),
+    Symbol(SymbolId),
+    // The empty list, which () and [] both read as
+    Nil,
+    Cons(Rc<Cons>),
+    // A non-empty expression as read, to be evaluated.  Lists built at run
+    // time are conses.
+    List(Rc<Vec<Value>>),
+    // A pair made by @p
+    Tuple(Rc<Tuple>),
+    // Mutable, fixed length vector, shared between every copy of the value
+    Vector(Rc<RefCell<Vec<Value>>>),
+    // Mutable hash table, shared like vectors
+    Dict(Rc<RefCell<Dict>>),
+    Closure(Rc<Closure>),
+    Partial(Rc<Partial>),
+    Frozen(Rc<Frozen>),
+    // The rest of an evaluation captured by shift
+    Continuation(Rc<Continuation>),
+    // An error caught by trap-error
+    Error(Rc<EvalError>),
+}
+
+pub struct Cons {
+    pub head: Value,
+    pub tail: Value,
+}
+
+pub struct Tuple {
+    pub first: Value,
+    pub second: Value,
+}
+
+// A one argument function along with the bindings in scope where it was made
+pub struct Closure {
+    pub param: SymbolId,
+    pub body: Value,
+    pub env: Env,
+}
+
+// A global function applied to fewer arguments than it takes, waiting for
+// the rest
+pub struct Partial {
+    pub function: SymbolId,
+    pub args: Vec<Value>,
+}
+
+// An expression whose evaluation is delayed until it is thawed
+pub struct Frozen {
+    pub body: Value,
+    pub env: Env,
+}
+
+// Local bindings as a persistent list, innermost first, so closures can share
+// the bindings of the scope they were made in
+#[derive(Clone)]
+pub struct Env {
+    head: Option<Rc<Binding>>,
+}
+
+struct Binding {
+    name: SymbolId,
+    value: Value,
+    next: Env,
+}
+
+impl Env {
+    pub fn new() -> Env {
+        Env { head: None }
+    }
+
+    // A new scope with name bound, shadowing any outer binding of it
+    pub fn bind(&self, name: SymbolId, value: Value) -> Env {
+        Env { head: Some(Rc::new(Binding { name: name, value: value, next: self.clone() })) }
+    }
+
+    // The variables bound and their values, innermost first, leaving out
+    // those shadowed
+    pub fn bindings(&self) -> Vec<(SymbolId, Value)> {
+        let mut bindings: Vec<(SymbolId, Value)> = Vec::new();
+        let mut env = self;
+        while let Some(ref binding) = env.head {
+            if !bindings.iter().any(|&(name, _)| name == binding.name) {
+                bindings.push((binding.name, binding.value.clone()));
+            }
+            env = &binding.next;
+        }
+        bindings
+    }
+
+    pub fn lookup(&self, name: SymbolId) -> Option<&Value> {
+        let mut env = self;
+        loop {
+            match env.head {
+                Some(ref binding) if binding.name == name => return Some(&binding.value),
+                Some(ref binding) => env = &binding.next,
+                None => return None
+            }
+        }
+    }
+}
+
+impl Value {
+    // Shen has no character type, so #\a becomes the unit string "a"
+    pub fn from_node(node: &Node) -> Value {
+        match node.expr {
+            SymbolicExpr::Int(i) => Value::Num(Number::Int(i)),
+            SymbolicExpr::BigInt(ref b) => Value::Num(Number::Big(b.clone())),
+            SymbolicExpr::Rational(ref r) => Value::Num(Number::Ratio(r.clone())),
+            SymbolicExpr::Float(x) => Value::Num(Number::Float(x)),
+            SymbolicExpr::Bool(b) => Value::Bool(b),
+            SymbolicExpr::Str(ref s) => Value::Str(Rc::new(s.clone())),
+            SymbolicExpr::Char(c) => Value::Str(Rc::new(c.to_string())),
+            SymbolicExpr::Symbol(s) => Value::Symbol(s),
+            SymbolicExpr::ListExpr(ref items) if items.is_empty() => Value::Nil,
+            SymbolicExpr::ListExpr(ref items) => {
+                Value::List(Rc::new(items.iter().map(Value::from_node).collect()))
+            }
+        }
+    }
+
+    // As from_node but with lists as conses, for code handled as data
+    pub fn data_from_node(node: &Node) -> Value {
+        match node.expr {
+            SymbolicExpr::ListExpr(ref items) => Value::from_vec(items.iter().map(Value::data_from_node).collect()),
+            _ => Value::from_node(node)
+        }
+    }
+
+    // The code represented by data built at runtime, turning proper lists of
+    // conses into forms.  Anything else, including improper lists, is
+    // self evaluating and left alone.
+    pub fn to_code(&self) -> Value {
+        match *self {
+            Value::Cons(_) => {
+                match self.to_vec() {
+                    Some(items) => Value::list(items.iter().map(|item| item.to_code()).collect()),
+                    None => self.clone()
+                }
+            }
+            _ => self.clone()
+        }
+    }
+
+    // Forms as list data, the inverse of to_code
+    pub fn to_data(&self) -> Value {
+        match *self {
+            Value::List(ref items) => Value::from_vec(items.iter().map(|item| item.to_data()).collect()),
+            _ => self.clone()
+        }
+    }
+
+    pub fn list(items: Vec<Value>) -> Value {
+        Value::List(Rc::new(items))
+    }
+
+    pub fn string(s: String) -> Value {
+        Value::Str(Rc::new(s))
+    }
+
+    pub fn cons(head: Value, tail: Value) -> Value {
+        Value::Cons(Rc::new(Cons { head: head, tail: tail }))
+    }
+
+    pub fn vector(items: Vec<Value>) -> Value {
+        Value::Vector(Rc::new(RefCell::new(items)))
+    }
+
+    pub fn tuple(first: Value, second: Value) -> Value {
+        Value::Tuple(Rc::new(Tuple { first: first, second: second }))
+    }
+
+    // A proper list of conses holding the items
+    pub fn from_vec(items: Vec<Value>) -> Value {
+        items.into_iter().rev().fold(Value::Nil, |tail, head| Value::cons(head, tail))
+    }
+
+    // The items of a proper list, or None for anything else
+    pub fn to_vec(&self) -> Option<Vec<Value>> {
+        let mut items = Vec::new();
+        let mut list = self;
+        loop {
+            match *list {
+                Value::Nil => return Some(items),
+                Value::Cons(ref cons) => {
+                    items.push(cons.head.clone());
+                    list = &cons.tail;
+                }
+                _ => return None
+            }
+        }
+    }
+}
+
+// Shen's structural equality.  Numbers are equal whatever their
+// representation, so 1 = 1.0, and functions only equal themselves.
+//
+// Pairs of parts still to compare are kept on a stack rather than recursed
+// into, so deeply nested structures can be compared.  Vectors can be made
+// to contain themselves with address->, so pairs of vectors already being
+// compared are remembered and taken as equal if met again, the comparison
+// then resting on the rest of their elements.
+impl PartialEq for Value {
+    fn eq(&self, other: &Value) -> bool {
+        let mut pending: Vec<(Value, Value)> = Vec::new();
+        let mut vectors = HashSet::new();
+        let (mut a, mut b) = (self.clone(), other.clone());
+        loop {
+            let equal = match (&a, &b) {
+                (&Value::Num(ref x), &Value::Num(ref y)) => {
+                    number::compare(x.clone(), y.clone()) == Some(Ordering::Equal)
+                }
+                (&Value::Bool(x), &Value::Bool(y)) => x == y,
+                (&Value::Str(ref x), &Value::Str(ref y)) => x == y,
+                (&Value::Symbol(x), &Value::Symbol(y)) => x == y,
+                (&Value::Nil, &Value::Nil) => true,
+                (&Value::Cons(ref x), &Value::Cons(ref y)) => {
+                    if !same(&**x, &**y) {
+                        pending.push((x.tail.clone(), y.tail.clone()));
+                        pending.push((x.head.clone(), y.head.clone()));
+                    }
+                    true
+                }
+                (&Value::List(ref x), &Value::List(ref y)) => {
+                    if x.len() != y.len() {
+                        false
+                    } else {
+                        pending.extend(x.iter().cloned().zip(y.iter().cloned()).rev());
+                        true
+                    }
+                }
+                (&Value::Tuple(ref x), &Value::Tuple(ref y)) => {
+                    pending.push((x.second.clone(), y.second.clone()));
+                    pending.push((x.first.clone(), y.first.clone()));
+                    true
+                }
+                (&Value::Vector(ref x), &Value::Vector(ref y)) => {
+                    let key = (&*x.borrow() as *const Vec<Value> as usize, &*y.borrow() as *const Vec<Value> as usize);
+                    if same(&**x, &**y) || !vectors.insert(key) {
+                        true
+                    } else if x.borrow().len() != y.borrow().len() {
+                        false
+                    } else {
+                        pending.extend(x.borrow().iter().cloned().zip(y.borrow().iter().cloned()).rev());
+                        true
+                    }
+                }
+                (&Value::Dict(ref x), &Value::Dict(ref y)) => same(&**x, &**y),
+                (&Value::Closure(ref x), &Value::Closure(ref y)) => same(&**x, &**y),
+                (&Value::Partial(ref x), &Value::Partial(ref y)) => same(&**x, &**y),
+                (&Value::Frozen(ref x), &Value::Frozen(ref y)) => same(&**x, &**y),
+                (&Value::Continuation(ref x), &Value::Continuation(ref y)) => same(&**x, &**y),
+                (&Value::Error(ref x), &Value::Error(ref y)) => same(&**x, &**y),
+                _ => false
+            };
+            if !equal {
+                return false
+            }
+            match pending.pop() {
+                Some((x, y)) => {
+                    a = x;
+                    b = y;
+                }
+                None => return true
+            }
+        }
+    }
+}
+
+fn same<T>(a: &T, b: &T) -> bool {
+    a as *const T == b as *const T
+}
+
+impl Value {
+    // The value as printed, with lists and vectors cut short after limit
+    // items, the rest shown as ...
+    pub fn abbreviated(&self, limit: usize) -> String {
+        format!("{}", Abbreviated(self, limit))
+    }
+
+    // Vectors can hold themselves, through address->, so those being written
+    // are kept by address on the path, one met again being written as <...>
+    fn write(&self, f: &mut fmt::Formatter, limit: Option<usize>, path: &mut Vec<usize>) -> fmt::Result {
+        let cut = |i: usize| limit.map_or(false, |limit| i >= limit);
+        match *self {
+            Value::Num(ref n) => write!(f, "{}", n),
+            Value::Bool(b) => write!(f, "{}", b),
+            Value::Str(ref s) => f.write_str(&*reader::escape_string(&**s)),
+            Value::Symbol(s) => write!(f, "{}", s),
+            Value::Nil => f.write_str("[]"),
+            Value::Cons(ref cons) => {
+                if cut(0) {
+                    return f.write_str("[...]")
+                }
+                try!(f.write_str("["));
+                try!(cons.head.write(f, limit, path));
+                let mut tail = &cons.tail;
+                let mut i = 1;
+                loop {
+                    match *tail {
+                        Value::Nil => break,
+                        Value::Cons(_) if cut(i) => {
+                            try!(f.write_str(" ..."));
+                            break
+                        }
+                        Value::Cons(ref cons) => {
+                            try!(f.write_str(" "));
+                            try!(cons.head.write(f, limit, path));
+                            tail = &cons.tail;
+                        }
+                        ref last => {
+                            try!(f.write_str(" | "));
+                            try!(last.write(f, limit, path));
+                            break
+                        }
+                    }
+                    i += 1;
+                }
+                f.write_str("]")
+            }
+            Value::List(ref items) => {
+                try!(f.write_str("("));
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        try!(f.write_str(" "));
+                    }
+                    if cut(i) {
+                        try!(f.write_str("..."));
+                        break
+                    }
+                    try!(item.write(f, limit, path));
+                }
+                f.write_str(")")
+            }
+            Value::Tuple(ref tuple) => {
+                try!(f.write_str("(@p "));
+                try!(tuple.first.write(f, limit, path));
+                try!(f.write_str(" "));
+                try!(tuple.second.write(f, limit, path));
+                f.write_str(")")
+            }
+            Value::Vector(ref items) => {
+                let address = &**items as *const _ as usize;
+                if path.contains(&address) {
+                    return f.write_str("<...>")
+                }
+                path.push(address);
+                try!(f.write_str("<"));
+                for (i, item) in items.borrow().iter().enumerate() {
+                    if i > 0 {
+                        try!(f.write_str(" "));
+                    }
+                    if cut(i) {
+                        try!(f.write_str("..."));
+                        break
+                    }
+                    try!(item.write(f, limit, path));
+                }
+                path.pop();
+                f.write_str(">")
+            }
+            Value::Dict(ref dict) => write!(f, "<dict {}>", dict.borrow().len()),
+            Value::Closure(_) => f.write_str("<closure>"),
+            Value::Partial(ref partial) => write!(f, "<function {}>", partial.function),
+            Value::Frozen(_) => f.write_str("<frozen>"),
+            Value::Continuation(_) => f.write_str("<continuation>"),
+            Value::Error(ref e) => write!(f, "<error {}>", e),
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.write(f, None, &mut Vec::new())
+    }
+}
+
+struct Abbreviated<'a>(&'a Value, usize);
+
+impl<'a> fmt::Display for Abbreviated<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.0.write(f, Some(self.1), &mut Vec::new())
+    }
+}
+
+impl fmt::Debug for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
@@ -0,0 +1,177 @@
+use eval::{EvalError, Interpreter};
+use symbol::{self, SymbolId};
+use types;
+use value::Value;
+
+// A rule of a defcc, Items := Action where Guard;
+struct Rule {
+    items: Vec<Value>,
+    action: Option<Value>,
+    guard: Option<Value>,
+}
+
+fn call(name: &str, args: Vec<Value>) -> Value {
+    let mut items = vec![Value::Symbol(symbol::intern(name))];
+    items.extend(args.into_iter());
+    Value::list(items)
+}
+
+fn fail() -> Value {
+    call("fail", Vec::new())
+}
+
+fn is_nonterminal(name: SymbolId) -> bool {
+    name.with_name(|name| name.len() > 2 && name.starts_with("<") && name.ends_with(">"))
+}
+
+fn rules(expr: &Value, items: &[Value]) -> Result<Vec<Rule>, EvalError> {
+    let malformed = || EvalError::Malformed(symbol::DEFCC, expr.clone());
+    let action = Value::Symbol(symbol::intern(":="));
+    let where_ = Value::Symbol(symbol::intern("where"));
+    let semicolon = Value::Symbol(symbol::intern(";"));
+    let tokens = types::semicolons(items);
+    let mut rules = Vec::new();
+    let mut rest = &tokens[..];
+    while !rest.is_empty() {
+        let end = match rest.iter().position(|item| *item == semicolon) {
+            Some(end) => end,
+            None => return Err(malformed())
+        };
+        let rule = &rest[..end];
+        let (items, action, guard) = match rule.iter().position(|item| *item == action) {
+            None => (rule, None, None),
+            Some(i) if i + 2 == rule.len() => (&rule[..i], Some(rule[i + 1].clone()), None),
+            Some(i) if i + 4 == rule.len() && rule[i + 2] == where_ => {
+                (&rule[..i], Some(rule[i + 1].clone()), Some(rule[i + 3].clone()))
+            }
+            Some(_) => return Err(malformed())
+        };
+        rules.push(Rule { items: items.to_vec(), action: action, guard: guard });
+        rest = &rest[end + 1..];
+    }
+    if rules.is_empty() {
+        return Err(malformed())
+    }
+    Ok(rules)
+}
+
+struct Compiler<'i> {
+    interpreter: &'i mut Interpreter,
+}
+
+impl<'i> Compiler<'i> {
+    fn fresh(&mut self, prefix: &str) -> Value {
+        Value::Symbol(self.interpreter.gensym(prefix))
+    }
+
+    // Code parsing the items from the input, which is the name of a
+    // variable, giving (@p Rest Result) or (fail).  The values of the items
+    // matched so far are collected for a rule without an action.
+    fn sequence(&mut self, rule: &Rule, i: usize, input: Value, matched: &mut Vec<Value>) -> Value {
+        if i == rule.items.len() {
+            let result = match rule.action {
+                Some(ref action) => action.clone(),
+                None => matched.iter().rev().fold(Value::Nil, |tail, head| call("cons", vec![head.clone(), tail]))
+            };
+            let parsed = call("@p", vec![input, result]);
+            return match rule.guard {
+                Some(ref guard) => Value::list(vec![Value::Symbol(symbol::IF), guard.clone(), parsed, fail()]),
+                None => parsed
+            }
+        }
+
+        let rest = self.fresh("Rest");
+        let item = rule.items[i].clone();
+        let (binding, value) = match item {
+            Value::Symbol(name) if name == symbol::intern("<e>") => {
+                matched.push(Value::Nil);
+                let parsed = self.sequence(rule, i + 1, input, matched);
+                matched.pop();
+                return parsed
+            }
+            // <!> takes all the input that's left
+            Value::Symbol(name) if name == symbol::intern("<!>") => {
+                matched.push(item.clone());
+                let parsed = self.sequence(rule, i + 1, Value::Nil, matched);
+                matched.pop();
+                return Value::list(vec![Value::Symbol(symbol::LET), item, input, parsed])
+            }
+            Value::Symbol(name) if is_nonterminal(name) => {
+                let parse = self.fresh("Parse");
+                matched.push(item.clone());
+                let parsed = self.sequence(rule, i + 1, rest.clone(), matched);
+                matched.pop();
+                let bound = Value::list(vec![
+                    Value::Symbol(symbol::LET),
+                    item.clone(), call("snd", vec![parse.clone()]),
+                    rest, call("fst", vec![parse.clone()]),
+                    parsed
+                ]);
+                return Value::list(vec![
+                    Value::Symbol(symbol::LET), parse.clone(), call(&*name.name(), vec![input]),
+                    Value::list(vec![Value::Symbol(symbol::IF), call("=", vec![parse, fail()]), fail(), bound])
+                ])
+            }
+            Value::Symbol(name) if name == symbol::intern("_") => (None, self.fresh("Token")),
+            Value::Symbol(name) if name.is_variable() => (Some(item.clone()), item.clone()),
+            _ => (None, item.clone())
+        };
+
+        // A single token, which a terminal must equal
+        let token = call("hd", vec![input.clone()]);
+        let mut test = call("cons?", vec![input.clone()]);
+        if binding.is_none() && value == item {
+            test = Value::list(vec![Value::Symbol(symbol::AND), test, call("=", vec![token.clone(), item.clone()])]);
+        }
+        matched.push(if binding.is_some() { value.clone() } else { token.clone() });
+        let parsed = self.sequence(rule, i + 1, rest.clone(), matched);
+        matched.pop();
+        let mut bindings = vec![Value::Symbol(symbol::LET)];
+        match binding {
+            Some(variable) => {
+                bindings.push(variable);
+                bindings.push(token);
+            }
+            None => {}
+        }
+        bindings.push(rest);
+        bindings.push(call("tl", vec![input]));
+        bindings.push(parsed);
+        Value::list(vec![Value::Symbol(symbol::IF), test, Value::list(bindings), fail()])
+    }
+}
+
+// (defcc <name> Rules...) compiles to a function from a list of tokens to
+// (@p Rest Result), or (fail) when no rule parses the start of the tokens.
+// Rules are tried in turn, each a sequence of items ending in a semicolon,
+// optionally followed by := and a semantic action and a where guard:
+//
+// (defcc <digits>
+//   <digit> <digits> := [<digit> | <digits>];
+//   <digit> := [<digit>];)
+//
+// Items are nonterminals such as <digit>, whose results are named by them in
+// the action, variables matching any one token, <e> matching nothing, <!>
+// matching the rest of the input, and terminals which match tokens equal to
+// them.
+pub fn compile(interpreter: &mut Interpreter, expr: &Value, items: &[Value]) -> Result<Value, EvalError> {
+    let name = match items.get(1) {
+        Some(&Value::Symbol(name)) if is_nonterminal(name) => name,
+        _ => return Err(EvalError::Malformed(symbol::DEFCC, expr.clone()))
+    };
+    let rules = try!(rules(expr, &items[2..]));
+    let mut compiler = Compiler { interpreter: interpreter };
+    let input = compiler.fresh("Input");
+
+    // Each rule is tried while the ones before fail
+    let mut body = fail();
+    for rule in rules.iter().rev() {
+        let parsed = compiler.sequence(rule, 0, input.clone(), &mut Vec::new());
+        let result = compiler.fresh("Result");
+        body = Value::list(vec![
+            Value::Symbol(symbol::LET), result.clone(), parsed,
+            Value::list(vec![Value::Symbol(symbol::IF), call("=", vec![result.clone(), fail()]), body, result])
+        ]);
+    }
+    Ok(Value::list(vec![Value::Symbol(symbol::DEFUN), Value::Symbol(name), Value::list(vec![input]), body]))
+}